@@ -0,0 +1,66 @@
+#import bevy_pbr::mesh_view_bindings       view
+#import bevy_pbr::mesh_bindings            mesh
+#import bevy_pbr::mesh_functions           as mesh_functions
+#import bevy_pbr::pbr_functions            as pbr_functions
+#import bevy_core_pipeline::tonemapping    tone_mapping
+
+@group(1) @binding(0) var block_textures: texture_2d_array<f32>;
+@group(1) @binding(1) var block_sampler: sampler;
+
+struct Vertex {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+    @location(4) color: vec4<f32>,
+    @location(8) layer: u32,
+};
+
+struct BlockVertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) world_position: vec4<f32>,
+    @location(1) world_normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+    @location(4) color: vec4<f32>,
+    @location(5) @interpolate(flat) layer: u32,
+};
+
+@vertex
+fn vertex(vertex: Vertex) -> BlockVertexOutput {
+    var out: BlockVertexOutput;
+
+    out.world_normal = mesh_functions::mesh_normal_local_to_world(vertex.normal);
+    out.world_position = mesh_functions::mesh_position_local_to_world(mesh.model, vec4<f32>(vertex.position, 1.0));
+    out.position = mesh_functions::mesh_position_world_to_clip(out.world_position);
+    out.uv = vertex.uv;
+    out.color = vertex.color;
+    out.layer = vertex.layer;
+
+    return out;
+}
+
+@fragment
+fn fragment(
+    in: BlockVertexOutput,
+    @builtin(front_facing) is_front: bool,
+) -> @location(0) vec4<f32> {
+    var pbr_input: pbr_functions::PbrInput = pbr_functions::pbr_input_new();
+
+    // The vertex colour holds the baked block light
+    pbr_input.material.base_color = textureSample(block_textures, block_sampler, in.uv, in.layer) * in.color;
+
+    pbr_input.frag_coord = in.position;
+    pbr_input.world_position = in.world_position;
+    pbr_input.world_normal = pbr_functions::prepare_world_normal(in.world_normal, false, is_front);
+    pbr_input.is_orthographic = view.projection[3].w == 1.0;
+    pbr_input.N = normalize(pbr_input.world_normal);
+    pbr_input.V = pbr_functions::calculate_view(in.world_position, pbr_input.is_orthographic);
+    pbr_input.flags = mesh.flags;
+
+    var output_color = pbr_functions::pbr(pbr_input);
+
+#ifdef TONEMAP_IN_SHADER
+    output_color = tone_mapping(output_color, view.color_grading);
+#endif
+
+    return output_color;
+}
@@ -0,0 +1,242 @@
+use std::{collections::VecDeque, time::Duration};
+
+use bevy::prelude::*;
+
+use crate::camera::FlyCam;
+
+/// How far behind the latest position avatars are shown, so there's usually a newer one to move towards
+pub const INTERPOLATION_DELAY: Duration = Duration::from_millis(100);
+/// Older positions are dropped once there are this many
+const MAX_SNAPSHOTS: usize = 16;
+/// How far above an avatar's eyes its name is shown
+const NAME_TAG_HEIGHT: f32 = 0.6;
+const NAME_TAG_WIDTH: f32 = 200.;
+
+/// Another player in a multiplayer game, spawned and moved by `NetClientPlugin`
+#[derive(Component, Clone, PartialEq, Eq, Debug)]
+pub struct RemotePlayer {
+    pub id: u32,
+    pub name: String,
+}
+
+/// Where a remote player was, and when we heard about it
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Snapshot {
+    pub time: Duration,
+    pub translation: Vec3,
+    pub rotation: Quat,
+}
+
+/// A remote player's recent positions, oldest first
+#[derive(Component, Default)]
+pub struct Snapshots(VecDeque<Snapshot>);
+
+impl Snapshots {
+    /// Adds the newest position, dropping it if it's out of order
+    pub fn push(&mut self, snapshot: Snapshot) {
+        if self.0.back().is_some_and(|last| last.time > snapshot.time) {
+            return;
+        }
+
+        self.0.push_back(snapshot);
+        if self.0.len() > MAX_SNAPSHOTS {
+            self.0.pop_front();
+        }
+    }
+
+    /// Where the player was at `time`, blending between the positions either side of it.
+    ///
+    /// Holds the first or last position when `time` is outside them.
+    pub fn sample(&self, time: Duration) -> Option<Transform> {
+        let first = self.0.front()?;
+        let last = self.0.back()?;
+
+        let (from, to) = if time <= first.time {
+            (first, first)
+        } else if time >= last.time {
+            (last, last)
+        } else {
+            let next = self.0.iter().position(|snapshot| snapshot.time > time)?;
+            (&self.0[next - 1], &self.0[next])
+        };
+
+        let span = (to.time - from.time).as_secs_f32();
+        let t = if span > 0. {
+            (time - from.time).as_secs_f32() / span
+        } else {
+            0.
+        };
+
+        Some(
+            Transform::from_translation(from.translation.lerp(to.translation, t))
+                .with_rotation(from.rotation.slerp(to.rotation, t)),
+        )
+    }
+}
+
+/// Moves remote players smoothly along their positions, a little in the past
+pub fn interpolate(
+    time: Res<Time>,
+    mut query: Query<(&Snapshots, &mut Transform), With<RemotePlayer>>,
+) {
+    let at = time.elapsed().saturating_sub(INTERPOLATION_DELAY);
+
+    for (snapshots, mut transform) in &mut query {
+        if let Some(sampled) = snapshots.sample(at) {
+            *transform = sampled;
+        }
+    }
+}
+
+/// Draws remote players as a head and body, with their name above them
+pub struct AvatarPlugin;
+
+impl Plugin for AvatarPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                Self::spawn_avatars,
+                Self::remove_name_tags,
+                Self::place_name_tags.after(interpolate),
+            ),
+        );
+    }
+}
+
+/// The text showing a remote player's name, which follows them around the screen
+#[derive(Component)]
+struct NameTag(Entity);
+
+impl AvatarPlugin {
+    /// Gives each player a different colour
+    fn colour(id: u32) -> Color {
+        Color::hsl((id as f32 * 137.5) % 360., 0.6, 0.5)
+    }
+
+    fn spawn_avatars(
+        mut commands: Commands,
+        query: Query<(Entity, &RemotePlayer), Added<RemotePlayer>>,
+        mut meshes: ResMut<Assets<Mesh>>,
+        mut materials: ResMut<Assets<StandardMaterial>>,
+    ) {
+        for (entity, player) in &query {
+            let material = materials.add(Self::colour(player.id).into());
+
+            // The transform is the player's camera, so the head is centred on it
+            commands.entity(entity).with_children(|parent| {
+                parent.spawn(PbrBundle {
+                    mesh: meshes.add(shape::Cube::new(0.5).into()),
+                    material: material.clone(),
+                    ..default()
+                });
+                parent.spawn(PbrBundle {
+                    mesh: meshes.add(shape::Box::new(0.6, 1.2, 0.3).into()),
+                    material,
+                    transform: Transform::from_xyz(0., -0.9, 0.),
+                    ..default()
+                });
+            });
+
+            commands.spawn((
+                TextBundle::from_section(
+                    player.name.clone(),
+                    TextStyle {
+                        font_size: 20.,
+                        color: Color::WHITE,
+                        ..default()
+                    },
+                )
+                .with_text_alignment(TextAlignment::Center)
+                .with_style(Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Px(NAME_TAG_WIDTH),
+                    ..default()
+                }),
+                NameTag(entity),
+            ));
+        }
+    }
+
+    fn remove_name_tags(
+        mut commands: Commands,
+        tags: Query<(Entity, &NameTag)>,
+        players: Query<(), With<RemotePlayer>>,
+    ) {
+        for (entity, tag) in &tags {
+            if !players.contains(tag.0) {
+                commands.entity(entity).despawn();
+            }
+        }
+    }
+
+    fn place_name_tags(
+        camera: Query<(&Camera, &GlobalTransform), With<FlyCam>>,
+        players: Query<&GlobalTransform, With<RemotePlayer>>,
+        mut tags: Query<(&NameTag, &mut Style, &mut Visibility)>,
+    ) {
+        let Ok((camera, camera_transform)) = camera.get_single() else { return; };
+
+        for (tag, mut style, mut visibility) in &mut tags {
+            let screen_pos = players.get(tag.0).ok().and_then(|transform| {
+                camera.world_to_viewport(
+                    camera_transform,
+                    transform.translation() + Vec3::Y * NAME_TAG_HEIGHT,
+                )
+            });
+
+            // Also hidden when the player's behind the camera
+            let Some(screen_pos) = screen_pos else {
+                *visibility = Visibility::Hidden;
+                continue;
+            };
+
+            *visibility = Visibility::Inherited;
+            style.left = Val::Px(screen_pos.x - NAME_TAG_WIDTH / 2.);
+            style.top = Val::Px(screen_pos.y - 24.);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(millis: u64, x: f32) -> Snapshot {
+        Snapshot {
+            time: Duration::from_millis(millis),
+            translation: Vec3::new(x, 0., 0.),
+            rotation: Quat::IDENTITY,
+        }
+    }
+
+    fn x(snapshots: &Snapshots, millis: u64) -> f32 {
+        snapshots
+            .sample(Duration::from_millis(millis))
+            .unwrap()
+            .translation
+            .x
+    }
+
+    #[test]
+    fn snapshots_interpolate() {
+        let mut snapshots = Snapshots::default();
+        assert_eq!(snapshots.sample(Duration::ZERO), None);
+
+        snapshots.push(snapshot(100, 0.));
+        snapshots.push(snapshot(200, 10.));
+        // Older than the latest, so it's ignored
+        snapshots.push(snapshot(150, 100.));
+
+        assert_eq!(x(&snapshots, 0), 0.);
+        assert_eq!(x(&snapshots, 150), 5.);
+        assert_eq!(x(&snapshots, 175), 7.5);
+        assert_eq!(x(&snapshots, 300), 10.);
+
+        for i in 0..20 {
+            snapshots.push(snapshot(300 + i, 20.));
+        }
+        assert_eq!(snapshots.0.len(), MAX_SNAPSHOTS);
+        assert_eq!(x(&snapshots, 0), 20.);
+    }
+}
@@ -0,0 +1,85 @@
+use std::{net::SocketAddr, path::PathBuf, time::Duration};
+
+use bevy::{app::ScheduleRunnerPlugin, log::LogPlugin, prelude::*};
+use bevy_craft::{
+    console::ConsoleCommandsPlugin,
+    net::{NetServerPlugin, DEFAULT_PORT},
+    save::{SaveDir, SavePlugin},
+    time_of_day::TimeOfDay,
+    world::World,
+    ChunkViewer, WorldConfig, WorldSimPlugin,
+};
+
+/// How many times a second the world is updated
+const TICK_RATE: f64 = 20.;
+/// How often the loaded chunks are saved, as the server is normally stopped with Ctrl+C
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Resource)]
+struct Autosave(Timer);
+
+fn autosave(
+    mut timer: ResMut<Autosave>,
+    time: Res<Time>,
+    save_dir: Res<SaveDir>,
+    world: Res<World>,
+    time_of_day: Res<TimeOfDay>,
+) {
+    if timer.0.tick(time.delta()).just_finished() {
+        SavePlugin::save_and_log(&save_dir, &world, &time_of_day);
+    }
+}
+
+fn spawn_viewer(mut commands: Commands) {
+    // Keeps the chunks around spawn loaded
+    commands.spawn((Transform::default(), ChunkViewer));
+}
+
+fn main() {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if args.len() > 3 {
+        eprintln!("Usage: bevy_craft-server [save dir] [seed] [port]");
+        std::process::exit(1);
+    }
+
+    let mut config = WorldConfig::default();
+    if let Some(save_dir) = args.first() {
+        config.save_dir = PathBuf::from(save_dir);
+    }
+    if let Some(seed) = args.get(1) {
+        config.seed = match seed.parse() {
+            Ok(seed) => seed,
+            Err(err) => {
+                eprintln!("Invalid seed {:?}: {}", seed, err);
+                std::process::exit(1);
+            }
+        };
+    }
+    let port = match args.get(2).map(|port| port.parse::<u16>()) {
+        None => DEFAULT_PORT,
+        Some(Ok(port)) => port,
+        Some(Err(err)) => {
+            eprintln!("Invalid port {:?}: {}", args[2], err);
+            std::process::exit(1);
+        }
+    };
+    let addr = SocketAddr::from(([0, 0, 0, 0], port));
+
+    App::new()
+        .add_plugins(
+            MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(
+                1. / TICK_RATE,
+            ))),
+        )
+        .add_plugins(LogPlugin::default())
+        .add_plugins(WorldSimPlugin { config })
+        .add_plugins(ConsoleCommandsPlugin)
+        .add_plugins(NetServerPlugin { addr })
+        .insert_resource(Autosave(Timer::new(
+            AUTOSAVE_INTERVAL,
+            TimerMode::Repeating,
+        )))
+        .add_systems(Startup, spawn_viewer)
+        .add_systems(Update, autosave)
+        .run();
+}
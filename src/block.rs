@@ -1,27 +1,98 @@
-use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
 pub enum Block {
     Air,
     Dirt,
     Stone,
+    Torch,
+    Lava,
 }
 
 impl Block {
-    pub fn uvs(&self) -> [Vec2; 4] {
-        let (x, y) = match self {
-            Block::Air => (0, 0),
-            Block::Dirt => (2, 0),
-            Block::Stone => (3, 0),
-        };
-
-        let offset = Vec2::new(x as f32, y as f32) / 16.;
-
-        [
-            offset + Vec2::new(0., 1. / 16.),
-            offset + Vec2::new(0., 0.),
-            offset + Vec2::new(1. / 16., 0.),
-            offset + Vec2::new(1. / 16., 1. / 16.),
-        ]
+    pub const ALL: [Block; 5] = [
+        Block::Air,
+        Block::Dirt,
+        Block::Stone,
+        Block::Torch,
+        Block::Lava,
+    ];
+
+    /// A stable id used when saving
+    pub fn id(&self) -> u8 {
+        match self {
+            Block::Air => 0,
+            Block::Dirt => 1,
+            Block::Stone => 2,
+            Block::Torch => 3,
+            Block::Lava => 4,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(Block::Air),
+            1 => Some(Block::Dirt),
+            2 => Some(Block::Stone),
+            3 => Some(Block::Torch),
+            4 => Some(Block::Lava),
+            _ => None,
+        }
+    }
+
+    /// The name used to type the block, such as in console commands
+    pub fn name(&self) -> &'static str {
+        match self {
+            Block::Air => "air",
+            Block::Dirt => "dirt",
+            Block::Stone => "stone",
+            Block::Torch => "torch",
+            Block::Lava => "lava",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|block| block.name() == name)
+    }
+
+    /// The name of the texture in `assets/blocks`, or None if the block is never drawn
+    pub fn texture(&self) -> Option<&'static str> {
+        match self {
+            Block::Air => None,
+            Block::Dirt => Some("dirt"),
+            Block::Stone => Some("stone"),
+            Block::Torch => Some("torch"),
+            Block::Lava => Some("lava"),
+        }
+    }
+
+    /// Roughly the average colour of the block's texture, or None if the block is never drawn
+    pub fn colour(&self) -> Option<[u8; 3]> {
+        match self {
+            Block::Air => None,
+            Block::Dirt => Some([101, 75, 49]),
+            Block::Stone => Some([54, 54, 54]),
+            Block::Torch => Some([203, 152, 69]),
+            Block::Lava => Some([227, 108, 22]),
+        }
+    }
+
+    /// The layer of the block texture array holding this block's texture
+    pub fn layer(&self) -> u32 {
+        self.id() as u32
+    }
+
+    /// Whether light can pass through this block
+    pub fn is_opaque(&self) -> bool {
+        !matches!(self, Block::Air)
+    }
+
+    /// The block light level this block emits
+    pub fn emission(&self) -> u8 {
+        match self {
+            Block::Torch => 14,
+            Block::Lava => 15,
+            _ => 0,
+        }
     }
 }
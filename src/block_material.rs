@@ -0,0 +1,209 @@
+use bevy::{
+    asset::LoadState,
+    pbr::{MaterialPipeline, MaterialPipelineKey},
+    prelude::*,
+    reflect::{TypePath, TypeUuid},
+    render::{
+        mesh::MeshVertexBufferLayout,
+        render_resource::{
+            AddressMode, AsBindGroup, Extent3d, FilterMode, RenderPipelineDescriptor,
+            SamplerDescriptor, ShaderRef, SpecializedMeshPipelineError, TextureDimension,
+            TextureFormat, TextureViewDescriptor, TextureViewDimension,
+        },
+        texture::ImageSampler,
+    },
+};
+
+use crate::{block::Block, mesh::ATTRIBUTE_LAYER, world::World};
+
+/// The width and height of each block texture
+pub const TEXTURE_SIZE: u32 = 16;
+
+/// Loads each block's texture from `assets/blocks` into the layers of a texture array.
+///
+/// Layer 0 holds the missing texture, and each block uses the layer matching its id.
+pub struct BlockMaterialPlugin;
+
+impl Plugin for BlockMaterialPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(MaterialPlugin::<BlockMaterial>::default())
+            .add_systems(Startup, Self::setup)
+            .add_systems(Update, Self::fill_texture_array);
+    }
+}
+
+#[derive(AsBindGroup, Debug, Clone, TypeUuid, TypePath)]
+#[uuid = "5f0e3c4c-2b6a-4d0e-9a4f-8a1f3f1b6c27"]
+pub struct BlockMaterial {
+    #[texture(0, dimension = "2d_array")]
+    #[sampler(1)]
+    pub textures: Handle<Image>,
+}
+
+impl Material for BlockMaterial {
+    fn vertex_shader() -> ShaderRef {
+        "shaders/block.wgsl".into()
+    }
+
+    fn fragment_shader() -> ShaderRef {
+        "shaders/block.wgsl".into()
+    }
+
+    fn specialize(
+        pipeline: &MaterialPipeline<Self>,
+        descriptor: &mut RenderPipelineDescriptor,
+        layout: &MeshVertexBufferLayout,
+        _key: MaterialPipelineKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        // The prepass and shadow pipelines use bevy's own vertex shader
+        if pipeline.vertex_shader.as_ref() != Some(&descriptor.vertex.shader) {
+            return Ok(());
+        }
+
+        descriptor.vertex.buffers = vec![layout.get_layout(&[
+            Mesh::ATTRIBUTE_POSITION.at_shader_location(0),
+            Mesh::ATTRIBUTE_NORMAL.at_shader_location(1),
+            Mesh::ATTRIBUTE_UV_0.at_shader_location(2),
+            Mesh::ATTRIBUTE_COLOR.at_shader_location(4),
+            ATTRIBUTE_LAYER.at_shader_location(8),
+        ])?];
+
+        Ok(())
+    }
+}
+
+/// The block textures still being loaded
+#[derive(Resource)]
+pub struct BlockTextures {
+    pub array: Handle<Image>,
+    loading: Vec<(Block, Handle<Image>)>,
+}
+
+/// The number of layers in the block texture array
+pub fn texture_layers() -> u32 {
+    Block::ALL.iter().map(|block| block.layer()).max().unwrap_or(0) + 1
+}
+
+/// A magenta and black checkerboard
+pub fn missing_texture() -> Vec<u8> {
+    (0..TEXTURE_SIZE * TEXTURE_SIZE)
+        .flat_map(|i| {
+            let (x, y) = (i % TEXTURE_SIZE, i / TEXTURE_SIZE);
+            if (x / 4 + y / 4) % 2 == 0 {
+                [255, 0, 255, 255]
+            } else {
+                [0, 0, 0, 255]
+            }
+        })
+        .collect()
+}
+
+impl BlockMaterialPlugin {
+    pub fn setup(
+        mut commands: Commands,
+        asset_server: Res<AssetServer>,
+        mut images: ResMut<Assets<Image>>,
+        mut materials: ResMut<Assets<BlockMaterial>>,
+        mut world: ResMut<World>,
+    ) {
+        let layers = texture_layers();
+
+        // Every layer starts as the missing texture until its block texture loads
+        let mut array = Image::new(
+            Extent3d {
+                width: TEXTURE_SIZE,
+                height: TEXTURE_SIZE,
+                depth_or_array_layers: layers,
+            },
+            TextureDimension::D2,
+            missing_texture().repeat(layers as usize),
+            TextureFormat::Rgba8UnormSrgb,
+        );
+        array.texture_view_descriptor = Some(TextureViewDescriptor {
+            dimension: Some(TextureViewDimension::D2Array),
+            ..default()
+        });
+        // Repeating allows one face to span several blocks
+        array.sampler_descriptor = ImageSampler::Descriptor(SamplerDescriptor {
+            address_mode_u: AddressMode::Repeat,
+            address_mode_v: AddressMode::Repeat,
+            mag_filter: FilterMode::Nearest,
+            min_filter: FilterMode::Nearest,
+            ..default()
+        });
+
+        let array = images.add(array);
+        world.material = materials.add(BlockMaterial {
+            textures: array.clone(),
+        });
+
+        let loading = Block::ALL
+            .iter()
+            .filter_map(|block| {
+                block.texture().map(|name| {
+                    (*block, asset_server.load(format!("blocks/{}.png", name)))
+                })
+            })
+            .collect();
+
+        commands.insert_resource(BlockTextures { array, loading });
+    }
+
+    /// Copies each block texture into its layer once loaded
+    pub fn fill_texture_array(
+        asset_server: Res<AssetServer>,
+        mut block_textures: ResMut<BlockTextures>,
+        mut images: ResMut<Assets<Image>>,
+        mut materials: ResMut<Assets<BlockMaterial>>,
+        world: Res<World>,
+    ) {
+        if block_textures.loading.is_empty() {
+            return;
+        }
+
+        let mut changed = false;
+        let array_handle = block_textures.array.clone();
+
+        block_textures.loading.retain(|(block, handle)| {
+            match asset_server.get_load_state(handle) {
+                LoadState::Loaded => {}
+                LoadState::Failed => {
+                    warn!("Texture for {:?} failed to load", block);
+                    return false;
+                }
+                _ => return true,
+            }
+
+            let Some(image) = images.get(handle) else { return true; };
+            let size = image.texture_descriptor.size;
+
+            let data = if size.width != TEXTURE_SIZE || size.height != TEXTURE_SIZE {
+                warn!(
+                    "Texture for {:?} is {}x{} instead of {}x{}",
+                    block, size.width, size.height, TEXTURE_SIZE, TEXTURE_SIZE
+                );
+                None
+            } else if image.texture_descriptor.format == TextureFormat::Rgba8UnormSrgb {
+                Some(image.data.clone())
+            } else {
+                image
+                    .convert(TextureFormat::Rgba8UnormSrgb)
+                    .map(|image| image.data)
+            };
+
+            if let (Some(data), Some(array)) = (data, images.get_mut(&array_handle)) {
+                let layer_size = (TEXTURE_SIZE * TEXTURE_SIZE * 4) as usize;
+                let start = block.layer() as usize * layer_size;
+                array.data[start..start + layer_size].copy_from_slice(&data);
+                changed = true;
+            }
+
+            false
+        });
+
+        // Touch the material so its bind group picks up the new texture data
+        if changed {
+            materials.get_mut(&world.material);
+        }
+    }
+}
@@ -1,4 +1,10 @@
-use crate::{block::Block, world::World};
+use crate::{
+    block::Block,
+    chunk::CHUNK_SIZE_I32,
+    console::not_typing,
+    inventory::Inventory,
+    world::{apply_block_edits, BlockEdit, BlockEdited, ChunkViewer, World},
+};
 use bevy::{
     input::mouse::MouseMotion,
     prelude::*,
@@ -6,8 +12,14 @@ use bevy::{
 };
 use parry3d::na;
 
-#[derive(Component)]
+/// How fast a `FlyCam` flies by default, in blocks per second
+pub const MOVE_SPEED: f32 = 30.;
+/// How many times faster a `FlyCam` flies with shift held, by default
+pub const SPRINT_MOD: f32 = 3.;
+
+#[derive(Component, Clone)]
 pub struct FlyCam {
+    /// In blocks per second
     pub move_speed: f32,
     pub sprint_mod: f32,
     pub look_speed: f32,
@@ -16,38 +28,98 @@ pub struct FlyCam {
 impl Default for FlyCam {
     fn default() -> Self {
         Self {
-            move_speed: 1.,
-            sprint_mod: 3.,
+            move_speed: MOVE_SPEED,
+            sprint_mod: SPRINT_MOD,
             look_speed: 0.003,
         }
     }
 }
 
-#[derive(Default)]
-pub struct FlyCamPlugin;
+/// Placements are forgotten after this many more, in case the server turned them down
+const MAX_PENDING: usize = 8;
+
+/// Blocks the camera has asked to place, which come out of its inventory once they're placed
+#[derive(Component, Default)]
+struct PendingPlacements(Vec<BlockEdit>);
+
+/// Spawns a camera that flies with WASD, QE and the right mouse button,
+/// breaking blocks into drops with the left mouse button
+/// and placing the selected block from its inventory with the middle one.
+///
+/// Chunks are loaded around it, and it has a crosshair.
+pub struct FlyCamPlugin {
+    /// Where the camera starts
+    pub transform: Transform,
+    pub fly_cam: FlyCam,
+    /// What the camera starts out carrying
+    pub inventory: Inventory,
+}
+
+impl Default for FlyCamPlugin {
+    fn default() -> Self {
+        Self {
+            transform: Transform::from_xyz(120., 40., 120.)
+                .looking_at(Vec3::new(0., 0., 0.), Vec3::Y),
+            fly_cam: FlyCam::default(),
+            inventory: Inventory::starting_kit(),
+        }
+    }
+}
 
 impl Plugin for FlyCamPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, Self::setup)
-            .add_systems(Update, Self::movement)
-            .add_systems(Update, Self::rotate)
-            .add_systems(Update, Self::pointer);
+        let (transform, fly_cam, inventory) =
+            (self.transform, self.fly_cam.clone(), self.inventory.clone());
+
+        app.add_systems(Startup, move |mut commands: Commands| {
+            commands.spawn((
+                Camera3dBundle {
+                    transform,
+                    ..default()
+                },
+                fly_cam.clone(),
+                inventory.clone(),
+                PendingPlacements::default(),
+                ChunkViewer,
+            ));
+        })
+        .add_systems(Startup, Self::create_crosshair)
+        .add_systems(Update, Self::movement.run_if(not_typing))
+        .add_systems(Update, Self::rotate)
+        .add_event::<BlockEdit>()
+        .add_event::<BlockEdited>()
+        .add_systems(Update, Self::pointer.before(apply_block_edits))
+        .add_systems(Update, Self::use_placed_blocks.after(apply_block_edits));
     }
 }
 
 impl FlyCamPlugin {
-    fn setup(mut commands: Commands) {
-        commands.spawn((
-            Camera3dBundle {
-                transform: Transform::from_xyz(120., 40., 120.)
-                    .looking_at(Vec3::new(0., 0., 0.), Vec3::Y),
+    fn create_crosshair(mut commands: Commands) {
+        commands
+            .spawn(NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.),
+                    height: Val::Percent(100.),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
                 ..default()
-            },
-            FlyCam::default(),
-        ));
+            })
+            .with_children(|parent| {
+                parent.spawn(ButtonBundle {
+                    style: Style {
+                        width: Val::Px(4.),
+                        height: Val::Px(4.),
+                        ..default()
+                    },
+                    background_color: Color::WHITE.into(),
+                    ..default()
+                });
+            });
     }
 
-    fn create_ray(transform: &Transform) -> parry3d::query::Ray {
+    pub fn create_ray(transform: &Transform) -> parry3d::query::Ray {
         let origin = transform.translation;
         let direction = transform.forward();
 
@@ -57,42 +129,82 @@ impl FlyCamPlugin {
         )
     }
 
-    pub fn pointer(
-        query: Query<&Transform, With<FlyCam>>,
+    /// The block the camera is looking at,
+    /// and the air block in front of it where a new block would go, as global positions
+    pub fn target(world: &World, transform: &Transform) -> Option<(IVec3, IVec3)> {
+        let hit = world.cast_ray(&Self::create_ray(transform))?;
+        let origin = hit.chunk_id * CHUNK_SIZE_I32;
+        let target = world.target_from_hit(hit)?;
+
+        Some((origin + target.local_pos, origin + target.place_pos))
+    }
+
+    fn pointer(
+        mut query: Query<(&Transform, &Inventory, &mut PendingPlacements), With<FlyCam>>,
         mouse_btns: Res<Input<MouseButton>>,
-        mut world: ResMut<World>,
+        world: Res<World>,
+        mut edits: EventWriter<BlockEdit>,
     ) {
-        let transform = query
-            .get_single()
+        let (transform, inventory, mut pending) = query
+            .get_single_mut()
             .expect("None / more than 1 camera present");
 
-        if mouse_btns.pressed(MouseButton::Left) {
-            let ray = Self::create_ray(transform);
+        let edit = if mouse_btns.pressed(MouseButton::Left) {
+            Self::target(&world, transform).map(|(pos, _)| BlockEdit {
+                pos,
+                block: Block::Air,
+            })
+        } else if mouse_btns.just_pressed(MouseButton::Middle) {
+            Self::target(&world, transform).and_then(|(_, place_pos)| {
+                let edit = BlockEdit {
+                    pos: place_pos,
+                    block: inventory.selected()?.block,
+                };
 
-            if let Some((hit, target)) = world
-                .cast_ray(&ray)
-                .and_then(|world_hit| Some(world_hit.clone()).zip(world.target_from_hit(world_hit)))
-            {
-                let block = world.chunks.get_mut(&hit.chunk_id).unwrap().get_mut(
-                    target.local_pos.x as usize,
-                    target.local_pos.y as usize,
-                    target.local_pos.z as usize,
-                );
+                if pending.0.len() == MAX_PENDING {
+                    pending.0.remove(0);
+                }
+                pending.0.push(edit);
+                Some(edit)
+            })
+        } else {
+            None
+        };
 
-                *block = Block::Air;
+        if let Some(edit) = edit {
+            edits.send(edit);
+        }
+    }
 
-                world.invalid_meshes.push(hit.chunk_id);
+    /// Takes placed blocks out of the inventory once they're in the world
+    fn use_placed_blocks(
+        mut query: Query<(&mut Inventory, &mut PendingPlacements), With<FlyCam>>,
+        mut edited: EventReader<BlockEdited>,
+    ) {
+        let (mut inventory, mut pending) = query
+            .get_single_mut()
+            .expect("None / more than 1 camera present");
+
+        for &BlockEdited { pos, new, .. } in edited.iter() {
+            let edit = BlockEdit { pos, block: new };
+            if let Some(i) = pending.0.iter().position(|pending| *pending == edit) {
+                pending.0.remove(i);
+                inventory.take_placed(new);
             }
         }
     }
 
-    fn movement(mut query: Query<(&mut Transform, &FlyCam)>, keys: Res<Input<KeyCode>>) {
+    fn movement(
+        mut query: Query<(&mut Transform, &FlyCam)>,
+        keys: Res<Input<KeyCode>>,
+        time: Res<Time>,
+    ) {
         for (mut transform, fly_cam) in &mut query {
             let move_speed = if keys.pressed(KeyCode::ShiftLeft) {
                 fly_cam.move_speed * fly_cam.sprint_mod
             } else {
                 fly_cam.move_speed
-            };
+            } * time.delta_seconds();
 
             if keys.pressed(KeyCode::W) {
                 let forward = transform.forward();
@@ -0,0 +1,154 @@
+use std::{
+    collections::VecDeque,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::PathBuf,
+};
+
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+
+use crate::console::{ConsolePlugin, Typing};
+
+/// Longer messages are cut short
+pub const MAX_CHAT_LEN: usize = 256;
+/// How many of the latest messages players are sent when they join
+pub const CHAT_HISTORY: usize = 20;
+/// The most lines kept in the chat window
+const WINDOW_LIMIT: usize = 200;
+
+/// A message or `/command` for the server, sent by `NetClientPlugin`
+#[derive(Event, Clone, Debug)]
+pub struct SendChat(pub String);
+
+/// A line of chat or command output from the server
+#[derive(Event, Clone, Debug)]
+pub struct ChatReceived(pub String);
+
+/// Strips anything that would break the chat window or the log, like newlines
+pub fn clean_chat(text: &str) -> String {
+    text.chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_CHAT_LEN)
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+/// Every chat message on a server, appended to a file in the save directory
+#[derive(Resource)]
+pub struct ChatLog {
+    path: PathBuf,
+    /// The latest messages, oldest first
+    recent: VecDeque<String>,
+}
+
+impl ChatLog {
+    /// Opens the log at `path`, remembering the latest messages from last time
+    pub fn load(path: PathBuf) -> Self {
+        let text = fs::read_to_string(&path).unwrap_or_default();
+        let mut recent = text.lines().map(str::to_string).collect::<VecDeque<_>>();
+        recent.drain(..recent.len().saturating_sub(CHAT_HISTORY));
+
+        Self { path, recent }
+    }
+
+    pub fn push(&mut self, line: String) {
+        let written = self
+            .path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| {
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&self.path)
+            })
+            .and_then(|mut file| writeln!(file, "{}", line));
+        if let Err(err) = written {
+            error!("Failed to write to {:?}: {}", self.path, err);
+        }
+
+        self.recent.push_back(line);
+        if self.recent.len() > CHAT_HISTORY {
+            self.recent.pop_front();
+        }
+    }
+
+    /// The latest messages, oldest first
+    pub fn recent(&self) -> impl Iterator<Item = &str> {
+        self.recent.iter().map(String::as_str)
+    }
+}
+
+/// A chat window for talking to other players and running commands on the server,
+/// which needs `EguiPlugin` and `NetClientPlugin`
+pub struct ChatPlugin;
+
+impl Plugin for ChatPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ChatWindow>()
+            .init_resource::<Typing>()
+            .add_event::<SendChat>()
+            .add_event::<ChatReceived>()
+            .add_systems(Update, (Self::receive, Self::chat_window).chain());
+
+        // Otherwise nothing stops the camera moving while typing
+        if !app.is_plugin_added::<ConsolePlugin>() {
+            app.add_systems(Update, ConsolePlugin::track_typing);
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct ChatWindow {
+    /// Received lines, oldest first
+    lines: Vec<String>,
+    input: String,
+}
+
+impl ChatPlugin {
+    pub fn receive(mut window: ResMut<ChatWindow>, mut received: EventReader<ChatReceived>) {
+        window
+            .lines
+            .extend(received.iter().map(|ChatReceived(line)| line.clone()));
+
+        if window.lines.len() > WINDOW_LIMIT {
+            let excess = window.lines.len() - WINDOW_LIMIT;
+            window.lines.drain(..excess);
+        }
+    }
+
+    pub fn chat_window(
+        mut ctx: EguiContexts,
+        mut window: ResMut<ChatWindow>,
+        mut send: EventWriter<SendChat>,
+    ) {
+        egui::Window::new("Chat")
+            .anchor(egui::Align2::LEFT_BOTTOM, [10., -10.])
+            .default_width(400.)
+            .show(ctx.ctx_mut(), |ui| {
+                egui::ScrollArea::vertical()
+                    .max_height(200.)
+                    .stick_to_bottom(true)
+                    .show(ui, |ui| {
+                        for line in &window.lines {
+                            ui.label(line);
+                        }
+                    });
+
+                let response = ui.add(
+                    egui::TextEdit::singleline(&mut window.input)
+                        .hint_text("Say something, or /help")
+                        .desired_width(f32::INFINITY),
+                );
+
+                if response.lost_focus() && ui.input(|input| input.key_pressed(egui::Key::Enter)) {
+                    let text = clean_chat(&std::mem::take(&mut window.input));
+                    if !text.is_empty() {
+                        send.send(SendChat(text));
+                    }
+                }
+            });
+    }
+}
@@ -1,11 +1,24 @@
+use std::ops::{Deref, DerefMut};
+
 use bevy::prelude::*;
 use noise::{Fbm, NoiseFn, SuperSimplex};
 
 use crate::{
     block::Block,
+    light::LightVolume,
     mesh::{Direction, IncompleteMesh},
+    palette::PalettedStorage,
 };
 
+/// The width, height and depth of every chunk in blocks.
+///
+/// Must be a power of 2 so chunks can be downsampled into LODs.
+pub const CHUNK_SIZE: usize = 16;
+/// `CHUNK_SIZE` as an `i32`, for working with block positions
+pub const CHUNK_SIZE_I32: i32 = CHUNK_SIZE as i32;
+/// The number of blocks in a chunk
+pub const CHUNK_VOLUME: usize = CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE;
+
 #[derive(Resource)]
 pub struct TerrainGen {
     pub height: Fbm<SuperSimplex>,
@@ -13,7 +26,13 @@ pub struct TerrainGen {
 
 impl Default for TerrainGen {
     fn default() -> Self {
-        let mut height = Fbm::new(0);
+        Self::new(0)
+    }
+}
+
+impl TerrainGen {
+    pub fn new(seed: u32) -> Self {
+        let mut height = Fbm::new(seed);
         height.octaves = 4;
         height.frequency = 0.04;
         height.lacunarity = 1.95;
@@ -21,11 +40,51 @@ impl Default for TerrainGen {
 
         Self { height }
     }
+
+    /// The generated block at a global position
+    pub fn block_at(&self, pos: IVec3) -> Block {
+        let height = (self.height.get([pos.x as f64, pos.z as f64, 0.]) * 4. + 8.) as i32;
+
+        match pos.y {
+            y if y < height => Block::Stone,
+            y if y == height => Block::Dirt,
+            _ => Block::Air,
+        }
+    }
 }
 
 pub struct Chunk {
     id: IVec3,
-    blocks: [[[Block; 16]; 16]; 16],
+    blocks: PalettedStorage,
+    pub light: LightVolume,
+}
+
+/// A mutable reference to a block in a chunk,
+/// which is written back to the chunk's storage when dropped
+pub struct BlockMut<'a> {
+    blocks: &'a mut PalettedStorage,
+    index: usize,
+    block: Block,
+}
+
+impl Deref for BlockMut<'_> {
+    type Target = Block;
+
+    fn deref(&self) -> &Block {
+        &self.block
+    }
+}
+
+impl DerefMut for BlockMut<'_> {
+    fn deref_mut(&mut self) -> &mut Block {
+        &mut self.block
+    }
+}
+
+impl Drop for BlockMut<'_> {
+    fn drop(&mut self) {
+        self.blocks.set(self.index, self.block);
+    }
 }
 
 impl Chunk {
@@ -33,48 +92,73 @@ impl Chunk {
     pub fn new(id: IVec3) -> Self {
         Self {
             id,
-            blocks: [[[Block::Air; 16]; 16]; 16],
+            blocks: PalettedStorage::new(CHUNK_VOLUME, Block::Air),
+            light: LightVolume::default(),
         }
     }
 
+    pub fn id(&self) -> IVec3 {
+        self.id
+    }
+
+    fn index(x: usize, y: usize, z: usize) -> usize {
+        (x * CHUNK_SIZE + y) * CHUNK_SIZE + z
+    }
+
+    /// Gets a block that's known to be in bounds
+    fn get(&self, x: usize, y: usize, z: usize) -> Block {
+        self.blocks.get(Self::index(x, y, z))
+    }
+
+    /// The number of bytes used to store the blocks
+    pub fn memory_usage(&self) -> usize {
+        self.blocks.memory_usage()
+    }
+
+    /// Serializes the blocks with one byte per block
+    pub fn to_bytes(&self) -> Vec<u8> {
+        (0..CHUNK_VOLUME).map(|i| self.blocks.get(i).id()).collect()
+    }
+
+    /// Returns None if the bytes weren't created by `to_bytes`
+    pub fn from_bytes(id: IVec3, bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != CHUNK_VOLUME {
+            return None;
+        }
+
+        let mut chunk = Self::new(id);
+        for (i, &byte) in bytes.iter().enumerate() {
+            chunk.blocks.set(i, Block::from_id(byte)?);
+        }
+        chunk.blocks.compact();
+
+        Some(chunk)
+    }
+
     pub fn generate(&mut self, terrain_gen: &TerrainGen) {
-        for x in 0..16 {
-            for y in 0..16 {
-                for z in 0..16 {
-                    let global_x = x as i32 + self.id.x * 16;
-                    let global_y = y as i32 + self.id.y * 16;
-                    let global_z = z as i32 + self.id.z * 16;
-
-                    let height = (terrain_gen
-                        .height
-                        .get([global_x as f64, global_z as f64, 0.])
-                        * 4.
-                        + 8.) as i32;
-
-                    let block = match global_y {
-                        y if y < height => Block::Stone,
-                        y if y == height => Block::Dirt,
-                        _ => Block::Air,
-                    };
+        let origin = self.id * CHUNK_SIZE_I32;
 
-                    self.blocks[x][y][z] = block;
+        for x in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE {
+                for z in 0..CHUNK_SIZE {
+                    let pos = origin + IVec3::new(x as i32, y as i32, z as i32);
+                    self.blocks
+                        .set(Self::index(x, y, z), terrain_gen.block_at(pos));
                 }
             }
         }
+
+        self.blocks.compact();
     }
 
     /// Try and get a block, returning None if outside bounds
     pub fn try_get(&self, x: i32, y: i32, z: i32) -> Option<Block> {
         // If less than 0 make outside of bounds on other side
-        let x = usize::try_from(x).unwrap_or(16);
-        let y = usize::try_from(y).unwrap_or(16);
-        let z = usize::try_from(z).unwrap_or(16);
+        let x = usize::try_from(x).unwrap_or(CHUNK_SIZE);
+        let y = usize::try_from(y).unwrap_or(CHUNK_SIZE);
+        let z = usize::try_from(z).unwrap_or(CHUNK_SIZE);
 
-        self.blocks
-            .get(x)
-            .and_then(|arr| arr.get(y))
-            .and_then(|arr| arr.get(z))
-            .copied()
+        (x < CHUNK_SIZE && y < CHUNK_SIZE && z < CHUNK_SIZE).then(|| self.get(x, y, z))
     }
 
     /// Tries to get a block, returning `Block::Air` if outside bounds
@@ -83,51 +167,127 @@ impl Chunk {
     }
 
     /// Mutable gets a block
-    pub fn get_mut(&mut self, x: usize, y: usize, z: usize) -> &mut Block {
-        &mut self.blocks[x][y][z]
+    pub fn get_mut(&mut self, x: usize, y: usize, z: usize) -> BlockMut<'_> {
+        let index = Self::index(x, y, z);
+
+        BlockMut {
+            block: self.blocks.get(index),
+            blocks: &mut self.blocks,
+            index,
+        }
     }
 
-    pub fn build_mesh(&self) -> Mesh {
-        let mut incomplete_mesh = IncompleteMesh::default();
+    /// Merges each cube of `2^lod` blocks into one,
+    /// picking the most common solid block if at least half are solid
+    fn downsample(&self, lod: u32) -> Vec<Block> {
+        let scale = 1 << lod;
+        let size = CHUNK_SIZE / scale;
+        let mut cells = Vec::with_capacity(size * size * size);
+
+        for x in 0..size {
+            for y in 0..size {
+                for z in 0..size {
+                    let mut counts: Vec<(Block, usize)> = vec![];
+                    let mut solid = 0;
+
+                    for dx in 0..scale {
+                        for dy in 0..scale {
+                            for dz in 0..scale {
+                                let block =
+                                    self.get(x * scale + dx, y * scale + dy, z * scale + dz);
+                                if block == Block::Air {
+                                    continue;
+                                }
+
+                                solid += 1;
+                                match counts.iter_mut().find(|(b, _)| *b == block) {
+                                    Some((_, count)) => *count += 1,
+                                    None => counts.push((block, 1)),
+                                }
+                            }
+                        }
+                    }
 
-        for i in 0..16 {
-            for j in 0..16 {
+                    let block = if solid * 2 >= scale * scale * scale {
+                        counts
+                            .into_iter()
+                            .max_by_key(|(_, count)| *count)
+                            .map_or(Block::Air, |(block, _)| block)
+                    } else {
+                        Block::Air
+                    };
+
+                    cells.push(block);
+                }
+            }
+        }
+
+        cells
+    }
+
+    /// Builds a mesh where every `2^lod` blocks along each axis are merged into one.
+    ///
+    /// `light` samples the brightness at a position local to this chunk,
+    /// which may be outside its bounds.
+    ///
+    /// The faces on the chunk borders are always built,
+    /// so the border walls cover any cracks between chunks of different LODs.
+    pub fn build_mesh(&self, lod: u32, light: impl Fn(IVec3) -> f32) -> Mesh {
+        let size = CHUNK_SIZE >> lod;
+        let cells = self.downsample(lod);
+
+        let get = |x: usize, y: usize, z: usize| cells[(x * size + y) * size + z];
+        let get_or_air = |x: usize, y: usize, z: usize| {
+            if x < size && y < size && z < size {
+                get(x, y, z)
+            } else {
+                Block::Air
+            }
+        };
+
+        let mut incomplete_mesh = IncompleteMesh::with_scale(1 << lod);
+
+        for i in 0..size {
+            for j in 0..size {
                 incomplete_mesh.maybe_add_face(
                     Vec3::new(0., i as f32, j as f32),
                     Direction::Nx,
-                    self.blocks[0][i][j],
+                    get(0, i, j),
                     Block::Air,
+                    &light,
                 );
 
                 incomplete_mesh.maybe_add_face(
                     Vec3::new(i as f32, 0., j as f32),
                     Direction::Ny,
-                    self.blocks[i][0][j],
+                    get(i, 0, j),
                     Block::Air,
+                    &light,
                 );
 
                 incomplete_mesh.maybe_add_face(
                     Vec3::new(i as f32, j as f32, 0.),
                     Direction::Nz,
-                    self.blocks[i][j][0],
+                    get(i, j, 0),
                     Block::Air,
+                    &light,
                 );
             }
         }
 
-        for x in 0..16 {
-            for y in 0..16 {
-                for z in 0..16 {
-                    let a = self.blocks[x][y][z];
+        for x in 0..size {
+            for y in 0..size {
+                for z in 0..size {
+                    let a = get(x, y, z);
 
-                    let px = self.get_or_air(x as i32 + 1, y as i32, z as i32);
-                    let py = self.get_or_air(x as i32, y as i32 + 1, z as i32);
-                    let pz = self.get_or_air(x as i32, y as i32, z as i32 + 1);
+                    let px = get_or_air(x + 1, y, z);
+                    let py = get_or_air(x, y + 1, z);
+                    let pz = get_or_air(x, y, z + 1);
 
                     let pos = Vec3::new(x as f32, y as f32, z as f32);
-                    incomplete_mesh.maybe_add_face(pos, Direction::Px, a, px);
-                    incomplete_mesh.maybe_add_face(pos, Direction::Py, a, py);
-                    incomplete_mesh.maybe_add_face(pos, Direction::Pz, a, pz);
+                    incomplete_mesh.maybe_add_face(pos, Direction::Px, a, px, &light);
+                    incomplete_mesh.maybe_add_face(pos, Direction::Py, a, py, &light);
+                    incomplete_mesh.maybe_add_face(pos, Direction::Pz, a, pz, &light);
                 }
             }
         }
@@ -135,3 +295,77 @@ impl Chunk {
         incomplete_mesh.complete()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edits_round_trip_through_chunk_api() {
+        let mut chunk = Chunk::new(IVec3::ZERO);
+        chunk.generate(&TerrainGen::default());
+
+        let mut expected = vec![];
+        for x in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE {
+                for z in 0..CHUNK_SIZE {
+                    let block = Block::ALL[(x * 7 + y * 3 + z) % Block::ALL.len()];
+                    *chunk.get_mut(x, y, z) = block;
+                    expected.push(block);
+                }
+            }
+        }
+
+        let loaded = Chunk::from_bytes(chunk.id(), &chunk.to_bytes()).unwrap();
+
+        for (i, block) in expected.into_iter().enumerate() {
+            let (x, y, z) = (
+                (i / (CHUNK_SIZE * CHUNK_SIZE)) as i32,
+                (i / CHUNK_SIZE % CHUNK_SIZE) as i32,
+                (i % CHUNK_SIZE) as i32,
+            );
+            assert_eq!(chunk.get_or_air(x, y, z), block);
+            assert_eq!(loaded.try_get(x, y, z), Some(block));
+        }
+    }
+
+    #[test]
+    fn downsampling_keeps_the_majority_block() {
+        use Block::{Air, Dirt, Stone};
+
+        let mut chunk = Chunk::new(IVec3::ZERO);
+        let mut fill = |origin: usize, blocks: [Block; 8]| {
+            for (i, block) in blocks.into_iter().enumerate() {
+                *chunk.get_mut(origin + i / 4, i / 2 % 2, i % 2) = block;
+            }
+        };
+
+        fill(0, [Stone, Stone, Dirt, Stone, Dirt, Stone, Dirt, Stone]);
+        // Exactly half solid still counts, taking the most common of the solid blocks
+        fill(2, [Air, Dirt, Air, Stone, Dirt, Air, Dirt, Air]);
+        fill(4, [Stone, Air, Air, Stone, Air, Stone, Air, Air]);
+
+        let cells = chunk.downsample(1);
+        let size = CHUNK_SIZE / 2;
+        assert_eq!(cells.len(), size * size * size);
+        let cell = |x: usize| cells[x * size * size];
+        assert_eq!(
+            [cell(0), cell(1), cell(2), cell(3)],
+            [Stone, Dirt, Air, Air]
+        );
+
+        // Each LOD 2 cell covers 8 of the cells above, and the first has 12 solid blocks out of 64
+        let cells = chunk.downsample(2);
+        assert_eq!(cells.len(), (CHUNK_SIZE / 4).pow(3));
+        assert_eq!(cells[0], Air);
+    }
+
+    #[test]
+    fn try_get_outside_bounds() {
+        let chunk = Chunk::new(IVec3::ZERO);
+
+        assert_eq!(chunk.try_get(-1, 0, 0), None);
+        assert_eq!(chunk.try_get(0, CHUNK_SIZE_I32, 0), None);
+        assert_eq!(chunk.get_or_air(0, 0, CHUNK_SIZE_I32), Block::Air);
+    }
+}
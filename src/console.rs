@@ -0,0 +1,738 @@
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+    str::FromStr,
+};
+
+use bevy::{ecs::world::World as EcsWorld, prelude::*};
+use bevy_egui::{egui, EguiContexts};
+use noise::Seedable;
+
+use crate::{
+    block::Block,
+    camera::FlyCam,
+    chunk::TerrainGen,
+    history::EditHistory,
+    net::MAX_REACH,
+    world::{world_mesh_gen, World},
+    world_edit::{fill, Selection},
+};
+
+/// The built in console commands, without the console window, so servers can run them too
+pub struct ConsoleCommandsPlugin;
+
+impl Plugin for ConsoleCommandsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ConsoleCommands>()
+            .add_console_command(
+                "tp",
+                ConsoleCommand {
+                    usage: "<x> <y> <z>".into(),
+                    description: "Moves the camera".into(),
+                    run: tp,
+                    remote: false,
+                },
+            )
+            .add_console_command(
+                "setblock",
+                ConsoleCommand {
+                    usage: "<x> <y> <z> <block>".into(),
+                    description: "Sets a single block".into(),
+                    run: setblock,
+                    remote: true,
+                },
+            )
+            .add_console_command(
+                "fill",
+                ConsoleCommand {
+                    usage: "<x1> <y1> <z1> <x2> <y2> <z2> <block>".into(),
+                    description: "Sets every block in a box".into(),
+                    run: fill_command,
+                    remote: true,
+                },
+            )
+            .add_console_command(
+                "seed",
+                ConsoleCommand {
+                    usage: "[seed]".into(),
+                    description: "Shows or sets the terrain seed, which applies on regen".into(),
+                    run: seed,
+                    remote: true,
+                },
+            )
+            .add_console_command(
+                "regen",
+                ConsoleCommand {
+                    usage: "".into(),
+                    description: "Generates the loaded chunks again, throwing away any edits".into(),
+                    run: regen,
+                    remote: false,
+                },
+            );
+    }
+}
+
+/// A console opened with the backtick key, running commands registered with `add_console_command`
+pub struct ConsolePlugin;
+
+impl Plugin for ConsolePlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<ConsoleCommandsPlugin>() {
+            app.add_plugins(ConsoleCommandsPlugin);
+        }
+
+        app.init_resource::<Console>()
+            .init_resource::<Typing>()
+            .add_systems(Update, (Self::toggle, Self::track_typing))
+            .add_systems(
+                Update,
+                (
+                    Self::console_window,
+                    Self::run_queued.before(world_mesh_gen),
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// Whether an egui text field has keyboard focus, in which case key bindings should be ignored
+#[derive(Resource, Default)]
+pub struct Typing(pub bool);
+
+/// A run condition for systems reading the keyboard
+pub fn not_typing(typing: Option<Res<Typing>>) -> bool {
+    !typing.is_some_and(|typing| typing.0)
+}
+
+pub type CommandFn = fn(&mut EcsWorld, &Args) -> Result<String, String>;
+
+pub struct ConsoleCommand {
+    /// The arguments it takes, like `<x> <y> <z> [block]`
+    pub usage: Cow<'static, str>,
+    pub description: Cow<'static, str>,
+    /// Returns the message to print, or what went wrong
+    pub run: CommandFn,
+    /// Whether players on a server can run it from chat, as well as the server itself.
+    ///
+    /// Their commands get `Args::player`, so the command can check what they're allowed to do.
+    pub remote: bool,
+}
+
+#[derive(Resource, Default)]
+pub struct ConsoleCommands {
+    commands: BTreeMap<Cow<'static, str>, ConsoleCommand>,
+}
+
+impl ConsoleCommands {
+    /// Replaces any command with the same name
+    pub fn register(&mut self, name: impl Into<Cow<'static, str>>, command: ConsoleCommand) {
+        self.commands.insert(name.into(), command);
+    }
+
+    pub fn unregister(&mut self, name: &str) -> Option<ConsoleCommand> {
+        self.commands.remove(name)
+    }
+
+    /// Runs a line typed into the console
+    pub fn run(&self, world: &mut EcsWorld, line: &str) -> Result<String, String> {
+        self.run_as(world, line, None)
+    }
+
+    /// Runs a line a player at `player` sent to the server, which can only use `remote` commands
+    pub fn run_remote(
+        &self,
+        world: &mut EcsWorld,
+        line: &str,
+        player: Vec3,
+    ) -> Result<String, String> {
+        self.run_as(world, line, Some(player))
+    }
+
+    fn run_as(
+        &self,
+        world: &mut EcsWorld,
+        line: &str,
+        player: Option<Vec3>,
+    ) -> Result<String, String> {
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        let Some((name, words)) = words.split_first() else { return Ok(String::new()); };
+        let args = Args {
+            command: name,
+            words,
+            player,
+        };
+        let remote = player.is_some();
+
+        if *name == "help" {
+            return self.help(&args, remote);
+        }
+
+        let command = self
+            .commands
+            .get(*name)
+            .ok_or_else(|| format!("Unknown command {:?}, try help", name))?;
+        if remote && !command.remote {
+            return Err(format!("{} can only be run on the server", name));
+        }
+
+        (command.run)(world, &args)
+            .map_err(|err| format!("{}\nUsage: {} {}", err, name, command.usage))
+    }
+
+    /// Only lists the `remote` commands for `remote` players
+    fn help(&self, args: &Args, remote: bool) -> Result<String, String> {
+        args.at_most(1)?;
+
+        match args.optional::<String>(0, "command")? {
+            Some(name) => {
+                let command = self
+                    .commands
+                    .get(name.as_str())
+                    .filter(|command| command.remote || !remote)
+                    .ok_or_else(|| format!("Unknown command {:?}", name))?;
+                Ok(format!(
+                    "{} {}\n{}",
+                    name, command.usage, command.description
+                ))
+            }
+            None => Ok(self
+                .commands
+                .iter()
+                .filter(|(_, command)| command.remote || !remote)
+                .map(|(name, command)| {
+                    format!("{} {} - {}", name, command.usage, command.description)
+                })
+                .collect::<Vec<_>>()
+                .join("\n")),
+        }
+    }
+
+    /// The names of the commands starting with `partial`, in order
+    pub fn complete(&self, partial: &str) -> Vec<&str> {
+        std::iter::once("help")
+            .chain(self.commands.keys().map(|name| name.as_ref()))
+            .filter(|name| name.starts_with(partial))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+}
+
+pub trait AppConsoleExt {
+    fn add_console_command(&mut self, name: &'static str, command: ConsoleCommand) -> &mut Self;
+}
+
+impl AppConsoleExt for App {
+    fn add_console_command(&mut self, name: &'static str, command: ConsoleCommand) -> &mut Self {
+        self.world
+            .get_resource_or_insert_with(ConsoleCommands::default)
+            .register(name, command);
+        self
+    }
+}
+
+/// The words after a command's name
+pub struct Args<'a> {
+    /// The command's name, for commands registered under several
+    pub command: &'a str,
+    words: &'a [&'a str],
+    /// Where the player running it is, when it's a `remote` command run from chat on a server
+    pub player: Option<Vec3>,
+}
+
+impl<'a> Args<'a> {
+    fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn words(&self) -> &[&str] {
+        self.words
+    }
+
+    /// Fails if there are more than `count` arguments
+    pub fn at_most(&self, count: usize) -> Result<(), String> {
+        if self.len() > count {
+            Err(format!(
+                "Expected at most {} arguments, got {}",
+                count,
+                self.len()
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Parses an argument, using `name` to describe it in errors
+    pub fn get<T: FromStr>(&self, index: usize, name: &str) -> Result<T, String>
+    where
+        T::Err: Display,
+    {
+        let word = self
+            .words
+            .get(index)
+            .ok_or_else(|| format!("Missing {}", name))?;
+
+        word.parse()
+            .map_err(|err| format!("Invalid {} {:?}: {}", name, word, err))
+    }
+
+    /// Like `get`, but None if there aren't enough arguments
+    pub fn optional<T: FromStr>(&self, index: usize, name: &str) -> Result<Option<T>, String>
+    where
+        T::Err: Display,
+    {
+        if index < self.len() {
+            self.get(index, name).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Three arguments starting at `index`
+    pub fn ivec3(&self, index: usize, name: &str) -> Result<IVec3, String> {
+        Ok(IVec3::new(
+            self.get(index, &format!("{} x", name))?,
+            self.get(index + 1, &format!("{} y", name))?,
+            self.get(index + 2, &format!("{} z", name))?,
+        ))
+    }
+
+    pub fn vec3(&self, index: usize, name: &str) -> Result<Vec3, String> {
+        Ok(Vec3::new(
+            self.get(index, &format!("{} x", name))?,
+            self.get(index + 1, &format!("{} y", name))?,
+            self.get(index + 2, &format!("{} z", name))?,
+        ))
+    }
+
+    /// Fails if any block from `min` to `max` is out of reach of the player running the command, if it's a player
+    pub fn reach(&self, min: IVec3, max: IVec3) -> Result<(), String> {
+        let Some(player) = self.player else { return Ok(()); };
+
+        let (min, max) = (min.as_vec3() + 0.5, max.as_vec3() + 0.5);
+        let furthest = Vec3::select((player - min).abs().cmpgt((player - max).abs()), min, max);
+        if furthest.distance(player) > MAX_REACH {
+            Err(format!("{} is out of reach", furthest.floor().as_ivec3()))
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn block(&self, index: usize) -> Result<Block, String> {
+        let name = self.get::<String>(index, "block")?;
+
+        Block::from_name(&name).ok_or_else(|| {
+            format!(
+                "Unknown block {:?}, expected one of {}",
+                name,
+                Block::ALL.map(|block| block.name()).join(", ")
+            )
+        })
+    }
+}
+
+/// Applies an edit to the world, recording it in the edit history if there is one.
+///
+/// Returns how many blocks changed.
+fn record_edit(
+    world: &mut EcsWorld,
+    edit: impl FnOnce(&mut World) -> Vec<(IVec3, Block)>,
+) -> usize {
+    world.resource_scope(|world, mut voxel_world: Mut<World>| {
+        let changed = edit(&mut voxel_world);
+        let count = changed.len();
+
+        if let Some(mut history) = world.get_resource_mut::<EditHistory>() {
+            history.record(&voxel_world, changed);
+        }
+
+        count
+    })
+}
+
+fn tp(world: &mut EcsWorld, args: &Args) -> Result<String, String> {
+    args.at_most(3)?;
+    let pos = args.vec3(0, "position")?;
+
+    let mut query = world.query_filtered::<&mut Transform, With<FlyCam>>();
+    let mut transform = query
+        .get_single_mut(world)
+        .map_err(|_| "There's no camera to move".to_string())?;
+    transform.translation = pos;
+
+    Ok(format!("Moved to {}", pos))
+}
+
+fn setblock(world: &mut EcsWorld, args: &Args) -> Result<String, String> {
+    args.at_most(4)?;
+    let pos = args.ivec3(0, "position")?;
+    let block = args.block(3)?;
+    args.reach(pos, pos)?;
+
+    if world.resource::<World>().get_block(pos).is_none() {
+        return Err(format!("{} isn't loaded", pos));
+    }
+
+    record_edit(world, |world| world.set_blocks([(pos, block)]));
+    Ok(format!("Set {} to {}", pos, block.name()))
+}
+
+/// The most blocks `fill` changes at once, so a typo can't freeze the game
+const MAX_FILL_VOLUME: i64 = 32 * 32 * 32;
+
+fn fill_command(world: &mut EcsWorld, args: &Args) -> Result<String, String> {
+    args.at_most(7)?;
+    let selection = Selection::new(args.ivec3(0, "from")?, args.ivec3(3, "to")?);
+    let block = args.block(6)?;
+
+    if selection.volume() > MAX_FILL_VOLUME {
+        return Err(format!(
+            "Can't fill {} blocks, the most is {}",
+            selection.volume(),
+            MAX_FILL_VOLUME
+        ));
+    }
+    args.reach(selection.min, selection.max)?;
+
+    let changed = record_edit(world, |world| fill(world, selection, block));
+    Ok(format!("Changed {} blocks", changed))
+}
+
+fn seed(world: &mut EcsWorld, args: &Args) -> Result<String, String> {
+    args.at_most(1)?;
+    let mut terrain_gen = world.resource_mut::<TerrainGen>();
+
+    match args.optional::<u32>(0, "seed")? {
+        Some(_) if args.player.is_some() => Err("Only the server can set the seed".to_string()),
+        Some(seed) => {
+            terrain_gen.height = terrain_gen.height.clone().set_seed(seed);
+            Ok(format!("Seed set to {}, use regen to apply it", seed))
+        }
+        None => Ok(format!("Seed is {}", terrain_gen.height.seed())),
+    }
+}
+
+fn regen(world: &mut EcsWorld, args: &Args) -> Result<String, String> {
+    args.at_most(0)?;
+
+    world.resource_scope(|world, terrain_gen: Mut<TerrainGen>| {
+        let mut voxel_world = world.resource_mut::<World>();
+        voxel_world.regenerate(&terrain_gen);
+        Ok(format!("Regenerated {} chunks", voxel_world.chunks.len()))
+    })
+}
+
+/// The most lines of output kept
+const OUTPUT_LIMIT: usize = 500;
+
+#[derive(Resource, Default)]
+pub struct Console {
+    pub open: bool,
+    input: String,
+    /// Printed lines, oldest first
+    output: Vec<String>,
+    /// Submitted lines, oldest first
+    history: Vec<String>,
+    /// The history entry being shown after pressing up
+    history_index: Option<usize>,
+    /// Submitted lines that haven't been run yet
+    queued: Vec<String>,
+    focus_input: bool,
+}
+
+impl Console {
+    pub fn print(&mut self, text: &str) {
+        self.output.extend(text.lines().map(str::to_string));
+
+        if self.output.len() > OUTPUT_LIMIT {
+            self.output.drain(..self.output.len() - OUTPUT_LIMIT);
+        }
+    }
+
+    /// Queues a line to be run, as if it was typed in
+    pub fn submit(&mut self, line: &str) {
+        let line = line.trim();
+        if line.is_empty() {
+            return;
+        }
+
+        self.print(&format!("> {}", line));
+        if self.history.last().map(String::as_str) != Some(line) {
+            self.history.push(line.to_string());
+        }
+        self.history_index = None;
+        self.queued.push(line.to_string());
+    }
+
+    /// Steps through the history, going back if `older` is true
+    fn scroll_history(&mut self, older: bool) {
+        let index = match (self.history_index, older) {
+            (None, true) => self.history.len().checked_sub(1),
+            (None, false) => None,
+            (Some(index), true) => Some(index.saturating_sub(1)),
+            (Some(index), false) => Some(index + 1).filter(|index| *index < self.history.len()),
+        };
+
+        self.history_index = index;
+        self.input = index.map_or(String::new(), |index| self.history[index].clone());
+    }
+
+    /// Completes the command name being typed,
+    /// or lists the options if there's more than one
+    fn complete(&mut self, commands: &ConsoleCommands) {
+        if self.input.contains(' ') {
+            return;
+        }
+
+        let names = commands.complete(&self.input);
+        match names.as_slice() {
+            [] => {}
+            [name] => self.input = format!("{} ", name),
+            [first, rest @ ..] => {
+                let common = rest.iter().fold(first.len(), |len, name| {
+                    first
+                        .chars()
+                        .zip(name.chars())
+                        .take(len)
+                        .take_while(|(a, b)| a == b)
+                        .count()
+                });
+                self.input = first[..common].to_string();
+                self.print(&names.join("  "));
+            }
+        }
+    }
+}
+
+impl ConsolePlugin {
+    pub fn toggle(keys: Res<Input<KeyCode>>, mut console: ResMut<Console>) {
+        if keys.just_pressed(KeyCode::Grave) {
+            console.open = !console.open;
+            console.focus_input = console.open;
+        }
+    }
+
+    pub fn track_typing(mut ctx: EguiContexts, mut typing: ResMut<Typing>) {
+        typing.0 = ctx.ctx_mut().wants_keyboard_input();
+    }
+
+    pub fn console_window(
+        mut ctx: EguiContexts,
+        mut console: ResMut<Console>,
+        commands: Res<ConsoleCommands>,
+    ) {
+        if !console.open {
+            return;
+        }
+
+        egui::Window::new("Console")
+            .default_width(500.)
+            .show(ctx.ctx_mut(), |ui| {
+                egui::ScrollArea::vertical()
+                    .max_height(300.)
+                    .stick_to_bottom(true)
+                    .show(ui, |ui| {
+                        for line in &console.output {
+                            ui.monospace(line);
+                        }
+                    });
+
+                let id = ui.make_persistent_id("console_input");
+                let mut moved_cursor = false;
+
+                // Taken before the text field sees them, so tab doesn't move focus
+                if ui.memory(|memory| memory.has_focus(id)) {
+                    let (tab, up, down) = ui.input_mut(|input| {
+                        (
+                            input.consume_key(egui::Modifiers::NONE, egui::Key::Tab),
+                            input.consume_key(egui::Modifiers::NONE, egui::Key::ArrowUp),
+                            input.consume_key(egui::Modifiers::NONE, egui::Key::ArrowDown),
+                        )
+                    });
+
+                    if tab {
+                        console.complete(&commands);
+                    }
+                    if up || down {
+                        console.scroll_history(up);
+                    }
+                    moved_cursor = tab || up || down;
+                }
+
+                let response = ui.add(
+                    egui::TextEdit::singleline(&mut console.input)
+                        .id(id)
+                        .font(egui::TextStyle::Monospace)
+                        .desired_width(f32::INFINITY),
+                );
+
+                if response.lost_focus() && ui.input(|input| input.key_pressed(egui::Key::Enter)) {
+                    let line = std::mem::take(&mut console.input);
+                    console.submit(&line);
+                    console.focus_input = true;
+                }
+
+                if std::mem::take(&mut console.focus_input) {
+                    response.request_focus();
+                }
+
+                if moved_cursor {
+                    if let Some(mut state) = egui::TextEdit::load_state(ui.ctx(), id) {
+                        let end = egui::text::CCursor::new(console.input.chars().count());
+                        state.set_ccursor_range(Some(egui::text::CCursorRange::one(end)));
+                        state.store(ui.ctx(), id);
+                    }
+                }
+            });
+    }
+
+    pub fn run_queued(world: &mut EcsWorld) {
+        let queued = std::mem::take(&mut world.resource_mut::<Console>().queued);
+        if queued.is_empty() {
+            return;
+        }
+
+        world.resource_scope(|world, commands: Mut<ConsoleCommands>| {
+            for line in queued {
+                let output = match commands.run(world, &line) {
+                    Ok(output) => output,
+                    Err(err) => format!("Error: {}", err),
+                };
+                world.resource_mut::<Console>().print(&output);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(ConsolePlugin);
+
+        app.insert_resource(World::with_chunks([IVec3::ZERO], |_| Block::Air))
+            .init_resource::<EditHistory>()
+            .init_resource::<TerrainGen>();
+
+        app
+    }
+
+    fn run(app: &mut App, line: &str) -> Result<String, String> {
+        app.world
+            .resource_scope(|world, commands: Mut<ConsoleCommands>| commands.run(world, line))
+    }
+
+    #[test]
+    fn commands_edit_the_world() {
+        let mut app = test_app();
+
+        run(&mut app, "setblock 1 2 3 lava").unwrap();
+        run(&mut app, "fill 0 0 0 1 1 1 stone").unwrap();
+
+        let world = app.world.resource::<World>();
+        assert_eq!(world.get_block(IVec3::new(1, 2, 3)), Some(Block::Lava));
+        assert_eq!(world.get_block(IVec3::new(1, 1, 0)), Some(Block::Stone));
+        assert!(app.world.resource::<EditHistory>().can_undo());
+    }
+
+    #[test]
+    fn argument_errors() {
+        let mut app = test_app();
+
+        let err = run(&mut app, "setblock 1 2").unwrap_err();
+        assert!(err.starts_with("Missing position z"), "{}", err);
+        assert!(
+            err.ends_with("Usage: setblock <x> <y> <z> <block>"),
+            "{}",
+            err
+        );
+
+        let err = run(&mut app, "setblock 1 two 3 stone").unwrap_err();
+        assert!(err.starts_with("Invalid position y \"two\""), "{}", err);
+
+        let err = run(&mut app, "setblock 1 2 3 glass").unwrap_err();
+        assert!(err.starts_with("Unknown block \"glass\""), "{}", err);
+
+        let err = run(&mut app, "setblock 100 2 3 stone").unwrap_err();
+        assert!(err.starts_with("[100, 2, 3] isn't loaded"), "{}", err);
+
+        assert!(run(&mut app, "regen now").is_err());
+        assert!(run(&mut app, "teleport 1 2 3").is_err());
+
+        let err = run(&mut app, "fill 0 0 0 1000 1000 1000 stone").unwrap_err();
+        assert!(err.starts_with("Can't fill 1003003001 blocks"), "{}", err);
+    }
+
+    #[test]
+    fn remote_players_only_run_remote_commands() {
+        let mut app = test_app();
+        let mut run_remote = |line: &str| {
+            app.world
+                .resource_scope(|world, commands: Mut<ConsoleCommands>| {
+                    commands.run_remote(world, line, Vec3::new(8., 8., 8.))
+                })
+        };
+
+        let err = run_remote("regen").unwrap_err();
+        assert!(
+            err.starts_with("regen can only be run on the server"),
+            "{}",
+            err
+        );
+        assert!(run_remote("help regen").is_err());
+        let help = run_remote("help").unwrap();
+        assert!(help.lines().any(|line| line.starts_with("setblock ")));
+        assert!(!help.lines().any(|line| line.starts_with("regen ")));
+
+        // Edits have to be in reach, and the seed can only be looked at
+        run_remote("setblock 1 2 3 lava").unwrap();
+        run_remote("fill 0 0 0 1 1 1 stone").unwrap();
+        let err = run_remote("fill 0 0 0 100 1 1 stone").unwrap_err();
+        assert!(err.starts_with("[100, 0, 0] is out of reach"), "{}", err);
+        assert!(run_remote("seed").is_ok());
+        assert!(run_remote("seed 1234").is_err());
+
+        let world = app.world.resource::<World>();
+        assert_eq!(world.get_block(IVec3::new(1, 2, 3)), Some(Block::Lava));
+        assert_eq!(world.get_block(IVec3::new(1, 1, 0)), Some(Block::Stone));
+        assert_eq!(world.get_block(IVec3::new(2, 0, 0)), Some(Block::Air));
+    }
+
+    #[test]
+    fn seed_round_trips() {
+        let mut app = test_app();
+
+        run(&mut app, "seed 1234").unwrap();
+        assert_eq!(run(&mut app, "seed"), Ok("Seed is 1234".to_string()));
+    }
+
+    #[test]
+    fn history_and_completion() {
+        let app = test_app();
+        let commands = app.world.resource::<ConsoleCommands>();
+        let mut console = Console::default();
+
+        assert_eq!(commands.complete("s"), vec!["seed", "setblock"]);
+
+        console.input = "s".to_string();
+        console.complete(commands);
+        assert_eq!(console.input, "se");
+        console.input = "set".to_string();
+        console.complete(commands);
+        assert_eq!(console.input, "setblock ");
+
+        console.submit("tp 0 0 0");
+        console.submit("regen");
+        console.scroll_history(true);
+        assert_eq!(console.input, "regen");
+        console.scroll_history(true);
+        console.scroll_history(true);
+        assert_eq!(console.input, "tp 0 0 0");
+        console.scroll_history(false);
+        console.scroll_history(false);
+        assert_eq!(console.input, "");
+    }
+}
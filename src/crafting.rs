@@ -0,0 +1,455 @@
+use std::{collections::HashMap, fs, path::PathBuf};
+
+use bevy::{asset::FileAssetIo, prelude::*};
+use bevy_egui::{egui, EguiContexts};
+use serde::Deserialize;
+
+use crate::{
+    block::Block,
+    camera::FlyCam,
+    inventory::{Inventory, ItemStack},
+};
+
+pub const GRID_SIZE: usize = 3;
+
+/// The blocks put into the crafting grid, top row first
+pub type Grid = [[Option<Block>; GRID_SIZE]; GRID_SIZE];
+
+/// A recipe as written in `assets/recipes.ron`, naming blocks like console commands do
+#[derive(Deserialize)]
+enum RecipeDef {
+    /// Each row is a string with a character per cell, looked up in `key`.
+    /// Spaces are empty cells.
+    Shaped {
+        pattern: Vec<String>,
+        key: HashMap<char, String>,
+        result: String,
+        #[serde(default = "one")]
+        count: u32,
+    },
+    /// The ingredients go anywhere in the grid
+    Shapeless {
+        ingredients: Vec<String>,
+        result: String,
+        #[serde(default = "one")]
+        count: u32,
+    },
+}
+
+fn one() -> u32 {
+    1
+}
+
+/// A way of turning blocks in the crafting grid into other blocks
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Recipe {
+    /// The pattern can go anywhere in the grid, but nothing else can be in it
+    Shaped {
+        /// Trimmed to the smallest box around its blocks
+        pattern: Vec<Vec<Option<Block>>>,
+        result: ItemStack,
+    },
+    Shapeless {
+        /// Sorted by id
+        ingredients: Vec<Block>,
+        result: ItemStack,
+    },
+}
+
+/// Looks up a block named in a recipe, which has to be one that can be held
+fn ingredient(name: &str) -> Result<Block, String> {
+    match Block::from_name(name) {
+        Some(Block::Air) => Err("\"air\" can't be crafted with".to_string()),
+        Some(block) => Ok(block),
+        None => Err(format!(
+            "Unknown block {:?}, expected one of {}",
+            name,
+            Block::ALL[1..]
+                .iter()
+                .map(Block::name)
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
+
+/// Cuts the empty rows and columns from around the blocks in a grid
+fn trim(rows: &[Vec<Option<Block>>]) -> Vec<Vec<Option<Block>>> {
+    let filled = |row: &Vec<Option<Block>>| row.iter().any(Option::is_some);
+    let width = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let column_filled = |x: usize| {
+        rows.iter()
+            .any(|row| row.get(x).copied().flatten().is_some())
+    };
+
+    let (Some(top), Some(bottom)) = (rows.iter().position(filled), rows.iter().rposition(filled))
+    else {
+        return vec![];
+    };
+    let left = (0..width).find(|x| column_filled(*x)).unwrap_or(0);
+    let right = (0..width).rfind(|x| column_filled(*x)).unwrap_or(0);
+
+    rows[top..=bottom]
+        .iter()
+        .map(|row| {
+            (left..=right)
+                .map(|x| row.get(x).copied().flatten())
+                .collect()
+        })
+        .collect()
+}
+
+impl Recipe {
+    fn from_def(def: RecipeDef) -> Result<Self, String> {
+        let stack = |result: &str, count| {
+            if count == 0 {
+                return Err("The result count can't be 0".to_string());
+            }
+            Ok(ItemStack {
+                block: ingredient(result)?,
+                count,
+            })
+        };
+
+        match def {
+            RecipeDef::Shaped {
+                pattern,
+                key,
+                result,
+                count,
+            } => {
+                if pattern.len() > GRID_SIZE
+                    || pattern.iter().any(|row| row.chars().count() > GRID_SIZE)
+                {
+                    return Err(format!("The pattern is bigger than {0}x{0}", GRID_SIZE));
+                }
+
+                let key = key
+                    .into_iter()
+                    .map(|(symbol, name)| Ok((symbol, ingredient(&name)?)))
+                    .collect::<Result<HashMap<_, _>, String>>()?;
+                let rows = pattern
+                    .iter()
+                    .map(|row| {
+                        row.chars()
+                            .map(|symbol| match symbol {
+                                ' ' => Ok(None),
+                                _ => key.get(&symbol).copied().map(Some).ok_or_else(|| {
+                                    format!("The pattern uses {:?}, which isn't in the key", symbol)
+                                }),
+                            })
+                            .collect()
+                    })
+                    .collect::<Result<Vec<_>, String>>()?;
+
+                let pattern = trim(&rows);
+                if pattern.is_empty() {
+                    return Err("The pattern is empty".to_string());
+                }
+
+                Ok(Recipe::Shaped {
+                    pattern,
+                    result: stack(&result, count)?,
+                })
+            }
+            RecipeDef::Shapeless {
+                ingredients,
+                result,
+                count,
+            } => {
+                if ingredients.is_empty() || ingredients.len() > GRID_SIZE * GRID_SIZE {
+                    return Err(format!(
+                        "There must be 1 to {} ingredients",
+                        GRID_SIZE * GRID_SIZE
+                    ));
+                }
+
+                let mut ingredients = ingredients
+                    .iter()
+                    .map(|name| ingredient(name))
+                    .collect::<Result<Vec<_>, String>>()?;
+                ingredients.sort_by_key(Block::id);
+
+                Ok(Recipe::Shapeless {
+                    ingredients,
+                    result: stack(&result, count)?,
+                })
+            }
+        }
+    }
+
+    pub fn result(&self) -> ItemStack {
+        match self {
+            Recipe::Shaped { result, .. } | Recipe::Shapeless { result, .. } => *result,
+        }
+    }
+
+    /// Whether the blocks in `grid` make this recipe
+    pub fn matches(&self, grid: &Grid) -> bool {
+        match self {
+            Recipe::Shaped { pattern, .. } => trim(&grid.map(Vec::from)) == *pattern,
+            Recipe::Shapeless { ingredients, .. } => {
+                let mut blocks = grid.iter().flatten().flatten().copied().collect::<Vec<_>>();
+                blocks.sort_by_key(Block::id);
+                blocks == *ingredients
+            }
+        }
+    }
+}
+
+/// Every recipe, loaded from `assets/recipes.ron` when the app starts
+#[derive(Resource, Default, Debug)]
+pub struct Recipes(pub Vec<Recipe>);
+
+impl Recipes {
+    fn path() -> PathBuf {
+        FileAssetIo::get_base_path()
+            .join("assets")
+            .join("recipes.ron")
+    }
+
+    /// Reads a list of recipes, checking every block they name exists.
+    ///
+    /// Returns an error for each recipe that's wrong.
+    pub fn parse(text: &str) -> Result<Self, Vec<String>> {
+        let defs = ron::from_str::<Vec<RecipeDef>>(text).map_err(|err| vec![err.to_string()])?;
+
+        let mut recipes = vec![];
+        let mut errors = vec![];
+        for (index, def) in defs.into_iter().enumerate() {
+            match Recipe::from_def(def) {
+                Ok(recipe) => recipes.push(recipe),
+                Err(err) => errors.push(format!("Recipe {}: {}", index + 1, err)),
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(Self(recipes))
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Loads `assets/recipes.ron`, logging what's wrong with it and crafting nothing if it's invalid
+    pub fn load() -> Self {
+        let path = Self::path();
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(err) => {
+                error!("Failed to read {:?}: {}", path, err);
+                return Self::default();
+            }
+        };
+
+        Self::parse(&text).unwrap_or_else(|errors| {
+            for err in errors {
+                error!("Invalid recipe in {:?}: {}", path, err);
+            }
+            Self::default()
+        })
+    }
+
+    /// The recipe the blocks in `grid` make, if any
+    pub fn lookup(&self, grid: &Grid) -> Option<&Recipe> {
+        self.0.iter().find(|recipe| recipe.matches(grid))
+    }
+}
+
+/// Crafts one of `recipe` from the blocks in `grid`, taking one of each from the inventory.
+///
+/// Returns false and leaves the inventory alone if there isn't enough of something,
+/// or no room for the result.
+pub fn craft(inventory: &mut Inventory, grid: &Grid, recipe: &Recipe) -> bool {
+    let mut counts = HashMap::<Block, u32>::new();
+    for block in grid.iter().flatten().flatten() {
+        *counts.entry(*block).or_default() += 1;
+    }
+
+    let mut crafted = inventory.clone();
+    let result = recipe.result();
+    if !counts
+        .into_iter()
+        .all(|(block, count)| crafted.remove(block, count))
+        || crafted.add(result.block, result.count) > 0
+    {
+        return false;
+    }
+
+    *inventory = crafted;
+    true
+}
+
+/// A window with a crafting grid, filled from and crafting into the camera's inventory,
+/// which needs `EguiPlugin`.
+///
+/// Crafting only changes the local inventory, so it's not for use with `net::NetClientPlugin`.
+pub struct CraftingPlugin;
+
+impl Plugin for CraftingPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Recipes::load())
+            .init_resource::<CraftingGrid>()
+            .add_systems(Update, Self::crafting_window);
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct CraftingGrid(pub Grid);
+
+impl CraftingPlugin {
+    pub fn crafting_window(
+        mut ctx: EguiContexts,
+        recipes: Res<Recipes>,
+        mut grid: ResMut<CraftingGrid>,
+        mut query: Query<&mut Inventory, With<FlyCam>>,
+    ) {
+        let Ok(mut inventory) = query.get_single_mut() else { return; };
+
+        egui::Window::new("Crafting").show(ctx.ctx_mut(), |ui| {
+            egui::Grid::new("crafting_grid").show(ui, |ui| {
+                for (y, row) in grid.0.iter_mut().enumerate() {
+                    for (x, cell) in row.iter_mut().enumerate() {
+                        let text = cell.map_or("", |block| block.name());
+
+                        egui::ComboBox::from_id_source(("crafting_cell", x, y))
+                            .selected_text(text)
+                            .width(60.)
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(cell, None, "");
+                                for block in Block::ALL[1..].iter().copied() {
+                                    let count = inventory.count(block);
+                                    if count > 0 {
+                                        ui.selectable_value(
+                                            cell,
+                                            Some(block),
+                                            format!("{} ({})", block.name(), count),
+                                        );
+                                    }
+                                }
+                            });
+                    }
+                    ui.end_row();
+                }
+            });
+
+            if ui.button("Clear").clicked() {
+                grid.0 = default();
+            }
+
+            ui.separator();
+
+            let Some(recipe) = recipes.lookup(&grid.0) else {
+                ui.label("Nothing to craft");
+                return;
+            };
+
+            let result = recipe.result();
+            ui.horizontal(|ui| {
+                ui.label(format!("Makes {} {}", result.count, result.block.name()));
+
+                if ui.button("Craft").clicked() && !craft(&mut inventory, &grid.0, recipe) {
+                    warn!(
+                        "Not enough blocks or no room to craft {}",
+                        result.block.name()
+                    );
+                }
+            });
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid(rows: [&str; GRID_SIZE]) -> Grid {
+        rows.map(|row| {
+            let mut cells = [None; GRID_SIZE];
+            for (cell, symbol) in cells.iter_mut().zip(row.chars()) {
+                *cell = match symbol {
+                    'd' => Some(Block::Dirt),
+                    's' => Some(Block::Stone),
+                    'l' => Some(Block::Lava),
+                    _ => None,
+                };
+            }
+            cells
+        })
+    }
+
+    #[test]
+    fn the_default_recipes_are_valid() {
+        let text = fs::read_to_string("assets/recipes.ron").unwrap();
+        assert!(!Recipes::parse(&text).unwrap().0.is_empty());
+    }
+
+    #[test]
+    fn unknown_blocks_are_reported() {
+        let errors = Recipes::parse(
+            r#"[
+                Shapeless(ingredients: ["dirt"], result: "stone"),
+                Shapeless(ingredients: ["dirt", "plank"], result: "stone"),
+                Shaped(pattern: ["X"], key: {'Y': "dirt"}, result: "stone"),
+                Shaped(pattern: ["X"], key: {'X': "dirt"}, result: "air"),
+            ]"#,
+        )
+        .unwrap_err();
+
+        assert_eq!(errors.len(), 3);
+        assert!(errors[0].starts_with("Recipe 2: Unknown block \"plank\""));
+        assert_eq!(
+            errors[1],
+            "Recipe 3: The pattern uses 'X', which isn't in the key"
+        );
+        assert_eq!(errors[2], "Recipe 4: \"air\" can't be crafted with");
+    }
+
+    #[test]
+    fn shaped_recipes_go_anywhere() {
+        let recipes = Recipes::parse(
+            r#"[
+                Shaped(pattern: ["ds", " s"], key: {'d': "dirt", 's': "stone"}, result: "torch", count: 4),
+                Shapeless(ingredients: ["lava", "dirt"], result: "stone"),
+            ]"#,
+        )
+        .unwrap();
+        let result = |rows| recipes.lookup(&grid(rows)).map(Recipe::result);
+
+        let torches = Some(ItemStack {
+            block: Block::Torch,
+            count: 4,
+        });
+        assert_eq!(result(["ds ", " s ", "   "]), torches);
+        assert_eq!(result(["   ", " ds", "  s"]), torches);
+        // Mirrored, or with something extra
+        assert_eq!(result(["sd ", "s  ", "   "]), None);
+        assert_eq!(result(["ds ", " s ", "d  "]), None);
+
+        let stone = Some(ItemStack {
+            block: Block::Stone,
+            count: 1,
+        });
+        assert_eq!(result(["  l", "   ", "d  "]), stone);
+        assert_eq!(result(["  l", "  d", "d  "]), None);
+    }
+
+    #[test]
+    fn crafting_uses_up_ingredients() {
+        let recipes =
+            Recipes::parse(r#"[Shapeless(ingredients: ["dirt", "dirt"], result: "stone")]"#)
+                .unwrap();
+        let grid = grid(["dd ", "   ", "   "]);
+        let recipe = recipes.lookup(&grid).unwrap();
+
+        let mut inventory = Inventory::default();
+        inventory.add(Block::Dirt, 3);
+
+        assert!(craft(&mut inventory, &grid, recipe));
+        assert_eq!(inventory.count(Block::Dirt), 1);
+        assert_eq!(inventory.count(Block::Stone), 1);
+
+        assert!(!craft(&mut inventory, &grid, recipe));
+        assert_eq!(inventory.count(Block::Dirt), 1);
+        assert_eq!(inventory.count(Block::Stone), 1);
+    }
+}
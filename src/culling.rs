@@ -0,0 +1,261 @@
+use std::collections::VecDeque;
+
+use bevy::{
+    diagnostic::{Diagnostic, DiagnosticId, Diagnostics, DiagnosticsStore},
+    prelude::*,
+    render::primitives::{Aabb, Frustum},
+    utils::HashSet,
+};
+
+use crate::{
+    chunk::{Chunk, CHUNK_SIZE, CHUNK_SIZE_I32, CHUNK_VOLUME},
+    mesh::Direction,
+    world::{split_pos, world_mesh_gen, ChunkViewer, World},
+};
+
+/// Hides chunks that can't be seen through any cave or opening,
+/// using a flood fill through the faces of each chunk.
+///
+/// Bevy's frustum culling still runs on whatever is left visible.
+pub struct OcclusionCullingPlugin;
+
+impl Plugin for OcclusionCullingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, Self::setup_diagnostic)
+            .add_systems(Update, Self::cull_chunks.after(world_mesh_gen));
+    }
+}
+
+/// Which faces of a chunk can see each other through non opaque blocks
+#[derive(Clone, Copy, Default)]
+pub struct Connectivity(u64);
+
+impl Connectivity {
+    /// Every face connected, used for chunks that aren't loaded
+    pub const ALL: Connectivity = Connectivity(u64::MAX);
+
+    fn bit(a: Direction, b: Direction) -> u64 {
+        1 << (a.index() * 6 + b.index())
+    }
+
+    pub fn connected(&self, a: Direction, b: Direction) -> bool {
+        self.0 & Self::bit(a, b) != 0
+    }
+
+    fn connect(&mut self, a: Direction, b: Direction) {
+        self.0 |= Self::bit(a, b) | Self::bit(b, a);
+    }
+
+    pub fn of(chunk: &Chunk) -> Self {
+        let mut connectivity = Self::default();
+        let mut visited = vec![false; CHUNK_VOLUME];
+        let index = |pos: IVec3| {
+            (pos.x as usize * CHUNK_SIZE + pos.y as usize) * CHUNK_SIZE + pos.z as usize
+        };
+        let mut queue = VecDeque::new();
+
+        for x in 0..CHUNK_SIZE_I32 {
+            for y in 0..CHUNK_SIZE_I32 {
+                for z in 0..CHUNK_SIZE_I32 {
+                    let start = IVec3::new(x, y, z);
+                    if visited[index(start)] || chunk.get_or_air(x, y, z).is_opaque() {
+                        continue;
+                    }
+
+                    // Flood fill this pocket of air, collecting the faces it touches
+                    let mut faces = vec![];
+                    visited[index(start)] = true;
+                    queue.push_back(start);
+
+                    while let Some(pos) = queue.pop_front() {
+                        for dir in Direction::iter() {
+                            let neighbour = pos + dir.normal();
+
+                            let Some(block) = chunk.try_get(neighbour.x, neighbour.y, neighbour.z)
+                            else {
+                                if !faces.contains(&dir) {
+                                    faces.push(dir);
+                                }
+                                continue;
+                            };
+
+                            let seen = &mut visited[index(neighbour)];
+                            if !*seen && !block.is_opaque() {
+                                *seen = true;
+                                queue.push_back(neighbour);
+                            }
+                        }
+                    }
+
+                    for &a in &faces {
+                        for &b in &faces {
+                            connectivity.connect(a, b);
+                        }
+                    }
+                }
+            }
+        }
+
+        connectivity
+    }
+}
+
+impl OcclusionCullingPlugin {
+    pub fn diagnostic_id() -> DiagnosticId {
+        DiagnosticId::from_u128(152385627374501837629103478261936201445)
+    }
+
+    pub fn setup_diagnostic(mut diagnostics: ResMut<DiagnosticsStore>) {
+        diagnostics.add(Diagnostic::new(Self::diagnostic_id(), "culled_chunks", 20));
+    }
+
+    /// Walks outwards from the camera's chunk through connected faces,
+    /// never turning back towards the camera, and hides every chunk it doesn't reach
+    pub fn cull_chunks(
+        camera: Query<(&Transform, &Frustum), With<ChunkViewer>>,
+        world: Res<World>,
+        mut visibilities: Query<&mut Visibility>,
+        mut diagnostics: Diagnostics,
+    ) {
+        let Ok((transform, frustum)) = camera.get_single() else { return; };
+        let start = split_pos(transform.translation.floor().as_ivec3()).0;
+
+        // Unloaded chunks are treated as open air, but only just around the loaded ones
+        let (mut min, mut max) = (start, start);
+        for &chunk_id in world.chunks.keys() {
+            min = min.min(chunk_id - IVec3::ONE);
+            max = max.max(chunk_id + IVec3::ONE);
+        }
+
+        let chunk_aabb = Aabb::from_min_max(Vec3::ZERO, Vec3::splat(CHUNK_SIZE as f32));
+        let in_frustum = |chunk_id: IVec3| {
+            frustum.intersects_obb(
+                &chunk_aabb,
+                &Mat4::from_translation((chunk_id * CHUNK_SIZE_I32).as_vec3()),
+                true,
+                false,
+            )
+        };
+
+        let mut visible = HashSet::new();
+        visible.insert(start);
+
+        // Each entry holds the face the chunk was entered through,
+        // and the directions travelled to reach it
+        let mut queue = VecDeque::new();
+        queue.push_back((start, None::<Direction>, 0u8));
+
+        while let Some((chunk_id, entered, travelled)) = queue.pop_front() {
+            let connectivity = world
+                .connectivity
+                .get(&chunk_id)
+                .copied()
+                .unwrap_or(Connectivity::ALL);
+
+            for dir in Direction::iter() {
+                if travelled & (1 << dir.opposite().index()) != 0 {
+                    continue;
+                }
+
+                if entered.is_some_and(|entered| !connectivity.connected(entered, dir)) {
+                    continue;
+                }
+
+                let neighbour = chunk_id + dir.normal();
+                if neighbour.cmplt(min).any() || neighbour.cmpgt(max).any() {
+                    continue;
+                }
+
+                if visible.contains(&neighbour) || !in_frustum(neighbour) {
+                    continue;
+                }
+
+                visible.insert(neighbour);
+                queue.push_back((
+                    neighbour,
+                    Some(dir.opposite()),
+                    travelled | (1 << dir.index()),
+                ));
+            }
+        }
+
+        let mut culled = 0;
+        for (chunk_id, &entity) in &world.meshes {
+            let Ok(mut visibility) = visibilities.get_mut(entity) else { continue; };
+
+            let new_visibility = if visible.contains(chunk_id) {
+                Visibility::Inherited
+            } else {
+                culled += 1;
+                Visibility::Hidden
+            };
+
+            if *visibility != new_visibility {
+                *visibility = new_visibility;
+            }
+        }
+
+        diagnostics.add_measurement(Self::diagnostic_id(), || culled as f64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::Block;
+
+    fn chunk(fill: impl Fn(IVec3) -> Block) -> Chunk {
+        let mut chunk = Chunk::new(IVec3::ZERO);
+        for x in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE {
+                for z in 0..CHUNK_SIZE {
+                    *chunk.get_mut(x, y, z) = fill(IVec3::new(x as i32, y as i32, z as i32));
+                }
+            }
+        }
+        chunk
+    }
+
+    /// The faces connected to any other face
+    fn connected_faces(connectivity: Connectivity) -> Vec<Direction> {
+        Direction::iter()
+            .filter(|&a| Direction::iter().any(|b| connectivity.connected(a, b)))
+            .collect()
+    }
+
+    #[test]
+    fn solid_chunks_connect_nothing() {
+        let connectivity = Connectivity::of(&chunk(|_| Block::Stone));
+        assert_eq!(connected_faces(connectivity), []);
+    }
+
+    #[test]
+    fn empty_chunks_connect_everything() {
+        let connectivity = Connectivity::of(&chunk(|_| Block::Air));
+
+        for a in Direction::iter() {
+            for b in Direction::iter() {
+                assert!(connectivity.connected(a, b));
+            }
+        }
+    }
+
+    #[test]
+    fn tunnels_connect_their_ends() {
+        let tunnel = chunk(|pos| {
+            if pos.y == 8 && pos.z == 8 {
+                Block::Air
+            } else {
+                Block::Stone
+            }
+        });
+        let connectivity = Connectivity::of(&tunnel);
+
+        assert_eq!(
+            connected_faces(connectivity),
+            [Direction::Px, Direction::Nx]
+        );
+        assert!(connectivity.connected(Direction::Nx, Direction::Px));
+        assert!(!connectivity.connected(Direction::Nx, Direction::Py));
+    }
+}
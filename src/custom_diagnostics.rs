@@ -20,14 +20,41 @@ impl CustomDiagnosticsPlugin {
         DiagnosticId::from_u128(311626136719528359392517333749239739456)
     }
 
+    pub fn chunk_memory_diagnostic_id() -> DiagnosticId {
+        DiagnosticId::from_u128(96872305521983174605923718265520383114)
+    }
+
+    /// The average time taken to mesh a chunk
+    pub fn mesh_time_diagnostic_id() -> DiagnosticId {
+        DiagnosticId::from_u128(252064214713895301637226810283150944790)
+    }
+
     /// Registers the asset count diagnostic for the current application.
     pub fn setup_system(mut diagnostics: ResMut<DiagnosticsStore>) {
         diagnostics.add(Diagnostic::new(Self::diagnostic_id(), "entity_count", 20));
+        diagnostics.add(
+            Diagnostic::new(Self::chunk_memory_diagnostic_id(), "chunk_memory", 20)
+                .with_suffix("KiB"),
+        );
+        diagnostics.add(
+            Diagnostic::new(Self::mesh_time_diagnostic_id(), "mesh_time", 20).with_suffix("ms"),
+        );
     }
 
     /// Updates the asset count of `T` assets.
     pub fn diagnostic_system(mut diagnostics: Diagnostics, world: &World) {
         diagnostics.add_measurement(Self::diagnostic_id(), || world.entities().len() as f64);
+
+        if let Some(voxel_world) = world.get_resource::<crate::world::World>() {
+            diagnostics.add_measurement(Self::chunk_memory_diagnostic_id(), || {
+                voxel_world
+                    .chunks
+                    .values()
+                    .map(|chunk| chunk.memory_usage())
+                    .sum::<usize>() as f64
+                    / 1024.
+            });
+        }
     }
 
     pub fn egui_diagnostics(mut ctx: EguiContexts, diagnostics: Res<DiagnosticsStore>) {
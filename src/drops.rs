@@ -0,0 +1,371 @@
+use bevy::prelude::*;
+
+use crate::{
+    block::Block,
+    inventory::{Inventory, ItemStack, MAX_STACK},
+    world::{BlockEdited, World},
+};
+
+const GRAVITY: f32 = 20.;
+const MAX_FALL_SPEED: f32 = 20.;
+/// How fast drops jump up out of the block that was broken
+const POP_SPEED: f32 = 4.;
+/// The width of a drop's box, which its model is drawn to fit
+const DROP_SIZE: f32 = 0.25;
+/// Drops of the same block closer than this become one stack
+const MERGE_RADIUS: f32 = 1.;
+/// Players closer than this to a drop pick it up
+const PICKUP_RADIUS: f32 = 2.;
+/// How long a drop is around before it can be picked up, in seconds
+const PICKUP_DELAY: f32 = 0.5;
+/// Drops nobody picks up disappear after this many seconds
+const DESPAWN_AFTER: f32 = 300.;
+
+/// Spawns a drop of `stack` centred on `translation`, as when a block is broken
+#[derive(Event, Clone, Copy, Debug)]
+pub struct SpawnDrop {
+    pub translation: Vec3,
+    pub stack: ItemStack,
+}
+
+/// A stack of blocks lying in the world, waiting for a player to pick it up
+#[derive(Component, Clone, Copy, Debug)]
+pub struct ItemDrop {
+    pub stack: ItemStack,
+    /// Upwards, in blocks per second
+    pub velocity: f32,
+    /// In seconds
+    pub age: f32,
+}
+
+impl ItemDrop {
+    pub fn new(stack: ItemStack) -> Self {
+        Self {
+            stack,
+            velocity: POP_SPEED,
+            age: 0.,
+        }
+    }
+}
+
+/// Whether a drop centred on `centre` only overlaps air.
+///
+/// Unloaded chunks count as solid so nothing falls out of the world.
+fn is_free(world: &World, centre: Vec3) -> bool {
+    let half = DROP_SIZE / 2.;
+
+    [-half, half].into_iter().all(|x| {
+        [-half, half].into_iter().all(|y| {
+            [-half, half].into_iter().all(|z| {
+                let corner = (centre + Vec3::new(x, y, z)).floor().as_ivec3();
+                world.get_block(corner) == Some(Block::Air)
+            })
+        })
+    })
+}
+
+/// Moves a drop down by `dt` seconds of gravity, landing it on top of the terrain
+pub fn fall(world: &World, translation: &mut Vec3, velocity: &mut f32, dt: f32) {
+    *velocity = (*velocity - GRAVITY * dt).max(-MAX_FALL_SPEED);
+
+    let moved = *translation + Vec3::Y * *velocity * dt;
+    if is_free(world, moved) {
+        *translation = moved;
+        return;
+    }
+
+    // Right up against the block it would have gone into
+    let half = DROP_SIZE / 2.;
+    let snapped = if *velocity < 0. {
+        (moved.y - half).floor() + 1. + half
+    } else {
+        (moved.y + half).floor() - half
+    };
+    if is_free(world, Vec3::new(moved.x, snapped, moved.z)) {
+        translation.y = snapped;
+    }
+
+    *velocity = 0.;
+}
+
+/// Spawns drops from `SpawnDrop` events and from blocks broken into air, as told by `BlockEdited`.
+/// They fall onto the terrain, bob up and down,
+/// merge with each other and get picked up by anything with an `Inventory` that comes close
+pub struct DropsPlugin;
+
+impl Plugin for DropsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<SpawnDrop>()
+            .add_event::<BlockEdited>()
+            .add_systems(
+                Update,
+                (
+                    Self::drop_broken_blocks,
+                    Self::spawn_drops,
+                    Self::add_models.run_if(resource_exists::<Assets<Mesh>>()),
+                    Self::fall,
+                    Self::merge,
+                    Self::pick_up,
+                    Self::bob,
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// The spinning cube drawn for a drop
+#[derive(Component)]
+struct DropModel;
+
+impl DropsPlugin {
+    fn drop_broken_blocks(mut edited: EventReader<BlockEdited>, mut drops: EventWriter<SpawnDrop>) {
+        for &BlockEdited { pos, old, new } in edited.iter() {
+            if old != Block::Air && new == Block::Air {
+                drops.send(SpawnDrop {
+                    translation: pos.as_vec3() + 0.5,
+                    stack: ItemStack {
+                        block: old,
+                        count: 1,
+                    },
+                });
+            }
+        }
+    }
+
+    fn spawn_drops(mut commands: Commands, mut events: EventReader<SpawnDrop>) {
+        for event in events.iter() {
+            commands.spawn((
+                ItemDrop::new(event.stack),
+                SpatialBundle::from_transform(Transform::from_translation(event.translation)),
+            ));
+        }
+    }
+
+    fn add_models(
+        mut commands: Commands,
+        query: Query<(Entity, &ItemDrop), Added<ItemDrop>>,
+        mut meshes: ResMut<Assets<Mesh>>,
+        mut materials: ResMut<Assets<StandardMaterial>>,
+    ) {
+        for (entity, drop) in &query {
+            let Some([r, g, b]) = drop.stack.block.colour() else { continue; };
+
+            commands.entity(entity).with_children(|parent| {
+                parent.spawn((
+                    PbrBundle {
+                        mesh: meshes.add(shape::Cube::new(DROP_SIZE).into()),
+                        material: materials.add(Color::rgb_u8(r, g, b).into()),
+                        ..default()
+                    },
+                    DropModel,
+                ));
+            });
+        }
+    }
+
+    fn fall(
+        mut commands: Commands,
+        time: Res<Time>,
+        world: Res<World>,
+        mut query: Query<(Entity, &mut ItemDrop, &mut Transform)>,
+    ) {
+        let dt = time.delta_seconds();
+
+        for (entity, mut drop, mut transform) in &mut query {
+            drop.age += dt;
+            if drop.age > DESPAWN_AFTER {
+                commands.entity(entity).despawn_recursive();
+                continue;
+            }
+
+            fall(&world, &mut transform.translation, &mut drop.velocity, dt);
+        }
+    }
+
+    fn merge(mut commands: Commands, mut query: Query<(Entity, &mut ItemDrop, &Transform)>) {
+        let mut drops = query
+            .iter()
+            .filter(|(_, drop, _)| drop.age <= DESPAWN_AFTER)
+            .map(|(entity, drop, transform)| (entity, drop.stack, transform.translation))
+            .collect::<Vec<_>>();
+        let mut merged = vec![false; drops.len()];
+
+        for i in 0..drops.len() {
+            if merged[i] {
+                continue;
+            }
+
+            for j in i + 1..drops.len() {
+                let (_, stack, translation) = drops[j];
+                if merged[j]
+                    || stack.block != drops[i].1.block
+                    || stack.count + drops[i].1.count > MAX_STACK
+                    || translation.distance(drops[i].2) > MERGE_RADIUS
+                {
+                    continue;
+                }
+
+                drops[i].1.count += stack.count;
+                merged[j] = true;
+                commands.entity(drops[j].0).despawn_recursive();
+            }
+        }
+
+        for ((entity, mut stack, _), merged) in drops.into_iter().zip(merged) {
+            // Emptied so nothing picks it up before it's despawned
+            if merged {
+                stack.count = 0;
+            }
+
+            if let Ok((_, mut drop, _)) = query.get_mut(entity) {
+                if drop.stack != stack {
+                    drop.stack = stack;
+                }
+            }
+        }
+    }
+
+    fn pick_up(
+        mut commands: Commands,
+        mut players: Query<(&Transform, &mut Inventory)>,
+        mut drops: Query<(Entity, &mut ItemDrop, &Transform), Without<Inventory>>,
+    ) {
+        for (player, mut inventory) in &mut players {
+            for (entity, mut drop, transform) in &mut drops {
+                if drop.stack.count == 0
+                    || drop.age < PICKUP_DELAY
+                    || transform.translation.distance(player.translation) > PICKUP_RADIUS
+                {
+                    continue;
+                }
+
+                let left = inventory.add(drop.stack.block, drop.stack.count);
+                if left == drop.stack.count {
+                    continue;
+                }
+
+                drop.stack.count = left;
+                if left == 0 {
+                    commands.entity(entity).despawn_recursive();
+                }
+            }
+        }
+    }
+
+    fn bob(drops: Query<&ItemDrop>, mut models: Query<(&Parent, &mut Transform), With<DropModel>>) {
+        for (parent, mut transform) in &mut models {
+            let Ok(drop) = drops.get(parent.get()) else { continue; };
+
+            transform.translation.y = 0.1 + (drop.age * 2.).sin() * 0.1;
+            transform.rotation = Quat::from_rotation_y(drop.age);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flat_world() -> World {
+        World::with_chunks([IVec3::ZERO], |pos| {
+            if pos.y == 0 {
+                Block::Dirt
+            } else {
+                Block::Air
+            }
+        })
+    }
+
+    fn stack(block: Block, count: u32) -> ItemStack {
+        ItemStack { block, count }
+    }
+
+    #[test]
+    fn drops_land_on_the_ground() {
+        let world = flat_world();
+        let mut translation = Vec3::new(4.5, 8.5, 4.5);
+        let mut velocity = POP_SPEED;
+
+        for _ in 0..120 {
+            fall(&world, &mut translation, &mut velocity, 1. / 60.);
+        }
+        assert_eq!(translation, Vec3::new(4.5, 1. + DROP_SIZE / 2., 4.5));
+        assert_eq!(velocity, 0.);
+
+        // Unloaded chunks are solid
+        let mut translation = Vec3::new(-4.5, 8.5, 4.5);
+        fall(&world, &mut translation, &mut velocity, 1. / 60.);
+        assert_eq!(translation, Vec3::new(-4.5, 8.5, 4.5));
+    }
+
+    #[test]
+    fn drops_merge_and_get_picked_up() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugins(DropsPlugin)
+            .insert_resource(flat_world());
+
+        let player = app
+            .world
+            .spawn((Transform::from_xyz(12., 2., 12.), Inventory::default()))
+            .id();
+
+        for (x, block, count) in [
+            (2.5, Block::Dirt, 3),
+            (3., Block::Dirt, 2),
+            (3.2, Block::Stone, 1),
+            (3.4, Block::Dirt, MAX_STACK),
+        ] {
+            app.world.spawn((
+                ItemDrop {
+                    age: PICKUP_DELAY,
+                    ..ItemDrop::new(stack(block, count))
+                },
+                TransformBundle::from_transform(Transform::from_xyz(x, 1.5, 2.5)),
+            ));
+        }
+
+        app.update();
+        let mut query = app.world.query::<&ItemDrop>();
+        let mut stacks = query
+            .iter(&app.world)
+            .map(|drop| (drop.stack.block.name(), drop.stack.count))
+            .collect::<Vec<_>>();
+        stacks.sort();
+        // The full stack can't be merged with
+        assert_eq!(stacks, [("dirt", 5), ("dirt", MAX_STACK), ("stone", 1)]);
+
+        app.world.get_mut::<Transform>(player).unwrap().translation = Vec3::new(3., 2., 2.5);
+        app.update();
+        assert_eq!(query.iter(&app.world).count(), 0);
+
+        let inventory = app.world.get::<Inventory>(player).unwrap();
+        assert_eq!(inventory.count(Block::Dirt), MAX_STACK + 5);
+        assert_eq!(inventory.count(Block::Stone), 1);
+    }
+
+    #[test]
+    fn broken_blocks_drop() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugins(DropsPlugin)
+            .insert_resource(flat_world());
+
+        let edited = |pos, old, new| BlockEdited { pos, old, new };
+        app.world
+            .send_event(edited(IVec3::new(2, 0, 2), Block::Dirt, Block::Air));
+        // Placing a block or swapping one for another drops nothing
+        app.world
+            .send_event(edited(IVec3::new(4, 1, 4), Block::Air, Block::Stone));
+        app.world
+            .send_event(edited(IVec3::new(6, 0, 6), Block::Dirt, Block::Stone));
+        app.update();
+
+        let mut query = app.world.query::<(&ItemDrop, &Transform)>();
+        let drops = query
+            .iter(&app.world)
+            .map(|(drop, transform)| (drop.stack, transform.translation))
+            .collect::<Vec<_>>();
+        assert_eq!(drops, [(stack(Block::Dirt, 1), Vec3::new(2.5, 0.5, 2.5))]);
+    }
+}
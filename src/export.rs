@@ -0,0 +1,577 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use bevy::{asset::FileAssetIo, prelude::*, render::mesh::VertexAttributeValues};
+use bevy_egui::{egui, EguiContexts};
+use serde_json::json;
+
+use crate::{
+    block::Block,
+    block_material::{missing_texture, texture_layers, TEXTURE_SIZE},
+    chunk::{TerrainGen, CHUNK_SIZE_I32},
+    light,
+    mesh::ATTRIBUTE_LAYER,
+    save::SaveDir,
+    world::{split_pos, World},
+    WorldConfig,
+};
+
+/// Adds a window for exporting a region of loaded chunks as an OBJ or glTF
+pub struct ExportPlugin;
+
+impl Plugin for ExportPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ExportState>()
+            .add_systems(Update, Self::export_window);
+    }
+}
+
+#[derive(Resource)]
+pub struct ExportState {
+    /// The first corner of the region, in chunks
+    pub from: IVec3,
+    /// The opposite corner of the region, in chunks
+    pub to: IVec3,
+    pub path: String,
+    /// The outcome of the last export
+    status: Option<String>,
+}
+
+impl Default for ExportState {
+    fn default() -> Self {
+        Self {
+            from: IVec3::new(-2, 0, -2),
+            to: IVec3::new(2, 0, 2),
+            path: "exports/terrain.gltf".to_string(),
+            status: None,
+        }
+    }
+}
+
+/// The merged meshes of a region of chunks, in world space
+#[derive(Default)]
+pub struct RegionMesh {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    /// Already mapped into the atlas built by `build_atlas`
+    pub uvs: Vec<[f32; 2]>,
+    /// The baked light
+    pub colours: Vec<[f32; 4]>,
+    pub indices: Vec<u32>,
+}
+
+impl RegionMesh {
+    /// Meshes every loaded chunk between the corners `from` and `to` at full detail.
+    ///
+    /// Faces between two chunks in the region are dropped,
+    /// as they're only there to hide cracks between LODs.
+    pub fn build(world: &World, from: IVec3, to: IVec3) -> Self {
+        let (min, max) = (from.min(to), from.max(to));
+        let in_region = |chunk_id: IVec3| chunk_id.cmpge(min).all() && chunk_id.cmple(max).all();
+
+        let mut region = Self::default();
+
+        for x in min.x..=max.x {
+            for y in min.y..=max.y {
+                for z in min.z..=max.z {
+                    let chunk_id = IVec3::new(x, y, z);
+                    if !world.chunks.contains_key(&chunk_id) {
+                        continue;
+                    }
+
+                    let mesh = world.build_chunk_mesh_at(chunk_id, 0);
+                    region.append(&mesh, chunk_id * CHUNK_SIZE_I32, |pos| {
+                        in_region(split_pos(pos).0)
+                            && world.get_block(pos).is_some_and(|block| block.is_opaque())
+                    });
+                }
+            }
+        }
+
+        region
+    }
+
+    /// Adds a chunk mesh built by `IncompleteMesh`, which has 4 vertices and 6 indices per face.
+    ///
+    /// Faces whose air side is `hidden` are skipped.
+    fn append(&mut self, mesh: &Mesh, origin: IVec3, hidden: impl Fn(IVec3) -> bool) {
+        let (
+            Some(VertexAttributeValues::Float32x3(positions)),
+            Some(VertexAttributeValues::Float32x3(normals)),
+            Some(VertexAttributeValues::Float32x2(uvs)),
+            Some(VertexAttributeValues::Float32x4(colours)),
+            Some(VertexAttributeValues::Uint32(layers)),
+            Some(indices),
+        ) = (
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION),
+            mesh.attribute(Mesh::ATTRIBUTE_NORMAL),
+            mesh.attribute(Mesh::ATTRIBUTE_UV_0),
+            mesh.attribute(Mesh::ATTRIBUTE_COLOR),
+            mesh.attribute(ATTRIBUTE_LAYER),
+            mesh.indices(),
+        ) else {
+            panic!("Chunk mesh is missing attributes");
+        };
+
+        let indices = indices.iter().collect::<Vec<_>>();
+        let offset = origin.as_vec3();
+        let layer_count = texture_layers() as f32;
+
+        for face in 0..positions.len() / 4 {
+            let verts = face * 4..face * 4 + 4;
+
+            let centre = verts
+                .clone()
+                .map(|i| Vec3::from(positions[i]))
+                .sum::<Vec3>()
+                / 4.;
+            let air = (offset + centre + Vec3::from(normals[face * 4]) * 0.5)
+                .floor()
+                .as_ivec3();
+            if hidden(air) {
+                continue;
+            }
+
+            let base = self.positions.len() as u32;
+            for &i in &indices[face * 6..face * 6 + 6] {
+                self.indices.push(base + (i - face * 4) as u32);
+            }
+
+            for i in verts {
+                self.positions
+                    .push((offset + Vec3::from(positions[i])).to_array());
+                self.normals.push(normals[i]);
+                // The atlas is a strip with one tile per layer
+                self.uvs
+                    .push([(layers[i] as f32 + uvs[i][0]) / layer_count, uvs[i][1]]);
+                self.colours.push(colours[i]);
+            }
+        }
+    }
+
+    /// Writes the mesh as an OBJ, referencing the material `material` from `mtl_file`.
+    ///
+    /// The baked light is written after each position,
+    /// which Blender and most other tools read as vertex colours.
+    pub fn write_obj(
+        &self,
+        out: &mut impl Write,
+        mtl_file: &str,
+        material: &str,
+    ) -> io::Result<()> {
+        writeln!(out, "mtllib {}", mtl_file)?;
+        writeln!(out, "o terrain")?;
+
+        for (pos, colour) in self.positions.iter().zip(&self.colours) {
+            writeln!(
+                out,
+                "v {} {} {} {} {} {}",
+                pos[0], pos[1], pos[2], colour[0], colour[1], colour[2]
+            )?;
+        }
+        // OBJ uvs start from the bottom of the texture
+        for uv in &self.uvs {
+            writeln!(out, "vt {} {}", uv[0], 1. - uv[1])?;
+        }
+        for normal in &self.normals {
+            writeln!(out, "vn {} {} {}", normal[0], normal[1], normal[2])?;
+        }
+
+        writeln!(out, "usemtl {}", material)?;
+        for tri in self.indices.chunks(3) {
+            let [a, b, c] = [tri[0] + 1, tri[1] + 1, tri[2] + 1];
+            writeln!(out, "f {a}/{a}/{a} {b}/{b}/{b} {c}/{c}/{c}")?;
+        }
+
+        Ok(())
+    }
+
+    /// Builds the binary buffer and the glTF json referencing it and the atlas
+    pub fn to_gltf(&self, bin_file: &str, atlas_file: &str) -> (serde_json::Value, Vec<u8>) {
+        let mut buffer = vec![];
+        let mut views = vec![];
+
+        let mut push_view = |bytes: Vec<u8>, target: u32| {
+            views.push(json!({
+                "buffer": 0,
+                "byteOffset": buffer.len(),
+                "byteLength": bytes.len(),
+                "target": target,
+            }));
+            buffer.extend(bytes);
+            views.len() - 1
+        };
+
+        fn floats<const N: usize>(values: &[[f32; N]]) -> Vec<u8> {
+            values
+                .iter()
+                .flatten()
+                .flat_map(|f| f.to_le_bytes())
+                .collect()
+        }
+
+        const ARRAY_BUFFER: u32 = 34962;
+        const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+        const FLOAT: u32 = 5126;
+        const UNSIGNED_INT: u32 = 5125;
+        const NEAREST: u32 = 9728;
+        const CLAMP_TO_EDGE: u32 = 33071;
+
+        let positions = push_view(floats(&self.positions), ARRAY_BUFFER);
+        let normals = push_view(floats(&self.normals), ARRAY_BUFFER);
+        let uvs = push_view(floats(&self.uvs), ARRAY_BUFFER);
+        let colours = push_view(floats(&self.colours), ARRAY_BUFFER);
+        let indices = push_view(
+            self.indices.iter().flat_map(|i| i.to_le_bytes()).collect(),
+            ELEMENT_ARRAY_BUFFER,
+        );
+
+        // Positions need bounds
+        let (min, max) = self.positions.iter().fold(
+            (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+            |(min, max), pos| (min.min(Vec3::from(*pos)), max.max(Vec3::from(*pos))),
+        );
+
+        let count = self.positions.len();
+        let gltf = json!({
+            "asset": { "version": "2.0", "generator": "bevy_craft" },
+            "scene": 0,
+            "scenes": [{ "nodes": [0] }],
+            "nodes": [{ "name": "terrain", "mesh": 0 }],
+            "meshes": [{
+                "primitives": [{
+                    "attributes": {
+                        "POSITION": 0,
+                        "NORMAL": 1,
+                        "TEXCOORD_0": 2,
+                        "COLOR_0": 3,
+                    },
+                    "indices": 4,
+                    "material": 0,
+                }],
+            }],
+            "materials": [{
+                "name": "blocks",
+                "pbrMetallicRoughness": {
+                    "baseColorTexture": { "index": 0 },
+                    "metallicFactor": 0.0,
+                    "roughnessFactor": 1.0,
+                },
+            }],
+            "textures": [{ "source": 0, "sampler": 0 }],
+            "images": [{ "uri": atlas_file }],
+            "samplers": [{
+                "magFilter": NEAREST,
+                "minFilter": NEAREST,
+                "wrapS": CLAMP_TO_EDGE,
+                "wrapT": CLAMP_TO_EDGE,
+            }],
+            "accessors": [
+                {
+                    "bufferView": positions,
+                    "componentType": FLOAT,
+                    "count": count,
+                    "type": "VEC3",
+                    "min": min.to_array(),
+                    "max": max.to_array(),
+                },
+                { "bufferView": normals, "componentType": FLOAT, "count": count, "type": "VEC3" },
+                { "bufferView": uvs, "componentType": FLOAT, "count": count, "type": "VEC2" },
+                { "bufferView": colours, "componentType": FLOAT, "count": count, "type": "VEC4" },
+                {
+                    "bufferView": indices,
+                    "componentType": UNSIGNED_INT,
+                    "count": self.indices.len(),
+                    "type": "SCALAR",
+                },
+            ],
+            "bufferViews": views,
+            "buffers": [{ "uri": bin_file, "byteLength": buffer.len() }],
+        });
+
+        (gltf, buffer)
+    }
+}
+
+/// Lays every block texture out in a strip, one tile per texture array layer.
+///
+/// Textures that can't be read are replaced with the missing texture.
+pub fn build_atlas() -> image::RgbaImage {
+    let layers = texture_layers();
+    let missing = image::RgbaImage::from_raw(TEXTURE_SIZE, TEXTURE_SIZE, missing_texture())
+        .expect("Missing texture is the wrong size");
+
+    let mut atlas = image::RgbaImage::new(TEXTURE_SIZE * layers, TEXTURE_SIZE);
+    for layer in 0..layers {
+        image::imageops::replace(&mut atlas, &missing, (layer * TEXTURE_SIZE) as i64, 0);
+    }
+
+    let blocks_dir = FileAssetIo::get_base_path().join("assets").join("blocks");
+
+    for block in Block::ALL {
+        let Some(name) = block.texture() else { continue; };
+        let path = blocks_dir.join(format!("{}.png", name));
+
+        match image::open(&path) {
+            Ok(texture) if texture.width() == TEXTURE_SIZE && texture.height() == TEXTURE_SIZE => {
+                let x = (block.layer() * TEXTURE_SIZE) as i64;
+                image::imageops::replace(&mut atlas, &texture.to_rgba8(), x, 0);
+            }
+            Ok(_) => warn!("Texture for {:?} is the wrong size", block),
+            Err(err) => warn!("Failed to read {:?}: {}", path, err),
+        }
+    }
+
+    atlas
+}
+
+/// Exports a region of chunks to `path`, choosing OBJ or glTF from its extension.
+///
+/// The atlas, and the MTL or binary buffer, are written alongside it.
+pub fn export_region(world: &World, from: IVec3, to: IVec3, path: &Path) -> io::Result<()> {
+    let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+    if extension != "obj" && extension != "gltf" {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Export path must end in .obj or .gltf",
+        ));
+    }
+
+    let region = RegionMesh::build(world, from, to);
+    if region.indices.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "No loaded blocks in the region",
+        ));
+    }
+
+    let stem = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("terrain");
+    let sibling = |suffix: &str| {
+        (
+            format!("{}{}", stem, suffix),
+            path.with_file_name(format!("{}{}", stem, suffix)),
+        )
+    };
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let (atlas_file, atlas_path) = sibling("_atlas.png");
+    build_atlas().save(&atlas_path).map_err(io::Error::other)?;
+
+    if extension == "obj" {
+        let (mtl_file, mtl_path) = sibling(".mtl");
+        fs::write(
+            mtl_path,
+            format!("newmtl blocks\nKd 1 1 1\nmap_Kd {}\n", atlas_file),
+        )?;
+
+        let mut out = io::BufWriter::new(fs::File::create(path)?);
+        region.write_obj(&mut out, &mtl_file, "blocks")?;
+        out.flush()
+    } else {
+        let (bin_file, bin_path) = sibling(".bin");
+        let (gltf, buffer) = region.to_gltf(&bin_file, &atlas_file);
+
+        fs::write(bin_path, buffer)?;
+        fs::write(path, serde_json::to_string_pretty(&gltf)?)
+    }
+}
+
+/// Parses a chunk id written as `x,y,z`
+fn parse_chunk_id(text: &str) -> Option<IVec3> {
+    let mut parts = text.split(',').map(|part| part.trim().parse().ok());
+    let id = IVec3::new(parts.next()??, parts.next()??, parts.next()??);
+    parts.next().is_none().then_some(id)
+}
+
+/// Runs `bevy_craft export <path> <from> <to> [save dir] [seed]` without opening a window,
+/// where the corners are chunk ids written as `x,y,z`.
+///
+/// Chunks are loaded from the save if they exist, otherwise they're generated from the seed,
+/// which should be the one the world was played with as it isn't saved.
+pub fn run_cli(args: &[String]) -> Result<(), String> {
+    let usage =
+        "Usage: bevy_craft export <path.obj|path.gltf> <from x,y,z> <to x,y,z> [save dir] [seed]";
+
+    let [path, from, to, rest @ ..] = args else { return Err(usage.to_string()); };
+    let (Some(from), Some(to)) = (parse_chunk_id(from), parse_chunk_id(to)) else {
+        return Err(usage.to_string());
+    };
+    let (save_dir, seed) = match rest {
+        [] => (SaveDir::default(), None),
+        [dir] => (SaveDir(PathBuf::from(dir)), None),
+        [dir, seed] => (SaveDir(PathBuf::from(dir)), Some(seed)),
+        _ => return Err(usage.to_string()),
+    };
+    let seed = match seed {
+        Some(seed) => seed
+            .parse()
+            .map_err(|err| format!("Invalid seed {:?}: {}", seed, err))?,
+        None => WorldConfig::default().seed,
+    };
+
+    let terrain_gen = TerrainGen::new(seed);
+    let mut world = World::new();
+
+    // Load a border around the region so the edges are lit properly
+    let (min, max) = (from.min(to) - IVec3::ONE, from.max(to) + IVec3::ONE);
+    let mut chunk_ids = vec![];
+    for x in min.x..=max.x {
+        for y in min.y..=max.y {
+            for z in min.z..=max.z {
+                let chunk_id = IVec3::new(x, y, z);
+                let chunk = save_dir.load_or_generate(chunk_id, &terrain_gen);
+                world.chunks.insert(chunk_id, chunk);
+                chunk_ids.push(chunk_id);
+            }
+        }
+    }
+    light::relight_chunks(&mut world, &chunk_ids);
+
+    export_region(&world, from, to, Path::new(path)).map_err(|err| err.to_string())?;
+    println!("Exported chunks {} to {} to {}", from, to, path);
+
+    Ok(())
+}
+
+impl ExportPlugin {
+    pub fn export_window(mut ctx: EguiContexts, mut state: ResMut<ExportState>, world: Res<World>) {
+        egui::Window::new("Export").show(ctx.ctx_mut(), |ui| {
+            egui::Grid::new("export_grid")
+                .num_columns(4)
+                .show(ui, |ui| {
+                    ui.label("From chunk");
+                    ui.add(egui::DragValue::new(&mut state.from.x));
+                    ui.add(egui::DragValue::new(&mut state.from.y));
+                    ui.add(egui::DragValue::new(&mut state.from.z));
+                    ui.end_row();
+
+                    ui.label("To chunk");
+                    ui.add(egui::DragValue::new(&mut state.to.x));
+                    ui.add(egui::DragValue::new(&mut state.to.y));
+                    ui.add(egui::DragValue::new(&mut state.to.z));
+                    ui.end_row();
+                });
+
+            ui.horizontal(|ui| {
+                ui.label("Path");
+                ui.text_edit_singleline(&mut state.path);
+            });
+
+            if ui.button("Export").clicked() {
+                let path = PathBuf::from(&state.path);
+
+                state.status = Some(match export_region(&world, state.from, state.to, &path) {
+                    Ok(()) => format!("Exported to {:?}", path),
+                    Err(err) => format!("Export failed: {}", err),
+                });
+            }
+
+            if let Some(status) = &state.status {
+                ui.label(status);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::CHUNK_SIZE;
+
+    fn solid_world(chunk_ids: &[IVec3]) -> World {
+        World::with_chunks(chunk_ids.iter().copied(), |_| Block::Stone)
+    }
+
+    #[test]
+    fn merged_chunks_drop_shared_faces() {
+        let world = solid_world(&[IVec3::ZERO, IVec3::X]);
+        let region = RegionMesh::build(&world, IVec3::ZERO, IVec3::X);
+
+        // The outside of a 2x1x1 chunk box
+        let size = CHUNK_SIZE * CHUNK_SIZE;
+        let faces = size * 2 * 4 + size * 2;
+        assert_eq!(region.positions.len(), faces * 4);
+        assert_eq!(region.indices.len(), faces * 6);
+        assert!(region
+            .indices
+            .iter()
+            .all(|i| (*i as usize) < region.positions.len()));
+
+        let max_x = region
+            .positions
+            .iter()
+            .map(|pos| pos[0])
+            .fold(f32::MIN, f32::max);
+        assert_eq!(max_x, (CHUNK_SIZE * 2) as f32);
+    }
+
+    #[test]
+    fn faces_next_to_other_regions_are_kept() {
+        let world = solid_world(&[IVec3::ZERO, IVec3::X]);
+        let region = RegionMesh::build(&world, IVec3::ZERO, IVec3::ZERO);
+
+        let faces = CHUNK_SIZE * CHUNK_SIZE * 6;
+        assert_eq!(region.positions.len(), faces * 4);
+    }
+
+    #[test]
+    fn obj_indices_are_one_based() {
+        let world = solid_world(&[IVec3::ZERO]);
+        let region = RegionMesh::build(&world, IVec3::ZERO, IVec3::ZERO);
+
+        let mut obj = vec![];
+        region.write_obj(&mut obj, "terrain.mtl", "blocks").unwrap();
+        let obj = String::from_utf8(obj).unwrap();
+
+        let vertex_count = obj.lines().filter(|line| line.starts_with("v ")).count();
+        let face_indices = obj
+            .lines()
+            .filter_map(|line| line.strip_prefix("f "))
+            .flat_map(|line| line.split(' '))
+            .map(|corner| corner.split('/').next().unwrap().parse::<usize>().unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(vertex_count, region.positions.len());
+        assert_eq!(face_indices.len(), region.indices.len());
+        assert!(face_indices.iter().all(|i| (1..=vertex_count).contains(i)));
+    }
+
+    #[test]
+    fn cli_generates_missing_chunks_from_the_seed() {
+        let dir = std::env::temp_dir().join("bevy_craft_export_cli_test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join("region.obj");
+        let export = |seed: Option<&str>| {
+            let args = [
+                path.to_str(),
+                Some("0,0,0"),
+                Some("0,0,0"),
+                dir.to_str(),
+                seed,
+            ]
+            .into_iter()
+            .flatten()
+            .map(String::from)
+            .collect::<Vec<_>>();
+            run_cli(&args).map(|()| fs::read_to_string(&path).unwrap())
+        };
+
+        let default = export(None).unwrap();
+        assert_eq!(export(Some("0")).unwrap(), default);
+        assert_ne!(export(Some("1")).unwrap(), default);
+        assert!(export(Some("one")).is_err());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,230 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+
+use crate::{
+    block::Block,
+    console::not_typing,
+    world::{world_mesh_gen, World},
+};
+
+/// Undoes edits with Ctrl+Z, and redoes them with Ctrl+Y or Ctrl+Shift+Z
+pub struct HistoryPlugin;
+
+impl Plugin for HistoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<EditHistory>().add_systems(
+            Update,
+            Self::undo_keys.run_if(not_typing).before(world_mesh_gen),
+        );
+    }
+}
+
+/// The blocks changed by one operation, as their position, old block, and new block
+pub struct Edit {
+    changes: Vec<(IVec3, Block, Block)>,
+}
+
+/// A bounded journal of edits that can be undone and redone.
+///
+/// Edits are replayed through `World::set_blocks`, so any in unloaded chunks are skipped.
+#[derive(Resource)]
+pub struct EditHistory {
+    undo: VecDeque<Edit>,
+    redo: Vec<Edit>,
+    /// The most edits kept, the oldest are forgotten first
+    pub limit: usize,
+}
+
+impl Default for EditHistory {
+    fn default() -> Self {
+        Self::new(100)
+    }
+}
+
+impl EditHistory {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: vec![],
+            limit,
+        }
+    }
+
+    /// Records an operation that's already been applied to `world`,
+    /// from the old blocks returned by `World::set_blocks`
+    pub fn record(&mut self, world: &World, changed: Vec<(IVec3, Block)>) {
+        if changed.is_empty() {
+            return;
+        }
+
+        let changes = changed
+            .into_iter()
+            .map(|(pos, old)| (pos, old, world.get_block(pos).unwrap_or(old)))
+            .collect();
+
+        self.undo.push_back(Edit { changes });
+        self.redo.clear();
+
+        while self.undo.len() > self.limit {
+            self.undo.pop_front();
+        }
+    }
+
+    /// Returns false if there was nothing to undo
+    pub fn undo(&mut self, world: &mut World) -> bool {
+        let Some(edit) = self.undo.pop_back() else { return false; };
+
+        // Backwards, so blocks changed twice end up as they first were
+        world.set_blocks(edit.changes.iter().rev().map(|(pos, old, _)| (*pos, *old)));
+        self.redo.push(edit);
+        true
+    }
+
+    /// Returns false if there was nothing to redo
+    pub fn redo(&mut self, world: &mut World) -> bool {
+        let Some(edit) = self.redo.pop() else { return false; };
+
+        world.set_blocks(edit.changes.iter().map(|(pos, _, new)| (*pos, *new)));
+        self.undo.push_back(edit);
+        true
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+}
+
+impl HistoryPlugin {
+    pub fn undo_keys(
+        keys: Res<Input<KeyCode>>,
+        mut history: ResMut<EditHistory>,
+        mut world: ResMut<World>,
+    ) {
+        if !keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+            return;
+        }
+
+        let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+
+        if keys.just_pressed(KeyCode::Y) || (shift && keys.just_pressed(KeyCode::Z)) {
+            history.redo(&mut world);
+        } else if keys.just_pressed(KeyCode::Z) {
+            history.undo(&mut world);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        chunk::{TerrainGen, CHUNK_SIZE_I32},
+        world_edit::{fill, Selection},
+    };
+
+    fn generated_world() -> World {
+        let terrain_gen = TerrainGen::default();
+        let chunk_ids = (-1..=1).flat_map(|x| [-1, 0].map(|z| IVec3::new(x, 0, z)));
+        World::with_chunks(chunk_ids, |pos| terrain_gen.block_at(pos))
+    }
+
+    fn snapshot(world: &World, selection: Selection) -> Vec<Option<Block>> {
+        selection
+            .positions()
+            .map(|pos| world.get_block(pos))
+            .collect()
+    }
+
+    #[test]
+    fn undo_and_redo_across_chunk_borders() {
+        let mut world = generated_world();
+        let mut history = EditHistory::default();
+
+        // Spans the corners of four chunks
+        let selection = Selection::new(IVec3::new(-3, 2, -3), IVec3::new(3, 12, 3));
+        let before = snapshot(&world, selection);
+
+        let changed = fill(&mut world, selection, Block::Lava);
+        history.record(&world, changed);
+        let after = snapshot(&world, selection);
+        assert_ne!(before, after);
+
+        world.invalid_meshes.clear();
+        assert!(history.undo(&mut world));
+        assert_eq!(snapshot(&world, selection), before);
+        for chunk_id in [IVec3::new(-1, 0, -1), IVec3::new(0, 0, 0)] {
+            assert!(world.invalid_meshes.contains(&chunk_id));
+        }
+
+        assert!(history.redo(&mut world));
+        assert_eq!(snapshot(&world, selection), after);
+        assert!(!history.can_redo());
+    }
+
+    #[test]
+    fn undo_restores_light() {
+        let mut world = generated_world();
+        let chunk_ids = world.chunks.keys().copied().collect::<Vec<_>>();
+        crate::light::relight_chunks(&mut world, &chunk_ids);
+        let pos = IVec3::new(CHUNK_SIZE_I32 - 1, 14, 0);
+        let before = crate::light::level_at(&world, pos);
+
+        let mut history = EditHistory::default();
+        let changed = world.set_blocks([(pos, Block::Torch)]);
+        history.record(&world, changed);
+        history.undo(&mut world);
+
+        assert_eq!(world.get_block(pos), Some(Block::Air));
+        assert_eq!(crate::light::level_at(&world, pos), before);
+    }
+
+    #[test]
+    fn blocks_changed_twice_undo_to_the_first_block() {
+        let mut world = generated_world();
+        let mut history = EditHistory::default();
+        let pos = IVec3::new(0, 14, 0);
+
+        let changed = world.set_blocks([(pos, Block::Stone), (pos, Block::Torch)]);
+        history.record(&world, changed);
+        history.undo(&mut world);
+
+        assert_eq!(world.get_block(pos), Some(Block::Air));
+    }
+
+    #[test]
+    fn history_is_bounded() {
+        let mut world = generated_world();
+        let mut history = EditHistory::new(3);
+
+        for x in 0..6 {
+            let changed = world.set_blocks([(IVec3::new(x, 15, 0), Block::Torch)]);
+            history.record(&world, changed);
+        }
+
+        let mut undone = 0;
+        while history.undo(&mut world) {
+            undone += 1;
+        }
+        assert_eq!(undone, 3);
+    }
+
+    #[test]
+    fn recording_clears_redo() {
+        let mut world = generated_world();
+        let mut history = EditHistory::default();
+
+        let changed = world.set_blocks([(IVec3::new(0, 15, 0), Block::Torch)]);
+        history.record(&world, changed);
+        history.undo(&mut world);
+        assert!(history.can_redo());
+
+        let changed = world.set_blocks([(IVec3::new(1, 15, 0), Block::Torch)]);
+        history.record(&world, changed);
+        assert!(!history.can_redo());
+    }
+}
@@ -0,0 +1,334 @@
+use bevy::{input::mouse::MouseWheel, prelude::*};
+
+use crate::{block::Block, camera::FlyCam, console::not_typing};
+
+/// The most of one block a slot can hold
+pub const MAX_STACK: u32 = 64;
+/// The slots shown at the bottom of the screen, which are the first slots of the inventory
+pub const HOTBAR_SLOTS: usize = 9;
+pub const INVENTORY_SLOTS: usize = 36;
+
+const SLOT_SIZE: f32 = 48.;
+
+/// Some number of the same block
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ItemStack {
+    pub block: Block,
+    pub count: u32,
+}
+
+/// The blocks a player is carrying, which they pick up from drops and use up placing them
+#[derive(Component, Clone, Debug)]
+pub struct Inventory {
+    pub slots: [Option<ItemStack>; INVENTORY_SLOTS],
+    /// The hotbar slot of the block that gets placed
+    pub selected: usize,
+}
+
+impl Default for Inventory {
+    fn default() -> Self {
+        Self {
+            slots: [None; INVENTORY_SLOTS],
+            selected: 0,
+        }
+    }
+}
+
+impl Inventory {
+    /// What players start out with, a stack of torches
+    pub fn starting_kit() -> Self {
+        let mut inventory = Self::default();
+        inventory.add(Block::Torch, MAX_STACK);
+        inventory
+    }
+
+    /// Adds `count` of `block`, topping up stacks of it before starting new ones.
+    ///
+    /// Returns how many didn't fit.
+    pub fn add(&mut self, block: Block, mut count: u32) -> u32 {
+        for stack in self.slots.iter_mut().flatten() {
+            if stack.block == block {
+                let added = count.min(MAX_STACK - stack.count);
+                stack.count += added;
+                count -= added;
+            }
+        }
+
+        for slot in &mut self.slots {
+            if count == 0 {
+                break;
+            }
+
+            if slot.is_none() {
+                let added = count.min(MAX_STACK);
+                *slot = Some(ItemStack {
+                    block,
+                    count: added,
+                });
+                count -= added;
+            }
+        }
+
+        count
+    }
+
+    /// How many of `block` there are across every slot
+    pub fn count(&self, block: Block) -> u32 {
+        self.slots
+            .iter()
+            .flatten()
+            .filter(|stack| stack.block == block)
+            .map(|stack| stack.count)
+            .sum()
+    }
+
+    /// Takes `count` of `block`, from the last slots first so the hotbar is left alone where it can be.
+    ///
+    /// Takes nothing and returns false if there aren't enough.
+    pub fn remove(&mut self, block: Block, mut count: u32) -> bool {
+        if self.count(block) < count {
+            return false;
+        }
+
+        for slot in self.slots.iter_mut().rev() {
+            let Some(stack) = slot.as_mut().filter(|stack| stack.block == block) else { continue; };
+
+            let taken = count.min(stack.count);
+            stack.count -= taken;
+            count -= taken;
+            if stack.count == 0 {
+                *slot = None;
+            }
+        }
+
+        true
+    }
+
+    pub fn hotbar(&self) -> &[Option<ItemStack>] {
+        &self.slots[..HOTBAR_SLOTS]
+    }
+
+    pub fn selected(&self) -> Option<ItemStack> {
+        self.slots[self.selected]
+    }
+
+    /// Takes one of the selected block, emptying the slot when it's the last one
+    pub fn take_selected(&mut self) -> Option<Block> {
+        let slot = &mut self.slots[self.selected];
+        let stack = slot.as_mut()?;
+        let block = stack.block;
+
+        stack.count -= 1;
+        if stack.count == 0 {
+            *slot = None;
+        }
+
+        Some(block)
+    }
+
+    /// Takes one of a block that's been placed, from the selected slot if it holds that block.
+    ///
+    /// Returns false if there wasn't one.
+    pub fn take_placed(&mut self, block: Block) -> bool {
+        if self.selected().is_some_and(|stack| stack.block == block) {
+            self.take_selected().is_some()
+        } else {
+            self.remove(block, 1)
+        }
+    }
+
+    /// Moves the selection along the hotbar, wrapping around at the ends
+    pub fn scroll(&mut self, by: i32) {
+        self.selected = (self.selected as i32 + by).rem_euclid(HOTBAR_SLOTS as i32) as usize;
+    }
+}
+
+/// Shows the camera's hotbar under the crosshair,
+/// picking the selected slot with the scroll wheel and number keys
+pub struct HotbarPlugin;
+
+impl Plugin for HotbarPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, Self::create_hotbar).add_systems(
+            Update,
+            (Self::select.run_if(not_typing), Self::update_hotbar).chain(),
+        );
+    }
+}
+
+#[derive(Component)]
+struct HotbarSlot(usize);
+
+#[derive(Component)]
+struct HotbarCount(usize);
+
+impl HotbarPlugin {
+    const KEYS: [KeyCode; HOTBAR_SLOTS] = [
+        KeyCode::Key1,
+        KeyCode::Key2,
+        KeyCode::Key3,
+        KeyCode::Key4,
+        KeyCode::Key5,
+        KeyCode::Key6,
+        KeyCode::Key7,
+        KeyCode::Key8,
+        KeyCode::Key9,
+    ];
+
+    fn create_hotbar(mut commands: Commands) {
+        commands
+            .spawn(NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.),
+                    height: Val::Percent(100.),
+                    align_items: AlignItems::End,
+                    justify_content: JustifyContent::Center,
+                    padding: UiRect::bottom(Val::Px(10.)),
+                    ..default()
+                },
+                ..default()
+            })
+            .with_children(|parent| {
+                for slot in 0..HOTBAR_SLOTS {
+                    parent
+                        .spawn((
+                            NodeBundle {
+                                style: Style {
+                                    width: Val::Px(SLOT_SIZE),
+                                    height: Val::Px(SLOT_SIZE),
+                                    margin: UiRect::horizontal(Val::Px(2.)),
+                                    border: UiRect::all(Val::Px(3.)),
+                                    align_items: AlignItems::End,
+                                    justify_content: JustifyContent::End,
+                                    ..default()
+                                },
+                                ..default()
+                            },
+                            HotbarSlot(slot),
+                        ))
+                        .with_children(|parent| {
+                            parent.spawn((
+                                TextBundle::from_section(
+                                    "",
+                                    TextStyle {
+                                        font_size: 18.,
+                                        color: Color::WHITE,
+                                        ..default()
+                                    },
+                                ),
+                                HotbarCount(slot),
+                            ));
+                        });
+                }
+            });
+    }
+
+    fn select(
+        mut query: Query<&mut Inventory, With<FlyCam>>,
+        keys: Res<Input<KeyCode>>,
+        mut wheel: EventReader<MouseWheel>,
+    ) {
+        let Ok(mut inventory) = query.get_single_mut() else { return; };
+
+        for event in wheel.iter() {
+            // Scrolling down moves right, like most games
+            if event.y != 0. {
+                inventory.scroll(-event.y.signum() as i32);
+            }
+        }
+
+        if let Some(slot) = Self::KEYS.iter().position(|key| keys.just_pressed(*key)) {
+            inventory.selected = slot;
+        }
+    }
+
+    fn update_hotbar(
+        query: Query<&Inventory, (With<FlyCam>, Changed<Inventory>)>,
+        mut slots: Query<(&HotbarSlot, &mut BackgroundColor, &mut BorderColor)>,
+        mut counts: Query<(&HotbarCount, &mut Text)>,
+    ) {
+        let Ok(inventory) = query.get_single() else { return; };
+        let hotbar = inventory.hotbar();
+
+        for (slot, mut background, mut border) in &mut slots {
+            let colour = hotbar[slot.0]
+                .and_then(|stack| stack.block.colour())
+                .map_or(Color::rgba(0., 0., 0., 0.4), |[r, g, b]| {
+                    Color::rgb_u8(r, g, b)
+                });
+
+            *background = colour.into();
+            *border = if slot.0 == inventory.selected {
+                Color::WHITE
+            } else {
+                Color::DARK_GRAY
+            }
+            .into();
+        }
+
+        for (count, mut text) in &mut counts {
+            text.sections[0].value =
+                hotbar[count.0].map_or(String::new(), |stack| stack.count.to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stacks_fill_up_before_new_ones() {
+        let mut inventory = Inventory::default();
+
+        assert_eq!(inventory.add(Block::Dirt, 10), 0);
+        assert_eq!(inventory.add(Block::Stone, 1), 0);
+        assert_eq!(inventory.add(Block::Dirt, 60), 0);
+
+        assert_eq!(
+            inventory.hotbar()[..3],
+            [
+                Some(ItemStack {
+                    block: Block::Dirt,
+                    count: MAX_STACK
+                }),
+                Some(ItemStack {
+                    block: Block::Stone,
+                    count: 1
+                }),
+                Some(ItemStack {
+                    block: Block::Dirt,
+                    count: 6
+                }),
+            ]
+        );
+
+        // Only 33 slots are left
+        assert_eq!(inventory.add(Block::Torch, 33 * MAX_STACK + 5), 5);
+        assert_eq!(inventory.add(Block::Dirt, MAX_STACK), 6);
+    }
+
+    #[test]
+    fn placing_uses_up_the_selected_stack() {
+        let mut inventory = Inventory::default();
+        inventory.add(Block::Torch, 2);
+        inventory.scroll(-1);
+        assert_eq!(inventory.selected, HOTBAR_SLOTS - 1);
+        assert_eq!(inventory.take_selected(), None);
+
+        inventory.scroll(1);
+        assert_eq!(inventory.take_selected(), Some(Block::Torch));
+        assert_eq!(inventory.take_selected(), Some(Block::Torch));
+        assert_eq!(inventory.take_selected(), None);
+        assert_eq!(inventory.selected(), None);
+
+        // Placed blocks come from the selected slot, or the last ones if it's since changed
+        inventory.add(Block::Dirt, MAX_STACK + 1);
+        assert!(inventory.take_placed(Block::Dirt));
+        assert_eq!(inventory.hotbar()[0].unwrap().count, MAX_STACK - 1);
+        inventory.scroll(2);
+        assert!(inventory.take_placed(Block::Dirt));
+        assert_eq!(inventory.hotbar()[1], None);
+        assert!(!inventory.take_placed(Block::Torch));
+    }
+}
@@ -0,0 +1,213 @@
+pub mod avatar;
+pub mod block;
+pub mod block_material;
+pub mod chat;
+pub mod camera;
+pub mod chunk;
+pub mod console;
+pub mod crafting;
+pub mod culling;
+pub mod custom_diagnostics;
+pub mod drops;
+pub mod export;
+pub mod history;
+pub mod inventory;
+pub mod light;
+pub mod lod;
+pub mod mesh;
+pub mod net;
+pub mod nbt;
+pub mod noise_debug;
+pub mod palette;
+pub mod save;
+pub mod schem;
+pub mod scripting;
+pub mod sculpt;
+pub mod streaming;
+pub mod ticks;
+pub mod time_of_day;
+pub mod vox;
+pub mod world;
+pub mod world_edit;
+
+use std::path::PathBuf;
+
+use bevy::{app::PluginGroupBuilder, prelude::*};
+
+pub use camera::{FlyCam, FlyCamPlugin};
+pub use world::ChunkViewer;
+
+use chunk::TerrainGen;
+use history::EditHistory;
+use lod::LodSettings;
+use save::SaveDir;
+use streaming::ViewDistance;
+use ticks::{tick_blocks, BlockTicked, BlockTicks};
+use world::{apply_block_edits, world_mesh_gen, BlockEdit, World};
+
+/// Settings for `WorldSimPlugin` and `VoxelWorldPlugin`
+#[derive(Clone)]
+pub struct WorldConfig {
+    pub seed: u32,
+    /// Where chunks and level data are saved
+    pub save_dir: PathBuf,
+    pub view_distance: ViewDistance,
+    pub lod: LodSettings,
+    /// The most edits that can be undone
+    pub history_limit: usize,
+    /// Where the Rhai scripts are loaded from
+    pub scripts_dir: PathBuf,
+}
+
+impl Default for WorldConfig {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            save_dir: SaveDir::default().0,
+            view_distance: ViewDistance::default(),
+            lod: LodSettings::default(),
+            history_limit: 100,
+            scripts_dir: scripting::ScriptingPlugin::default().dir,
+        }
+    }
+}
+
+/// Generates, lights, saves and streams chunks around the `ChunkViewer`,
+/// advances the time of day and block ticks, and runs scripts.
+///
+/// Nothing is meshed or drawn, so this works with `MinimalPlugins`.
+#[derive(Default)]
+pub struct WorldSimPlugin {
+    pub config: WorldConfig,
+}
+
+impl Plugin for WorldSimPlugin {
+    fn build(&self, app: &mut App) {
+        let config = self.config.clone();
+
+        // Inserted before the plugins, so they don't use their defaults
+        app.insert_resource(TerrainGen::new(config.seed))
+            .insert_resource(World {
+                ticks: BlockTicks::new(config.seed.into()),
+                ..World::new()
+            })
+            .insert_resource(SaveDir(config.save_dir))
+            .insert_resource(config.view_distance)
+            .add_event::<BlockTicked>()
+            .add_systems(FixedUpdate, tick_blocks)
+            .add_plugins(time_of_day::TimeOfDayPlugin)
+            .add_plugins(save::SavePlugin)
+            .add_plugins(streaming::ChunkStreamingPlugin)
+            .add_plugins(scripting::ScriptingPlugin {
+                dir: config.scripts_dir,
+            });
+    }
+}
+
+/// Everything in `WorldSimPlugin`, along with meshing, LODs, culling, the sky and undo history.
+///
+/// Needs `DefaultPlugins`, with `ImagePlugin::default_nearest()` to keep the block textures sharp.
+#[derive(Default)]
+pub struct VoxelWorldPlugin {
+    pub config: WorldConfig,
+    /// Leaves generating, saving and editing chunks to a server, for use with `net::NetClientPlugin`
+    pub remote: bool,
+}
+
+impl Plugin for VoxelWorldPlugin {
+    fn build(&self, app: &mut App) {
+        let config = self.config.clone();
+
+        app.insert_resource(config.lod.clone())
+            .insert_resource(EditHistory::new(config.history_limit))
+            .add_event::<BlockEdit>()
+            .add_plugins(block_material::BlockMaterialPlugin)
+            .add_plugins(time_of_day::SkyPlugin)
+            .add_plugins(lod::LodPlugin)
+            .add_plugins(culling::OcclusionCullingPlugin)
+            .add_systems(Update, world_mesh_gen);
+
+        if self.remote {
+            // The terrain generator is only used by the debug windows
+            app.insert_resource(TerrainGen::new(config.seed))
+                .insert_resource(World::new())
+                .insert_resource(config.view_distance)
+                .add_plugins(time_of_day::TimeOfDayPlugin);
+        } else {
+            app.add_plugins(WorldSimPlugin { config })
+                .add_plugins(history::HistoryPlugin)
+                .add_systems(Update, apply_block_edits.before(world_mesh_gen));
+        }
+    }
+}
+
+/// Diagnostics and windows for tweaking the terrain and time of day, which need `EguiPlugin`
+pub struct DebugPlugins;
+
+impl PluginGroup for DebugPlugins {
+    fn build(self) -> PluginGroupBuilder {
+        PluginGroupBuilder::start::<Self>()
+            .add(bevy::diagnostic::FrameTimeDiagnosticsPlugin)
+            .add(bevy::asset::diagnostic::AssetCountDiagnosticsPlugin::<Mesh>::default())
+            .add(custom_diagnostics::CustomDiagnosticsPlugin)
+            .add(noise_debug::NoiseDebugPlugin)
+            .add(time_of_day::TimeOfDayWindowPlugin)
+    }
+}
+
+/// The console and tools for importing, exporting and editing the world,
+/// which need `EguiPlugin` and `FlyCamPlugin`
+pub struct EditorPlugins;
+
+impl PluginGroup for EditorPlugins {
+    fn build(self) -> PluginGroupBuilder {
+        PluginGroupBuilder::start::<Self>()
+            .add(console::ConsolePlugin)
+            .add(export::ExportPlugin)
+            .add(vox::VoxImportPlugin)
+            .add(schem::SchematicPlugin)
+            .add(world_edit::WorldEditPlugin)
+            .add(sculpt::SculptPlugin)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn simulates_without_rendering() {
+        let save_dir = std::env::temp_dir().join("bevy_craft_sim_test");
+        let _ = std::fs::remove_dir_all(&save_dir);
+
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins).add_plugins(WorldSimPlugin {
+            config: WorldConfig {
+                save_dir: save_dir.clone(),
+                view_distance: ViewDistance {
+                    chunks: 2,
+                    per_frame: 4,
+                },
+                ..default()
+            },
+        });
+        app.world.spawn((Transform::default(), ChunkViewer));
+
+        for _ in 0..5 {
+            app.update();
+        }
+
+        // Every chunk within 2 of the origin, in a circle
+        let world = app.world.resource::<World>();
+        assert_eq!(world.chunks.len(), 13);
+        assert!(world.meshes.is_empty());
+        assert!(!app.world.contains_resource::<Assets<Mesh>>());
+
+        app.world
+            .resource::<SaveDir>()
+            .save(world, app.world.resource())
+            .unwrap();
+        assert!(save_dir.join("chunks").join("0_0_0.chunk").exists());
+        std::fs::remove_dir_all(&save_dir).unwrap();
+    }
+}
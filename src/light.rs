@@ -0,0 +1,395 @@
+use std::collections::VecDeque;
+
+use bevy::{prelude::*, utils::HashSet};
+
+use crate::{
+    chunk::{CHUNK_SIZE, CHUNK_SIZE_I32, CHUNK_VOLUME},
+    mesh::Direction,
+    world::{split_pos, World},
+};
+
+pub const MAX_LIGHT: u8 = 15;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LightKind {
+    /// Light coming down from the sky
+    Sky,
+    /// Light emitted by blocks such as torches and lava
+    Block,
+}
+
+/// The light levels of every block in a chunk.
+///
+/// Sky light is stored in the high nibble and block light in the low nibble.
+#[derive(Clone)]
+pub struct LightVolume {
+    levels: Box<[u8; CHUNK_VOLUME]>,
+}
+
+impl Default for LightVolume {
+    fn default() -> Self {
+        Self {
+            levels: Box::new([0; CHUNK_VOLUME]),
+        }
+    }
+}
+
+impl LightVolume {
+    fn index(local: IVec3) -> usize {
+        (local.x as usize * CHUNK_SIZE + local.y as usize) * CHUNK_SIZE + local.z as usize
+    }
+
+    pub fn get(&self, kind: LightKind, local: IVec3) -> u8 {
+        let packed = self.levels[Self::index(local)];
+
+        match kind {
+            LightKind::Sky => packed >> 4,
+            LightKind::Block => packed & 0xf,
+        }
+    }
+
+    pub fn set(&mut self, kind: LightKind, local: IVec3, level: u8) {
+        let packed = &mut self.levels[Self::index(local)];
+
+        *packed = match kind {
+            LightKind::Sky => (*packed & 0xf) | (level << 4),
+            LightKind::Block => (*packed & 0xf0) | level,
+        };
+    }
+
+    pub fn clear(&mut self, kind: LightKind) {
+        for x in 0..CHUNK_SIZE_I32 {
+            for y in 0..CHUNK_SIZE_I32 {
+                for z in 0..CHUNK_SIZE_I32 {
+                    self.set(kind, IVec3::new(x, y, z), 0);
+                }
+            }
+        }
+    }
+}
+
+/// Converts a light level into the brightness baked into vertex colours
+pub fn brightness(level: u8) -> f32 {
+    0.8f32.powi((MAX_LIGHT - level) as i32).max(0.05)
+}
+
+/// The combined light level at a global position.
+///
+/// Positions in unloaded chunks are treated as open sky.
+pub fn level_at(world: &World, pos: IVec3) -> u8 {
+    let (chunk_id, local) = split_pos(pos);
+
+    world.chunks.get(&chunk_id).map_or(MAX_LIGHT, |chunk| {
+        chunk
+            .light
+            .get(LightKind::Sky, local)
+            .max(chunk.light.get(LightKind::Block, local))
+    })
+}
+
+/// Flood fills one kind of light through the world,
+/// keeping track of which chunk meshes need rebuilding
+struct Propagator<'a> {
+    world: &'a mut World,
+    kind: LightKind,
+    add: VecDeque<IVec3>,
+    remove: VecDeque<(IVec3, u8)>,
+    touched: HashSet<IVec3>,
+}
+
+impl<'a> Propagator<'a> {
+    fn new(world: &'a mut World, kind: LightKind) -> Self {
+        Self {
+            world,
+            kind,
+            add: VecDeque::new(),
+            remove: VecDeque::new(),
+            touched: HashSet::default(),
+        }
+    }
+
+    /// Returns None if the chunk isn't loaded
+    fn get(&self, pos: IVec3) -> Option<u8> {
+        let (chunk_id, local) = split_pos(pos);
+        self.world
+            .chunks
+            .get(&chunk_id)
+            .map(|chunk| chunk.light.get(self.kind, local))
+    }
+
+    fn set(&mut self, pos: IVec3, level: u8) {
+        let (chunk_id, local) = split_pos(pos);
+        let Some(chunk) = self.world.chunks.get_mut(&chunk_id) else { return; };
+
+        chunk.light.set(self.kind, local, level);
+
+        // Faces of neighbouring chunks sample light across the border
+        for dir in Direction::iter() {
+            self.touched.insert(split_pos(pos + dir.normal()).0);
+        }
+    }
+
+    /// Whether light at `level` travelling in `dir` keeps its full strength
+    fn is_sunbeam(&self, dir: Direction, level: u8) -> bool {
+        self.kind == LightKind::Sky && dir == Direction::Ny && level == MAX_LIGHT
+    }
+
+    fn emission(&self, pos: IVec3) -> u8 {
+        match self.kind {
+            LightKind::Sky => 0,
+            LightKind::Block => self.world.get_block(pos).map_or(0, |block| block.emission()),
+        }
+    }
+
+    /// Removes the light at `pos`, and any light that depended on it
+    fn darken(&mut self, pos: IVec3) {
+        let level = self.get(pos).unwrap_or(0);
+        if level > 0 {
+            self.set(pos, 0);
+            self.remove.push_back((pos, level));
+        }
+    }
+
+    /// Sets the light at `pos` if brighter, and spreads it outwards
+    fn brighten(&mut self, pos: IVec3, level: u8) {
+        if self.get(pos).is_some_and(|current| current < level) {
+            self.set(pos, level);
+            self.add.push_back(pos);
+        }
+    }
+
+    fn flood(mut self) {
+        while let Some((pos, level)) = self.remove.pop_front() {
+            for dir in Direction::iter() {
+                let neighbour = pos + dir.normal();
+                let Some(neighbour_level) = self.get(neighbour) else { continue; };
+
+                let emission = self.emission(neighbour);
+                if emission > 0 {
+                    self.set(neighbour, emission);
+                    self.add.push_back(neighbour);
+                } else if neighbour_level != 0
+                    && (neighbour_level < level || self.is_sunbeam(dir, level))
+                {
+                    self.set(neighbour, 0);
+                    self.remove.push_back((neighbour, neighbour_level));
+                } else if neighbour_level >= level {
+                    self.add.push_back(neighbour);
+                }
+            }
+        }
+
+        while let Some(pos) = self.add.pop_front() {
+            let Some(level) = self.get(pos) else { continue; };
+
+            for dir in Direction::iter() {
+                let new_level = if self.is_sunbeam(dir, level) {
+                    MAX_LIGHT
+                } else {
+                    level.saturating_sub(1)
+                };
+
+                if new_level == 0 {
+                    continue;
+                }
+
+                let neighbour = pos + dir.normal();
+                match self.world.get_block(neighbour) {
+                    Some(block) if !block.is_opaque() => self.brighten(neighbour, new_level),
+                    _ => {}
+                }
+            }
+        }
+
+        for chunk_id in self.touched {
+            if self.world.chunks.contains_key(&chunk_id) {
+                self.world.invalidate_mesh(chunk_id);
+            }
+        }
+    }
+}
+
+/// Recalculates all light in the given chunks,
+/// pulling in light from any loaded neighbours
+pub fn relight_chunks(world: &mut World, chunk_ids: &[IVec3]) {
+    // Sunlight is seeded top down, so chunks above must be seeded first
+    let mut chunk_ids = chunk_ids.to_vec();
+    chunk_ids.sort_by_key(|chunk_id| -chunk_id.y);
+
+    for kind in [LightKind::Sky, LightKind::Block] {
+        let mut propagator = Propagator::new(world, kind);
+
+        for chunk_id in &chunk_ids {
+            if let Some(chunk) = propagator.world.chunks.get_mut(chunk_id) {
+                chunk.light.clear(kind);
+            }
+        }
+
+        for &chunk_id in &chunk_ids {
+            if !propagator.world.chunks.contains_key(&chunk_id) {
+                continue;
+            }
+
+            let origin = chunk_id * CHUNK_SIZE_I32;
+
+            for x in 0..CHUNK_SIZE_I32 {
+                for z in 0..CHUNK_SIZE_I32 {
+                    // Unloaded chunks above are treated as open sky
+                    let mut sky = propagator
+                        .get(origin + IVec3::new(x, CHUNK_SIZE_I32, z))
+                        .unwrap_or(MAX_LIGHT);
+
+                    for y in (0..CHUNK_SIZE_I32).rev() {
+                        let pos = origin + IVec3::new(x, y, z);
+                        let block = propagator.world.get_block(pos).unwrap();
+
+                        if block.is_opaque() {
+                            sky = 0;
+                        }
+
+                        let level = match kind {
+                            LightKind::Sky if sky == MAX_LIGHT => MAX_LIGHT,
+                            LightKind::Sky => 0,
+                            LightKind::Block => block.emission(),
+                        };
+
+                        if level > 0 {
+                            propagator.set(pos, level);
+                            propagator.add.push_back(pos);
+                        }
+                    }
+                }
+            }
+
+            // Let light from neighbouring chunks spill in
+            for dir in Direction::iter() {
+                for i in 0..CHUNK_SIZE_I32 {
+                    for j in 0..CHUNK_SIZE_I32 {
+                        let local = match dir {
+                            Direction::Px => IVec3::new(CHUNK_SIZE_I32, i, j),
+                            Direction::Nx => IVec3::new(-1, i, j),
+                            Direction::Py => IVec3::new(i, CHUNK_SIZE_I32, j),
+                            Direction::Ny => IVec3::new(i, -1, j),
+                            Direction::Pz => IVec3::new(i, j, CHUNK_SIZE_I32),
+                            Direction::Nz => IVec3::new(i, j, -1),
+                        };
+
+                        if propagator.get(origin + local).is_some_and(|level| level > 0) {
+                            propagator.add.push_back(origin + local);
+                        }
+                    }
+                }
+            }
+        }
+
+        propagator.flood();
+    }
+}
+
+/// Incrementally updates light after the block at `pos` changed
+pub fn update_block(world: &mut World, pos: IVec3) {
+    let Some(block) = world.get_block(pos) else { return; };
+
+    for kind in [LightKind::Sky, LightKind::Block] {
+        let mut propagator = Propagator::new(world, kind);
+
+        // Whatever was lighting this block may no longer be valid
+        propagator.darken(pos);
+
+        if !block.is_opaque() {
+            for dir in Direction::iter() {
+                let neighbour = pos + dir.normal();
+                if propagator.get(neighbour).is_some_and(|level| level > 0) {
+                    propagator.add.push_back(neighbour);
+                }
+            }
+
+            if kind == LightKind::Sky && propagator.get(pos + IVec3::Y).is_none() {
+                propagator.brighten(pos, MAX_LIGHT);
+            }
+        }
+
+        if kind == LightKind::Block {
+            propagator.brighten(pos, block.emission());
+        }
+
+        propagator.flood();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::Block;
+
+    const CHUNK_IDS: [IVec3; 2] = [IVec3::ZERO, IVec3::X];
+
+    /// A stone floor with a roof over the border between the two chunks
+    fn roofed_world() -> World {
+        World::with_chunks(CHUNK_IDS, |pos| {
+            let border = CHUNK_SIZE_I32 - 6..CHUNK_SIZE_I32 + 6;
+            if pos.y == 0 || (pos.y == 8 && border.contains(&pos.x)) {
+                Block::Stone
+            } else {
+                Block::Air
+            }
+        })
+    }
+
+    /// Checks the incrementally updated light against relighting both chunks from scratch
+    fn assert_matches_relight(world: &mut World, step: &str) {
+        let updated = CHUNK_IDS.map(|chunk_id| world.chunks[&chunk_id].light.clone());
+        relight_chunks(world, &CHUNK_IDS);
+
+        for (chunk_id, updated) in CHUNK_IDS.into_iter().zip(updated) {
+            let relit = &world.chunks[&chunk_id].light;
+
+            for kind in [LightKind::Sky, LightKind::Block] {
+                for x in 0..CHUNK_SIZE_I32 {
+                    for y in 0..CHUNK_SIZE_I32 {
+                        for z in 0..CHUNK_SIZE_I32 {
+                            let local = IVec3::new(x, y, z);
+                            assert_eq!(
+                                updated.get(kind, local),
+                                relit.get(kind, local),
+                                "{step}: {kind:?} light at {local} in chunk {chunk_id}"
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn edits_at_chunk_border_match_relight() {
+        let mut world = roofed_world();
+        let torch = IVec3::new(CHUNK_SIZE_I32 - 1, 1, 5);
+        let wall = IVec3::new(CHUNK_SIZE_I32, 1, 5);
+        let skylight = IVec3::new(CHUNK_SIZE_I32, 8, 5);
+
+        world.set_block(torch, Block::Torch);
+        assert_matches_relight(&mut world, "place torch");
+        // The torch lights up the next chunk over
+        let light = &world.chunks[&IVec3::X].light;
+        assert_eq!(
+            light.get(LightKind::Block, wall - IVec3::X * CHUNK_SIZE_I32),
+            Block::Torch.emission() - 1
+        );
+
+        world.set_block(wall, Block::Stone);
+        assert_matches_relight(&mut world, "place wall");
+
+        world.set_block(skylight, Block::Air);
+        assert_matches_relight(&mut world, "open roof");
+
+        world.set_block(torch, Block::Air);
+        assert_matches_relight(&mut world, "remove torch");
+
+        world.set_block(wall, Block::Air);
+        assert_matches_relight(&mut world, "remove wall");
+
+        world.set_block(skylight, Block::Stone);
+        assert_matches_relight(&mut world, "close roof");
+    }
+}
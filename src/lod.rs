@@ -0,0 +1,90 @@
+use bevy::prelude::*;
+
+use crate::{
+    chunk::CHUNK_SIZE_I32,
+    world::{world_mesh_gen, ChunkViewer, World},
+};
+
+pub struct LodPlugin;
+
+impl Plugin for LodPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LodSettings>()
+            .add_systems(Update, Self::select_lods.before(world_mesh_gen));
+    }
+}
+
+#[derive(Resource, Clone)]
+pub struct LodSettings {
+    /// The distances from the camera at which chunks drop to LOD 1, 2 and 3,
+    /// where each LOD halves the resolution
+    pub distances: [f32; 3],
+}
+
+impl Default for LodSettings {
+    fn default() -> Self {
+        Self {
+            distances: [96., 160., 256.],
+        }
+    }
+}
+
+impl LodSettings {
+    pub fn lod_for(&self, distance: f32) -> u32 {
+        self.distances
+            .iter()
+            .filter(|&&threshold| distance >= threshold)
+            .count() as u32
+    }
+}
+
+impl LodPlugin {
+    /// Picks each chunk's LOD by its distance to the camera,
+    /// remeshing any that changed
+    pub fn select_lods(
+        query: Query<&Transform, With<ChunkViewer>>,
+        settings: Res<LodSettings>,
+        mut world: ResMut<World>,
+    ) {
+        let Ok(transform) = query.get_single() else { return; };
+
+        let changed = world
+            .chunks
+            .keys()
+            .filter_map(|&chunk_id| {
+                let centre = (chunk_id * CHUNK_SIZE_I32).as_vec3()
+                    + Vec3::splat(CHUNK_SIZE_I32 as f32 / 2.);
+                let lod = settings.lod_for(centre.distance(transform.translation));
+
+                (world.lods.get(&chunk_id).copied().unwrap_or(0) != lod).then_some((chunk_id, lod))
+            })
+            .collect::<Vec<_>>();
+
+        for (chunk_id, lod) in changed {
+            world.lods.insert(chunk_id, lod);
+            world.invalidate_mesh(chunk_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lods_drop_at_each_threshold() {
+        let settings = LodSettings::default();
+
+        for (distance, lod) in [
+            (0., 0),
+            (95.9, 0),
+            (96., 1),
+            (159.9, 1),
+            (160., 2),
+            (256., 3),
+            (10_000., 3),
+        ] {
+            assert_eq!(settings.lod_for(distance), lod, "at {distance}");
+        }
+    }
+}
@@ -1,93 +1,71 @@
-mod block;
-mod camera;
-mod chunk;
-mod custom_diagnostics;
-mod mesh;
-mod noise_debug;
-mod world;
+use std::net::ToSocketAddrs;
 
-use crate::world::World;
 use bevy::prelude::*;
+use bevy_craft::{
+    avatar::AvatarPlugin, chat::ChatPlugin, crafting::CraftingPlugin, drops::DropsPlugin, export,
+    inventory::HotbarPlugin, net::NetClientPlugin, DebugPlugins, EditorPlugins, FlyCamPlugin,
+    VoxelWorldPlugin,
+};
 use bevy_egui::EguiPlugin;
-use chunk::{Chunk, TerrainGen};
-use world::world_mesh_gen;
 
 fn main() {
-    App::new()
-        .add_plugins(DefaultPlugins.set(ImagePlugin::default_nearest()))
-        .add_plugins(EguiPlugin)
-        .add_plugins(bevy::diagnostic::FrameTimeDiagnosticsPlugin)
-        .add_plugins(bevy::asset::diagnostic::AssetCountDiagnosticsPlugin::<Mesh>::default())
-        .add_plugins(custom_diagnostics::CustomDiagnosticsPlugin)
-        .add_plugins(noise_debug::NoiseDebugPlugin)
-        .add_plugins(camera::FlyCamPlugin)
-        .add_systems(Startup, create_axis)
-        .add_systems(Startup, create_crosshair)
-        .add_systems(Startup, setup)
-        .add_systems(Update, world_mesh_gen.after(camera::FlyCamPlugin::pointer))
-        .insert_resource(TerrainGen::default())
-        .insert_resource(World::new())
-        .run();
-}
+    let args = std::env::args().collect::<Vec<_>>();
+    if args.get(1).map(String::as_str) == Some("export") {
+        if let Err(err) = export::run_cli(&args[2..]) {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
 
-fn setup(
-    mut commands: Commands,
-    asset_server: Res<AssetServer>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    mut world: ResMut<World>,
-    terrain_gen: Res<TerrainGen>,
-) {
-    commands.spawn(DirectionalLightBundle {
-        directional_light: DirectionalLight {
-            color: Color::WHITE,
-            illuminance: 20_000.,
-            shadows_enabled: true,
-            ..default()
-        },
-        transform: Transform::from_xyz(1., 2., 0.2).looking_at(Vec3::new(0., 0., 0.), Vec3::Y),
-        ..default()
-    });
+    let mut app = App::new();
+    app.add_plugins(DefaultPlugins.set(ImagePlugin::default_nearest()))
+        .add_plugins(EguiPlugin);
 
-    let world_tex: Handle<Image> = asset_server.load("./Texture.png");
-    world.material = materials.add(StandardMaterial {
-        base_color_texture: Some(world_tex),
-        ..default()
-    });
+    if args.get(1).map(String::as_str) == Some("connect") {
+        let Some(server) = args.get(2) else {
+            eprintln!("Usage: bevy_craft connect <address:port> [name]");
+            std::process::exit(1);
+        };
+        let server = match server.to_socket_addrs().map(|mut addrs| addrs.next()) {
+            Ok(Some(server)) => server,
+            Ok(None) => {
+                eprintln!("{} has no addresses", server);
+                std::process::exit(1);
+            }
+            Err(err) => {
+                eprintln!("Invalid address {:?}: {}", server, err);
+                std::process::exit(1);
+            }
+        };
 
-    for x in -2..=2 {
-        for z in -2..=2 {
-            let id = IVec3::new(x, 0, z);
-            let mut chunk = Chunk::new(id);
-            chunk.generate(&terrain_gen);
-            world.chunks.insert(id, chunk);
-            world.invalid_meshes.push(id);
-        }
-    }
-}
+        let name = args
+            .get(3)
+            .cloned()
+            .or_else(|| std::env::var("USER").ok())
+            .unwrap_or_default();
 
-fn create_crosshair(mut commands: Commands) {
-    commands
-        .spawn(NodeBundle {
-            style: Style {
-                width: Val::Percent(100.),
-                height: Val::Percent(100.),
-                align_items: AlignItems::Center,
-                justify_content: JustifyContent::Center,
-                ..default()
-            },
+        // The editors change the world directly, so they're left out when it's on a server,
+        // as are drops and crafting, which the server doesn't know about
+        app.add_plugins(VoxelWorldPlugin {
+            remote: true,
             ..default()
         })
-        .with_children(|parent| {
-            parent.spawn(ButtonBundle {
-                style: Style {
-                    width: Val::Px(4.),
-                    height: Val::Px(4.),
-                    ..default()
-                },
-                background_color: Color::WHITE.into(),
-                ..default()
-            });
-        });
+        .add_plugins(NetClientPlugin { server, name })
+        .add_plugins(AvatarPlugin)
+        .add_plugins(ChatPlugin);
+    } else {
+        app.add_plugins(VoxelWorldPlugin::default())
+            .add_plugins(EditorPlugins)
+            .add_plugins(DropsPlugin)
+            .add_plugins(CraftingPlugin);
+    }
+
+    app.add_plugins(FlyCamPlugin::default())
+        .add_plugins(HotbarPlugin)
+        .add_plugins(DebugPlugins)
+        .add_systems(Startup, create_axis)
+        .run();
 }
 
 fn create_axis(
@@ -1,11 +1,27 @@
 use bevy::{
     prelude::*,
-    render::{mesh::Indices, render_resource::PrimitiveTopology},
+    render::{
+        mesh::{Indices, MeshVertexAttribute},
+        render_resource::{PrimitiveTopology, VertexFormat},
+    },
 };
 use parry3d::na;
 
 use crate::block::Block;
 
+/// The layer of the block texture array each vertex samples
+pub const ATTRIBUTE_LAYER: MeshVertexAttribute =
+    MeshVertexAttribute::new("Layer", 988540917, VertexFormat::Uint32);
+
+/// The uvs of each face's vertices, tiled once per block
+const FACE_UVS: [Vec2; 4] = [
+    Vec2::new(0., 1.),
+    Vec2::new(0., 0.),
+    Vec2::new(1., 0.),
+    Vec2::new(1., 1.),
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum Direction {
     Px,
     Py,
@@ -17,10 +33,38 @@ pub enum Direction {
 
 impl Direction {
     pub fn iter() -> impl Iterator<Item = Direction> {
-        use Direction::*;
+        use self::Direction::*;
         [Px, Py, Pz, Nx, Ny, Nz].into_iter()
     }
 
+    /// The position of this direction in `Direction::iter`
+    pub fn index(&self) -> usize {
+        *self as usize
+    }
+
+    pub fn opposite(&self) -> Direction {
+        match self {
+            Direction::Px => Direction::Nx,
+            Direction::Py => Direction::Ny,
+            Direction::Pz => Direction::Nz,
+            Direction::Nx => Direction::Px,
+            Direction::Ny => Direction::Py,
+            Direction::Nz => Direction::Pz,
+        }
+    }
+
+    /// The unit offset to the neighbouring block in this direction
+    pub fn normal(&self) -> IVec3 {
+        match self {
+            Direction::Px => IVec3::X,
+            Direction::Py => IVec3::Y,
+            Direction::Pz => IVec3::Z,
+            Direction::Nx => IVec3::NEG_X,
+            Direction::Ny => IVec3::NEG_Y,
+            Direction::Nz => IVec3::NEG_Z,
+        }
+    }
+
     pub fn face_verts(&self) -> [(Vec3, Vec3); 4] {
         match self {
             Direction::Px => [
@@ -63,39 +107,75 @@ impl Direction {
     }
 }
 
-#[derive(Default)]
 pub struct IncompleteMesh {
+    /// The size of each face, used for lower LODs
+    scale: i32,
+
     // Splitting the pos, uv, and normals into separate vectors
     // avoids calls to map when inserting the attrs,
     // should benchmark in the future to verify
     vertices: Vec<Vec3>,
     normals: Vec<Vec3>,
     uvs: Vec<Vec2>,
+    colours: Vec<[f32; 4]>,
+    layers: Vec<u32>,
+
+    /// Stored as `u32` and only narrowed to `u16` if every index fits
+    indices: Vec<u32>,
+}
 
-    indices: Vec<u16>,
+impl Default for IncompleteMesh {
+    fn default() -> Self {
+        Self::with_scale(1)
+    }
 }
 
 impl IncompleteMesh {
-    pub fn add_face(&mut self, pos: Vec3, dir: Direction, invert: bool, block: Block) {
+    pub fn with_scale(scale: i32) -> Self {
+        Self {
+            scale,
+            vertices: vec![],
+            normals: vec![],
+            uvs: vec![],
+            colours: vec![],
+            layers: vec![],
+            indices: vec![],
+        }
+    }
+
+    /// `light` is the brightness the face is lit with, baked into the vertex colours
+    pub fn add_face(&mut self, pos: Vec3, dir: Direction, invert: bool, block: Block, light: f32) {
         let mut indices = [0, 3, 1, 3, 2, 1];
         if invert {
             indices.reverse();
         }
         for i in indices {
-            self.indices.push(i + self.vertices.len() as u16);
+            self.indices.push(i + self.vertices.len() as u32);
         }
 
         for (v, n) in dir.face_verts() {
-            self.vertices.push(v + pos);
+            self.vertices.push((v + pos) * self.scale as f32);
             self.normals.push(n * if invert { -1. } else { 1. });
         }
 
-        for uv in block.uvs() {
-            self.uvs.push(uv);
+        for uv in FACE_UVS {
+            self.uvs.push(uv * self.scale as f32);
         }
+
+        self.colours.extend([[light, light, light, 1.]; 4]);
+        self.layers.extend([block.layer(); 4]);
     }
 
-    pub fn maybe_add_face(&mut self, pos: Vec3, dir: Direction, a: Block, b: Block) {
+    /// `light` samples the brightness at a block position relative to the mesh,
+    /// and is called with the air side of the face
+    pub fn maybe_add_face(
+        &mut self,
+        pos: Vec3,
+        dir: Direction,
+        a: Block,
+        b: Block,
+        light: &impl Fn(IVec3) -> f32,
+    ) {
         let (block, invert) = match (a, b) {
             (Block::Air, Block::Air) => return,
             (Block::Air, b) => (b, true),
@@ -103,7 +183,13 @@ impl IncompleteMesh {
             _ => return,
         };
 
-        self.add_face(pos, dir, invert, block);
+        let air_pos = if invert {
+            pos.as_ivec3()
+        } else {
+            pos.as_ivec3() + dir.normal()
+        };
+
+        self.add_face(pos, dir, invert, block, light(air_pos * self.scale));
     }
 
     pub fn complete(self) -> Mesh {
@@ -111,7 +197,15 @@ impl IncompleteMesh {
         mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, self.vertices);
         mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, self.normals);
         mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, self.uvs);
-        mesh.set_indices(Some(Indices::U16(self.indices)));
+        mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, self.colours);
+        mesh.insert_attribute(ATTRIBUTE_LAYER, self.layers);
+
+        let indices = if mesh.count_vertices() <= u16::MAX as usize + 1 {
+            Indices::U16(self.indices.into_iter().map(|i| i as u16).collect())
+        } else {
+            Indices::U32(self.indices)
+        };
+        mesh.set_indices(Some(indices));
         mesh
     }
 }
@@ -148,8 +242,74 @@ pub fn test_cube() -> Mesh {
     let mut incomplete_mesh = IncompleteMesh::default();
 
     for dir in Direction::iter() {
-        incomplete_mesh.add_face(Vec3::ZERO, dir, false, Block::Dirt);
+        incomplete_mesh.add_face(Vec3::ZERO, dir, false, Block::Dirt, 1.);
     }
 
     incomplete_mesh.complete()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::{Chunk, CHUNK_SIZE};
+
+    /// Checks every triangle indexes a real vertex
+    fn assert_valid(mesh: &Mesh) {
+        let vertex_count = mesh.count_vertices();
+        let indices = mesh.indices().expect("Mesh has no indices");
+
+        assert_eq!(indices.len() % 3, 0);
+        assert!(indices.iter().all(|i| i < vertex_count));
+    }
+
+    #[test]
+    fn promotes_to_u32_indices() {
+        let mut incomplete_mesh = IncompleteMesh::default();
+
+        // 4 vertices per face puts this past what u16 can index
+        for i in 0..20_000 {
+            let pos = Vec3::new(i as f32, 0., 0.);
+            incomplete_mesh.add_face(pos, Direction::Py, false, Block::Dirt, 1.);
+        }
+
+        let mesh = incomplete_mesh.complete();
+
+        assert!(matches!(mesh.indices(), Some(Indices::U32(_))));
+        assert_eq!(mesh.count_vertices(), 80_000);
+        assert_valid(&mesh);
+    }
+
+    #[test]
+    fn small_meshes_keep_u16_indices() {
+        let mesh = test_cube();
+
+        assert!(matches!(mesh.indices(), Some(Indices::U16(_))));
+        assert_valid(&mesh);
+    }
+
+    #[test]
+    fn checkerboard_chunk_mesh_is_valid() {
+        // Every solid block has all 6 faces exposed, the most faces a chunk can have
+        let mut chunk = Chunk::new(IVec3::ZERO);
+        for x in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE {
+                for z in 0..CHUNK_SIZE {
+                    if (x + y + z) % 2 == 0 {
+                        *chunk.get_mut(x, y, z) = Block::Stone;
+                    }
+                }
+            }
+        }
+
+        let mesh = chunk.build_mesh(0, |_| 1.);
+
+        assert_eq!(mesh.count_vertices(), CHUNK_SIZE.pow(3) / 2 * 6 * 4);
+        // Only chunks bigger than the default need u32 indices, even in the worst case
+        if mesh.count_vertices() > u16::MAX as usize + 1 {
+            assert!(matches!(mesh.indices(), Some(Indices::U32(_))));
+        } else {
+            assert!(matches!(mesh.indices(), Some(Indices::U16(_))));
+        }
+        assert_valid(&mesh);
+    }
+}
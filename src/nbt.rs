@@ -0,0 +1,359 @@
+use std::{
+    collections::BTreeMap,
+    io::{self, Read, Write},
+};
+
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+
+/// A value in Minecraft's Named Binary Tag format
+#[derive(Clone, PartialEq, Debug)]
+pub enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<i8>),
+    String(String),
+    /// Every element must be the same kind of tag
+    List(Vec<Tag>),
+    Compound(BTreeMap<String, Tag>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+const END: u8 = 0;
+/// How deeply lists and compounds can be nested, the same limit as Minecraft,
+/// so a malicious file can't overflow the stack
+const MAX_DEPTH: usize = 512;
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+impl Tag {
+    fn id(&self) -> u8 {
+        match self {
+            Tag::Byte(_) => 1,
+            Tag::Short(_) => 2,
+            Tag::Int(_) => 3,
+            Tag::Long(_) => 4,
+            Tag::Float(_) => 5,
+            Tag::Double(_) => 6,
+            Tag::ByteArray(_) => 7,
+            Tag::String(_) => 8,
+            Tag::List(_) => 9,
+            Tag::Compound(_) => 10,
+            Tag::IntArray(_) => 11,
+            Tag::LongArray(_) => 12,
+        }
+    }
+
+    /// Gets a child of a compound
+    pub fn get(&self, name: &str) -> Option<&Tag> {
+        match self {
+            Tag::Compound(children) => children.get(name),
+            _ => None,
+        }
+    }
+
+    /// Gets any integer tag as an `i64`
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Tag::Byte(value) => Some(value as i64),
+            Tag::Short(value) => Some(value as i64),
+            Tag::Int(value) => Some(value as i64),
+            Tag::Long(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    fn read_string(reader: &mut impl Read) -> io::Result<String> {
+        let mut len = [0; 2];
+        reader.read_exact(&mut len)?;
+
+        let mut bytes = vec![0; u16::from_be_bytes(len) as usize];
+        reader.read_exact(&mut bytes)?;
+
+        // Strings are really modified UTF-8, which only differs for nulls and emoji
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    fn write_string(writer: &mut impl Write, string: &str) -> io::Result<()> {
+        let len = u16::try_from(string.len()).map_err(|_| invalid_data("String too long"))?;
+        writer.write_all(&len.to_be_bytes())?;
+        writer.write_all(string.as_bytes())
+    }
+
+    fn read_len(reader: &mut impl Read) -> io::Result<usize> {
+        let mut bytes = [0; 4];
+        reader.read_exact(&mut bytes)?;
+
+        usize::try_from(i32::from_be_bytes(bytes)).map_err(|_| invalid_data("Negative length"))
+    }
+
+    /// Reads exactly `len` bytes, growing the buffer as they arrive
+    /// rather than trusting a length from the file up front
+    fn read_bytes(reader: &mut impl Read, len: usize) -> io::Result<Vec<u8>> {
+        let mut bytes = vec![];
+        reader.take(len as u64).read_to_end(&mut bytes)?;
+
+        if bytes.len() < len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(bytes)
+    }
+
+    /// Reads the bytes of a length prefixed array of numbers that are each `SIZE` bytes
+    fn read_array<const SIZE: usize>(reader: &mut impl Read) -> io::Result<Vec<[u8; SIZE]>> {
+        let len = Self::read_len(reader)?;
+        let bytes = Self::read_bytes(
+            reader,
+            len.checked_mul(SIZE)
+                .ok_or_else(|| invalid_data("Array too long"))?,
+        )?;
+
+        Ok(bytes
+            .chunks_exact(SIZE)
+            .map(|chunk| chunk.try_into().unwrap())
+            .collect())
+    }
+
+    fn write_len(writer: &mut impl Write, len: usize) -> io::Result<()> {
+        let len = i32::try_from(len).map_err(|_| invalid_data("Array too long"))?;
+        writer.write_all(&len.to_be_bytes())
+    }
+
+    /// `depth` is how many lists and compounds the tag is inside
+    fn read_payload(reader: &mut impl Read, id: u8, depth: usize) -> io::Result<Tag> {
+        if depth > MAX_DEPTH {
+            return Err(invalid_data("Tags are nested too deeply"));
+        }
+
+        macro_rules! read_number {
+            ($ty:ty) => {{
+                let mut bytes = [0; std::mem::size_of::<$ty>()];
+                reader.read_exact(&mut bytes)?;
+                <$ty>::from_be_bytes(bytes)
+            }};
+        }
+
+        Ok(match id {
+            1 => Tag::Byte(read_number!(i8)),
+            2 => Tag::Short(read_number!(i16)),
+            3 => Tag::Int(read_number!(i32)),
+            4 => Tag::Long(read_number!(i64)),
+            5 => Tag::Float(read_number!(f32)),
+            6 => Tag::Double(read_number!(f64)),
+            7 => {
+                let len = Self::read_len(reader)?;
+                let bytes = Self::read_bytes(reader, len)?;
+                Tag::ByteArray(bytes.into_iter().map(|byte| byte as i8).collect())
+            }
+            8 => Tag::String(Self::read_string(reader)?),
+            9 => {
+                let mut element_id = [0];
+                reader.read_exact(&mut element_id)?;
+                let len = Self::read_len(reader)?;
+
+                // Pushed one at a time, as every element takes at least a byte to read
+                let mut values = vec![];
+                for _ in 0..len {
+                    values.push(Self::read_payload(reader, element_id[0], depth + 1)?);
+                }
+                Tag::List(values)
+            }
+            10 => {
+                let mut children = BTreeMap::new();
+
+                loop {
+                    let mut child_id = [0];
+                    reader.read_exact(&mut child_id)?;
+                    if child_id[0] == END {
+                        break;
+                    }
+
+                    let name = Self::read_string(reader)?;
+                    children.insert(name, Self::read_payload(reader, child_id[0], depth + 1)?);
+                }
+
+                Tag::Compound(children)
+            }
+            11 => Tag::IntArray(
+                Self::read_array(reader)?
+                    .into_iter()
+                    .map(i32::from_be_bytes)
+                    .collect(),
+            ),
+            12 => Tag::LongArray(
+                Self::read_array(reader)?
+                    .into_iter()
+                    .map(i64::from_be_bytes)
+                    .collect(),
+            ),
+            _ => return Err(invalid_data(format!("Unknown tag id {}", id))),
+        })
+    }
+
+    fn write_payload(&self, writer: &mut impl Write) -> io::Result<()> {
+        match self {
+            Tag::Byte(value) => writer.write_all(&value.to_be_bytes()),
+            Tag::Short(value) => writer.write_all(&value.to_be_bytes()),
+            Tag::Int(value) => writer.write_all(&value.to_be_bytes()),
+            Tag::Long(value) => writer.write_all(&value.to_be_bytes()),
+            Tag::Float(value) => writer.write_all(&value.to_be_bytes()),
+            Tag::Double(value) => writer.write_all(&value.to_be_bytes()),
+            Tag::ByteArray(values) => {
+                Self::write_len(writer, values.len())?;
+                writer.write_all(&values.iter().map(|value| *value as u8).collect::<Vec<_>>())
+            }
+            Tag::String(value) => Self::write_string(writer, value),
+            Tag::List(values) => {
+                let element_id = values.first().map_or(END, Tag::id);
+                if values.iter().any(|value| value.id() != element_id) {
+                    return Err(invalid_data("List elements are different kinds of tag"));
+                }
+
+                writer.write_all(&[element_id])?;
+                Self::write_len(writer, values.len())?;
+                values
+                    .iter()
+                    .try_for_each(|value| value.write_payload(writer))
+            }
+            Tag::Compound(children) => {
+                for (name, child) in children {
+                    writer.write_all(&[child.id()])?;
+                    Self::write_string(writer, name)?;
+                    child.write_payload(writer)?;
+                }
+                writer.write_all(&[END])
+            }
+            Tag::IntArray(values) => {
+                Self::write_len(writer, values.len())?;
+                values
+                    .iter()
+                    .try_for_each(|value| writer.write_all(&value.to_be_bytes()))
+            }
+            Tag::LongArray(values) => {
+                Self::write_len(writer, values.len())?;
+                values
+                    .iter()
+                    .try_for_each(|value| writer.write_all(&value.to_be_bytes()))
+            }
+        }
+    }
+}
+
+/// Reads an uncompressed root tag along with its name
+pub fn read(reader: &mut impl Read) -> io::Result<(String, Tag)> {
+    let mut id = [0];
+    reader.read_exact(&mut id)?;
+    if id[0] != 10 {
+        return Err(invalid_data("Root tag isn't a compound"));
+    }
+
+    let name = Tag::read_string(reader)?;
+    Ok((name, Tag::read_payload(reader, id[0], 0)?))
+}
+
+pub fn write(writer: &mut impl Write, name: &str, root: &Tag) -> io::Result<()> {
+    writer.write_all(&[root.id()])?;
+    Tag::write_string(writer, name)?;
+    root.write_payload(writer)
+}
+
+/// Reads a gzipped root tag, which is how most NBT files are stored
+pub fn read_gzip(reader: impl Read) -> io::Result<(String, Tag)> {
+    read(&mut GzDecoder::new(reader))
+}
+
+pub fn write_gzip(writer: impl Write, name: &str, root: &Tag) -> io::Result<()> {
+    let mut encoder = GzEncoder::new(writer, Compression::default());
+    write(&mut encoder, name, root)?;
+    encoder.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_tag_round_trips() {
+        let root = Tag::Compound(BTreeMap::from([
+            ("byte".to_string(), Tag::Byte(-3)),
+            ("short".to_string(), Tag::Short(-300)),
+            ("int".to_string(), Tag::Int(70_000)),
+            ("long".to_string(), Tag::Long(-5_000_000_000)),
+            ("float".to_string(), Tag::Float(1.5)),
+            ("double".to_string(), Tag::Double(-0.25)),
+            ("bytes".to_string(), Tag::ByteArray(vec![-128, 0, 127])),
+            (
+                "string".to_string(),
+                Tag::String("minecraft:stone".to_string()),
+            ),
+            (
+                "list".to_string(),
+                Tag::List(vec![Tag::Short(1), Tag::Short(2)]),
+            ),
+            ("empty list".to_string(), Tag::List(vec![])),
+            (
+                "compound".to_string(),
+                Tag::Compound(BTreeMap::from([("inner".to_string(), Tag::Int(1))])),
+            ),
+            (
+                "ints".to_string(),
+                Tag::IntArray(vec![i32::MIN, 0, i32::MAX]),
+            ),
+            (
+                "longs".to_string(),
+                Tag::LongArray(vec![i64::MIN, i64::MAX]),
+            ),
+        ]));
+
+        let mut bytes = vec![];
+        write_gzip(&mut bytes, "Root", &root).unwrap();
+
+        let (name, loaded) = read_gzip(bytes.as_slice()).unwrap();
+        assert_eq!(name, "Root");
+        assert_eq!(loaded, root);
+    }
+
+    #[test]
+    fn bad_lengths_fail_without_allocating() {
+        // A byte array, int array and list of bytes claiming to hold i32::MAX values,
+        // with none following
+        for (id, element_id) in [(7, None), (11, None), (9, Some(1))] {
+            let mut bytes = vec![10, 0, 0, id, 0, 1, b'a'];
+            bytes.extend(element_id);
+            bytes.extend_from_slice(&i32::MAX.to_be_bytes());
+
+            let err = read(&mut bytes.as_slice()).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        }
+    }
+
+    #[test]
+    fn deep_nesting_is_rejected() {
+        let nested = |depth: usize| {
+            // Compounds each holding a compound named "", closed off at the end
+            let mut bytes = vec![10, 0, 0];
+            for _ in 0..depth {
+                bytes.extend_from_slice(&[10, 0, 0]);
+            }
+            bytes.resize(bytes.len() + depth + 1, END);
+            bytes
+        };
+
+        assert!(read(&mut nested(MAX_DEPTH).as_slice()).is_ok());
+        let err = read(&mut nested(100_000).as_slice()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn mixed_lists_are_rejected() {
+        let list = Tag::List(vec![Tag::Byte(1), Tag::Int(1)]);
+        assert!(write(&mut vec![], "", &list).is_err());
+    }
+}
@@ -0,0 +1,1597 @@
+use std::{
+    collections::HashMap,
+    io::{self, Read, Write},
+    net::{SocketAddr, UdpSocket},
+    time::Duration,
+};
+
+use bevy::{app::AppExit, ecs::world::World as EcsWorld, prelude::*};
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+
+use crate::{
+    avatar::{self, RemotePlayer, Snapshot, Snapshots},
+    block::Block,
+    camera::{MOVE_SPEED, SPRINT_MOD},
+    chat::{clean_chat, ChatLog, ChatReceived, SendChat},
+    chunk::{Chunk, CHUNK_VOLUME},
+    console::ConsoleCommands,
+    inventory::Inventory,
+    light,
+    save::SaveDir,
+    streaming::{chunks_around, distance_squared, ViewDistance},
+    world::{split_pos, world_mesh_gen, BlockEdit, BlockEdited, ChunkViewer, World},
+};
+
+/// Bumped whenever the messages change, so old clients are turned away
+pub const PROTOCOL_VERSION: u16 = 3;
+pub const DEFAULT_PORT: u16 = 25570;
+/// The most chunks a client asks for at once
+pub const MAX_CHUNK_REQUESTS: usize = 16;
+/// How far away from their camera a player can edit blocks
+pub const MAX_REACH: f32 = 64.;
+/// How fast a player can move in blocks per second,
+/// which is a `FlyCam` sprinting along all three axes at once with half as much again to spare
+pub const MAX_SPEED: f32 = MOVE_SPEED * SPRINT_MOD * SQRT_3 * 1.5;
+/// How much further than `MAX_SPEED` allows a move can go, as a move can arrive in the same frame as the one before
+pub const MOVE_SLACK: f32 = MAX_SPEED * SEND_INTERVAL.as_secs_f32();
+const SQRT_3: f32 = 1.732_050_8;
+/// Breaks the server hasn't confirmed are forgotten after this many more, in case it turned them down
+const MAX_BREAKING: usize = 8;
+/// Clients that haven't been heard from for this long are dropped
+pub const TIMEOUT: Duration = Duration::from_secs(10);
+/// How often a client sends its position, which also keeps it connected
+pub const SEND_INTERVAL: Duration = Duration::from_millis(50);
+/// How long a client waits for a chunk before asking again
+pub const REQUEST_TIMEOUT: Duration = Duration::from_millis(500);
+/// How often everyone's told who's connected, in case they missed someone joining or leaving
+pub const PLAYERS_INTERVAL: Duration = Duration::from_secs(1);
+/// Longer names are cut short
+pub const MAX_NAME_LEN: usize = 16;
+
+/// The largest possible UDP payload
+const MAX_DATAGRAM: usize = 65_507;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum ClientMessage {
+    /// Sent until the server replies with `Welcome`
+    Hello {
+        version: u16,
+        name: String,
+    },
+    /// Where the player's camera is, which chunks are loaded around
+    Move {
+        translation: Vec3,
+        rotation: Quat,
+    },
+    RequestChunks(Vec<IVec3>),
+    SetBlock {
+        pos: IVec3,
+        block: Block,
+    },
+    /// A message for everyone, or a command for the server if it starts with `/`
+    Chat(String),
+    Goodbye,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum ServerMessage {
+    Welcome {
+        player: u32,
+    },
+    /// A chunk's blocks, from `compress_chunk`
+    Chunk {
+        id: IVec3,
+        data: Vec<u8>,
+    },
+    BlockChanged {
+        pos: IVec3,
+        block: Block,
+    },
+    /// The id and name of everyone connected
+    Players(Vec<(u32, String)>),
+    PlayerMoved {
+        player: u32,
+        translation: Vec3,
+        rotation: Quat,
+    },
+    /// A line of chat, or of a command's output
+    Chat(String),
+}
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// Writes the big endian values messages are made of
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.0.extend(value.to_be_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.0.extend(value.to_be_bytes());
+    }
+
+    fn ivec3(&mut self, value: IVec3) {
+        for axis in value.to_array() {
+            self.0.extend(axis.to_be_bytes());
+        }
+    }
+
+    fn vec3(&mut self, value: Vec3) {
+        for axis in value.to_array() {
+            self.0.extend(axis.to_be_bytes());
+        }
+    }
+
+    fn quat(&mut self, value: Quat) {
+        for axis in value.to_array() {
+            self.0.extend(axis.to_be_bytes());
+        }
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.u32(bytes.len() as u32);
+        self.0.extend(bytes);
+    }
+
+    fn string(&mut self, string: &str) {
+        self.bytes(string.as_bytes());
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        Ok(self.slice(N)?.try_into().unwrap())
+    }
+
+    fn slice(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if len > self.bytes.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "Message ends early",
+            ));
+        }
+
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        Ok(u16::from_be_bytes(self.take()?))
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_be_bytes(self.take()?))
+    }
+
+    fn ivec3(&mut self) -> io::Result<IVec3> {
+        Ok(IVec3::new(
+            i32::from_be_bytes(self.take()?),
+            i32::from_be_bytes(self.take()?),
+            i32::from_be_bytes(self.take()?),
+        ))
+    }
+
+    fn vec3(&mut self) -> io::Result<Vec3> {
+        let pos = Vec3::new(
+            f32::from_be_bytes(self.take()?),
+            f32::from_be_bytes(self.take()?),
+            f32::from_be_bytes(self.take()?),
+        );
+
+        if pos.is_finite() {
+            Ok(pos)
+        } else {
+            Err(invalid_data("Position isn't finite"))
+        }
+    }
+
+    fn quat(&mut self) -> io::Result<Quat> {
+        let rotation = Quat::from_xyzw(
+            f32::from_be_bytes(self.take()?),
+            f32::from_be_bytes(self.take()?),
+            f32::from_be_bytes(self.take()?),
+            f32::from_be_bytes(self.take()?),
+        );
+
+        if rotation.is_finite() && rotation.length_squared() > 0. {
+            Ok(rotation.normalize())
+        } else {
+            Err(invalid_data("Rotation is invalid"))
+        }
+    }
+
+    fn block(&mut self) -> io::Result<Block> {
+        let id = self.u8()?;
+        Block::from_id(id).ok_or_else(|| invalid_data(format!("Unknown block id {}", id)))
+    }
+
+    fn bytes(&mut self) -> io::Result<&'a [u8]> {
+        let len = self.u32()? as usize;
+        self.slice(len)
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        String::from_utf8(self.bytes()?.to_vec()).map_err(|_| invalid_data("String isn't UTF-8"))
+    }
+
+    /// Fails if anything's left over, which means the message was misread
+    fn finish(self) -> io::Result<()> {
+        if self.bytes.is_empty() {
+            Ok(())
+        } else {
+            Err(invalid_data("Message has trailing bytes"))
+        }
+    }
+}
+
+impl ClientMessage {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer(vec![]);
+
+        match self {
+            ClientMessage::Hello { version, name } => {
+                writer.u8(0);
+                writer.u16(*version);
+                writer.string(name);
+            }
+            ClientMessage::Move {
+                translation,
+                rotation,
+            } => {
+                writer.u8(1);
+                writer.vec3(*translation);
+                writer.quat(*rotation);
+            }
+            ClientMessage::RequestChunks(chunk_ids) => {
+                writer.u8(2);
+                writer.u32(chunk_ids.len() as u32);
+                for chunk_id in chunk_ids {
+                    writer.ivec3(*chunk_id);
+                }
+            }
+            ClientMessage::SetBlock { pos, block } => {
+                writer.u8(3);
+                writer.ivec3(*pos);
+                writer.u8(block.id());
+            }
+            ClientMessage::Goodbye => writer.u8(4),
+            ClientMessage::Chat(text) => {
+                writer.u8(5);
+                writer.string(text);
+            }
+        }
+
+        writer.0
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let mut reader = Reader { bytes };
+
+        let message = match reader.u8()? {
+            0 => ClientMessage::Hello {
+                version: reader.u16()?,
+                name: reader.string()?,
+            },
+            1 => ClientMessage::Move {
+                translation: reader.vec3()?,
+                rotation: reader.quat()?,
+            },
+            2 => {
+                let len = reader.u32()? as usize;
+                if len > MAX_CHUNK_REQUESTS {
+                    return Err(invalid_data("Too many chunks requested"));
+                }
+
+                ClientMessage::RequestChunks(
+                    (0..len)
+                        .map(|_| reader.ivec3())
+                        .collect::<io::Result<_>>()?,
+                )
+            }
+            3 => ClientMessage::SetBlock {
+                pos: reader.ivec3()?,
+                block: reader.block()?,
+            },
+            4 => ClientMessage::Goodbye,
+            5 => ClientMessage::Chat(reader.string()?),
+            kind => return Err(invalid_data(format!("Unknown client message {}", kind))),
+        };
+
+        reader.finish()?;
+        Ok(message)
+    }
+}
+
+impl ServerMessage {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer(vec![]);
+
+        match self {
+            ServerMessage::Welcome { player } => {
+                writer.u8(0);
+                writer.u32(*player);
+            }
+            ServerMessage::Chunk { id, data } => {
+                writer.u8(1);
+                writer.ivec3(*id);
+                writer.bytes(data);
+            }
+            ServerMessage::BlockChanged { pos, block } => {
+                writer.u8(2);
+                writer.ivec3(*pos);
+                writer.u8(block.id());
+            }
+            ServerMessage::Players(players) => {
+                writer.u8(3);
+                writer.u32(players.len() as u32);
+                for (player, name) in players {
+                    writer.u32(*player);
+                    writer.string(name);
+                }
+            }
+            ServerMessage::PlayerMoved {
+                player,
+                translation,
+                rotation,
+            } => {
+                writer.u8(4);
+                writer.u32(*player);
+                writer.vec3(*translation);
+                writer.quat(*rotation);
+            }
+            ServerMessage::Chat(line) => {
+                writer.u8(5);
+                writer.string(line);
+            }
+        }
+
+        writer.0
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let mut reader = Reader { bytes };
+
+        let message = match reader.u8()? {
+            0 => ServerMessage::Welcome {
+                player: reader.u32()?,
+            },
+            1 => ServerMessage::Chunk {
+                id: reader.ivec3()?,
+                data: reader.bytes()?.to_vec(),
+            },
+            2 => ServerMessage::BlockChanged {
+                pos: reader.ivec3()?,
+                block: reader.block()?,
+            },
+            3 => {
+                let len = reader.u32()?;
+                ServerMessage::Players(
+                    (0..len)
+                        .map(|_| Ok((reader.u32()?, reader.string()?)))
+                        .collect::<io::Result<_>>()?,
+                )
+            }
+            4 => ServerMessage::PlayerMoved {
+                player: reader.u32()?,
+                translation: reader.vec3()?,
+                rotation: reader.quat()?,
+            },
+            5 => ServerMessage::Chat(reader.string()?),
+            kind => return Err(invalid_data(format!("Unknown server message {}", kind))),
+        };
+
+        reader.finish()?;
+        Ok(message)
+    }
+}
+
+/// Deflates a chunk's blocks, which shrinks most chunks to a few hundred bytes
+pub fn compress_chunk(chunk: &Chunk) -> Vec<u8> {
+    let mut encoder = DeflateEncoder::new(vec![], Compression::default());
+    encoder
+        .write_all(&chunk.to_bytes())
+        .and_then(|()| encoder.finish())
+        .expect("Writing to a Vec can't fail")
+}
+
+pub fn decompress_chunk(id: IVec3, data: &[u8]) -> io::Result<Chunk> {
+    let mut bytes = vec![];
+    // Reading one byte too many catches chunks that are too big, without inflating all of them
+    DeflateDecoder::new(data)
+        .take(CHUNK_VOLUME as u64 + 1)
+        .read_to_end(&mut bytes)?;
+
+    Chunk::from_bytes(id, &bytes).ok_or_else(|| invalid_data("Chunk is corrupt"))
+}
+
+/// Reads every datagram waiting on a non-blocking socket
+fn receive_all(socket: &UdpSocket) -> Vec<(Vec<u8>, SocketAddr)> {
+    let mut datagrams = vec![];
+    let mut buf = vec![0; MAX_DATAGRAM];
+
+    loop {
+        match socket.recv_from(&mut buf) {
+            Ok((len, addr)) => datagrams.push((buf[..len].to_vec(), addr)),
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+            // Windows reports when an earlier datagram couldn't be delivered
+            Err(err) if err.kind() == io::ErrorKind::ConnectionReset => continue,
+            Err(err) => {
+                error!("Failed to receive: {}", err);
+                break;
+            }
+        }
+    }
+
+    datagrams
+}
+
+/// Lets clients connect over UDP, streaming chunks around them
+/// and applying their edits after checking them.
+/// Chat is logged to the save directory, and `/` runs console commands.
+///
+/// Needs `WorldSimPlugin`, and panics if it can't bind to `addr`.
+/// Changed chunks are found with `World::invalid_meshes`, so it can't be used alongside meshing.
+pub struct NetServerPlugin {
+    pub addr: SocketAddr,
+}
+
+impl Plugin for NetServerPlugin {
+    fn build(&self, app: &mut App) {
+        let server = NetServer::bind(self.addr)
+            .unwrap_or_else(|err| panic!("Failed to bind to {}: {}", self.addr, err));
+        info!("Listening on {}", self.addr);
+        let chat_log = ChatLog::load(app.world.resource::<SaveDir>().chat_log_path());
+
+        app.insert_resource(server)
+            .insert_resource(chat_log)
+            .add_event::<BlockEdited>()
+            .add_systems(
+                Update,
+                (
+                    NetServer::receive,
+                    NetServer::run_commands,
+                    NetServer::drop_timed_out,
+                    NetServer::send_players,
+                    NetServer::send_changed_chunks,
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// A connected client
+struct RemoteClient {
+    player: u32,
+    name: String,
+    /// Loads chunks around the player, and is where the server thinks they are
+    viewer: Entity,
+    last_heard: Duration,
+    /// When their last move was accepted, None until their first move, which can be anywhere
+    moved: Option<Duration>,
+    /// The most the player could be carrying, starting with the same kit as the client.
+    ///
+    /// Blocks they break are added whether or not they pick up the drops,
+    /// and crafting happens on the client, so crafted blocks can't be placed on a server.
+    inventory: Inventory,
+}
+
+#[derive(Resource)]
+pub struct NetServer {
+    socket: UdpSocket,
+    clients: HashMap<SocketAddr, RemoteClient>,
+    next_player: u32,
+    /// Set when someone joins or leaves, so everyone's told straight away
+    players_changed: bool,
+    players_sent: Option<Duration>,
+    /// Commands sent in chat, which are run with exclusive access to the world
+    commands: Vec<(SocketAddr, String)>,
+}
+
+impl NetServer {
+    pub fn bind(addr: SocketAddr) -> io::Result<Self> {
+        let socket = UdpSocket::bind(addr)?;
+        socket.set_nonblocking(true)?;
+
+        Ok(Self {
+            socket,
+            clients: HashMap::new(),
+            next_player: 0,
+            players_changed: false,
+            players_sent: None,
+            commands: vec![],
+        })
+    }
+
+    /// The address actually bound to, useful when binding to port 0
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    pub fn player_count(&self) -> usize {
+        self.clients.len()
+    }
+
+    fn send(&self, addr: SocketAddr, message: &ServerMessage) {
+        if let Err(err) = self.socket.send_to(&message.to_bytes(), addr) {
+            warn!("Failed to send to {}: {}", addr, err);
+        }
+    }
+
+    fn broadcast(&self, message: &ServerMessage) {
+        for addr in self.clients.keys() {
+            self.send(*addr, message);
+        }
+    }
+
+    /// Sends a message to everyone but `except`
+    fn broadcast_except(&self, except: SocketAddr, message: &ServerMessage) {
+        for addr in self.clients.keys().filter(|addr| **addr != except) {
+            self.send(*addr, message);
+        }
+    }
+
+    /// Sends a line of chat to everyone, and logs it
+    fn say(&self, chat_log: &mut ChatLog, line: String) {
+        info!("{}", line);
+        self.broadcast(&ServerMessage::Chat(line.clone()));
+        chat_log.push(line);
+    }
+
+    /// Whether a player at `player_pos` carrying `inventory` may set `pos` to `block`,
+    /// which is either breaking a block or placing one they have into air
+    pub fn valid_edit(
+        world: &World,
+        player_pos: Vec3,
+        inventory: &Inventory,
+        pos: IVec3,
+        block: Block,
+    ) -> bool {
+        let Some(old) = world.get_block(pos) else { return false; };
+
+        let allowed = if block == Block::Air {
+            old != Block::Air
+        } else {
+            old == Block::Air && inventory.count(block) > 0
+        };
+
+        allowed && (pos.as_vec3() + 0.5).distance(player_pos) <= MAX_REACH
+    }
+
+    /// Whether a player could have moved from `from` to `to` in `elapsed`
+    pub fn valid_move(from: Vec3, to: Vec3, elapsed: Duration) -> bool {
+        from.distance(to) <= MAX_SPEED * elapsed.as_secs_f32() + MOVE_SLACK
+    }
+
+    /// Sends the chunk, along with any loaded chunks stacked above or below it
+    fn send_column(&self, addr: SocketAddr, world: &World, chunk_id: IVec3) {
+        for (id, chunk) in &world.chunks {
+            if id.x == chunk_id.x && id.z == chunk_id.z {
+                self.send(
+                    addr,
+                    &ServerMessage::Chunk {
+                        id: *id,
+                        data: compress_chunk(chunk),
+                    },
+                );
+            }
+        }
+    }
+
+    pub fn receive(
+        mut commands: Commands,
+        mut server: ResMut<NetServer>,
+        mut world: ResMut<World>,
+        mut viewers: Query<&mut Transform, With<ChunkViewer>>,
+        mut chat_log: ResMut<ChatLog>,
+        mut edited: EventWriter<BlockEdited>,
+        time: Res<Time>,
+    ) {
+        for (bytes, addr) in receive_all(&server.socket) {
+            let message = match ClientMessage::from_bytes(&bytes) {
+                Ok(message) => message,
+                Err(err) => {
+                    warn!("Bad message from {}: {}", addr, err);
+                    continue;
+                }
+            };
+
+            if let ClientMessage::Hello { version, name } = message {
+                if version != PROTOCOL_VERSION {
+                    warn!(
+                        "{} uses protocol version {}, not {}",
+                        addr, version, PROTOCOL_VERSION
+                    );
+                    continue;
+                }
+
+                // Welcomes can be lost, so clients keep saying hello until they get one
+                if !server.clients.contains_key(&addr) {
+                    let player = server.next_player;
+                    server.next_player += 1;
+
+                    let name = name.trim().chars().take(MAX_NAME_LEN).collect::<String>();
+                    let name = if name.is_empty() {
+                        format!("Player {}", player)
+                    } else {
+                        name
+                    };
+
+                    let viewer = commands.spawn((Transform::default(), ChunkViewer)).id();
+                    info!("{} joined from {} as player {}", name, addr, player);
+                    server.clients.insert(
+                        addr,
+                        RemoteClient {
+                            player,
+                            name,
+                            viewer,
+                            last_heard: time.elapsed(),
+                            moved: None,
+                            inventory: Inventory::starting_kit(),
+                        },
+                    );
+                    server.players_changed = true;
+
+                    for line in chat_log.recent() {
+                        server.send(addr, &ServerMessage::Chat(line.to_string()));
+                    }
+                    let line = format!("{} joined", server.clients[&addr].name);
+                    server.say(&mut chat_log, line);
+                }
+
+                let player = server.clients[&addr].player;
+                server.send(addr, &ServerMessage::Welcome { player });
+                continue;
+            }
+
+            let Some(client) = server.clients.get_mut(&addr) else { continue; };
+            client.last_heard = time.elapsed();
+            let (player, viewer) = (client.player, client.viewer);
+
+            match message {
+                ClientMessage::Hello { .. } => unreachable!(),
+                ClientMessage::Move {
+                    translation,
+                    rotation,
+                } => {
+                    let Ok(mut transform) = viewers.get_mut(viewer) else { continue; };
+                    let client = server.clients.get_mut(&addr).unwrap();
+                    let now = time.elapsed();
+
+                    // Too fast to be real, so they stay where they last were
+                    if client.moved.is_some_and(|moved| {
+                        !Self::valid_move(transform.translation, translation, now - moved)
+                    }) {
+                        continue;
+                    }
+
+                    client.moved = Some(now);
+                    *transform = Transform::from_translation(translation).with_rotation(rotation);
+                    server.broadcast_except(
+                        addr,
+                        &ServerMessage::PlayerMoved {
+                            player,
+                            translation,
+                            rotation,
+                        },
+                    );
+                }
+                ClientMessage::RequestChunks(chunk_ids) => {
+                    for chunk_id in chunk_ids {
+                        server.send_column(addr, &world, chunk_id);
+                    }
+                }
+                ClientMessage::SetBlock { pos, block } => {
+                    let player_pos = viewers
+                        .get(viewer)
+                        .map_or(Vec3::ZERO, |transform| transform.translation);
+                    let inventory = &mut server.clients.get_mut(&addr).unwrap().inventory;
+
+                    if Self::valid_edit(&world, player_pos, inventory, pos, block) {
+                        // Players light the edit themselves, so only the block is sent,
+                        // not every chunk its light reached
+                        let invalid = world.invalid_meshes.len();
+                        let old = world.set_block(pos, block).unwrap();
+                        world.invalid_meshes.truncate(invalid);
+                        if block == Block::Air {
+                            inventory.add(old, 1);
+                        } else {
+                            inventory.remove(block, 1);
+                        }
+
+                        server.broadcast(&ServerMessage::BlockChanged { pos, block });
+                        edited.send(BlockEdited {
+                            pos,
+                            old,
+                            new: block,
+                        });
+                    } else if let Some(block) = world.get_block(pos) {
+                        // Puts the player back in sync, in case they'd missed an update
+                        server.send(addr, &ServerMessage::BlockChanged { pos, block });
+                    }
+                }
+                ClientMessage::Goodbye => {
+                    if let Some(client) = server.clients.remove(&addr) {
+                        server.say(&mut chat_log, format!("{} left", client.name));
+                    }
+                    commands.entity(viewer).despawn();
+                    server.players_changed = true;
+                }
+                ClientMessage::Chat(text) => {
+                    let text = clean_chat(&text);
+
+                    if let Some(command) = text.strip_prefix('/') {
+                        server.commands.push((addr, command.to_string()));
+                    } else if !text.is_empty() {
+                        let line = format!("<{}> {}", server.clients[&addr].name, text);
+                        server.say(&mut chat_log, line);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Runs the commands players sent in chat, replying with the output
+    pub fn run_commands(world: &mut EcsWorld) {
+        let queued = std::mem::take(&mut world.resource_mut::<NetServer>().commands);
+        if queued.is_empty() {
+            return;
+        }
+
+        world.resource_scope(|world, commands: Mut<ConsoleCommands>| {
+            for (addr, line) in queued {
+                let server = world.resource::<NetServer>();
+                let Some(client) = server.clients.get(&addr) else { continue; };
+                info!("{} ran /{}", client.name, line);
+                let viewer = client.viewer;
+                let player = world
+                    .get::<Transform>(viewer)
+                    .map_or(Vec3::ZERO, |transform| transform.translation);
+
+                let output = match commands.run_remote(world, &line, player) {
+                    Ok(output) => output,
+                    Err(err) => format!("Error: {}", err),
+                };
+
+                let server = world.resource::<NetServer>();
+                for line in output.lines() {
+                    server.send(addr, &ServerMessage::Chat(line.to_string()));
+                }
+            }
+        });
+    }
+
+    pub fn drop_timed_out(
+        mut commands: Commands,
+        mut server: ResMut<NetServer>,
+        mut chat_log: ResMut<ChatLog>,
+        time: Res<Time>,
+    ) {
+        let now = time.elapsed();
+
+        let mut timed_out = vec![];
+        server.clients.retain(|addr, client| {
+            let alive = now.saturating_sub(client.last_heard) < TIMEOUT;
+            if !alive {
+                commands.entity(client.viewer).despawn();
+                info!("{} at {} timed out", client.name, addr);
+                timed_out.push(client.name.clone());
+            }
+            alive
+        });
+
+        for name in timed_out {
+            server.say(&mut chat_log, format!("{} left", name));
+            server.players_changed = true;
+        }
+    }
+
+    /// Tells everyone who's connected when that changes, and every so often in case they missed it
+    pub fn send_players(mut server: ResMut<NetServer>, time: Res<Time>) {
+        let now = time.elapsed();
+        let due = server
+            .players_sent
+            .is_none_or(|players_sent| now.saturating_sub(players_sent) >= PLAYERS_INTERVAL);
+        if !server.players_changed && !due {
+            return;
+        }
+        server.players_changed = false;
+        server.players_sent = Some(now);
+
+        let mut players = server
+            .clients
+            .values()
+            .map(|client| (client.player, client.name.clone()))
+            .collect::<Vec<_>>();
+        players.sort();
+        server.broadcast(&ServerMessage::Players(players));
+    }
+
+    /// Sends every chunk that's changed, such as by commands, to the players near it.
+    ///
+    /// Nothing is meshed on a server, so the chunks waiting to be meshed are the ones that changed.
+    pub fn send_changed_chunks(
+        server: Res<NetServer>,
+        mut world: ResMut<World>,
+        viewers: Query<&Transform, With<ChunkViewer>>,
+        view_distance: Res<ViewDistance>,
+    ) {
+        if world.invalid_meshes.is_empty() {
+            return;
+        }
+        let mut changed = std::mem::take(&mut world.invalid_meshes);
+        changed.sort_by_key(|chunk_id| chunk_id.to_array());
+        changed.dedup();
+
+        let radius = view_distance.chunks + 1;
+        for (addr, client) in &server.clients {
+            let Ok(transform) = viewers.get(client.viewer) else { continue; };
+            let centre = split_pos(transform.translation.floor().as_ivec3()).0;
+
+            for chunk_id in &changed {
+                if distance_squared(&[centre], *chunk_id) > radius * radius {
+                    continue;
+                }
+                let Some(chunk) = world.chunks.get(chunk_id) else { continue; };
+
+                server.send(
+                    *addr,
+                    &ServerMessage::Chunk {
+                        id: *chunk_id,
+                        data: compress_chunk(chunk),
+                    },
+                );
+            }
+        }
+    }
+}
+
+/// Connects to a `NetServerPlugin` over UDP, taking chunks from the server instead of generating them
+/// and sending `BlockEdit`s to it instead of applying them.
+/// Blocks the server changes are sent on as `BlockEdited`s, even when a chunk with the change arrived first,
+/// in which case `old` is the same as `new`.
+/// Other players are spawned as `RemotePlayer`s, which `AvatarPlugin` draws.
+///
+/// Drops aren't shared with the server, so leave out `DropsPlugin`,
+/// or players would pick up blocks the server doesn't think they have.
+/// Blocks we break go straight into the `ChunkViewer`'s `Inventory` instead, as they do on the server.
+/// Crafting isn't shared either, so leave out `CraftingPlugin` too.
+///
+/// Needs a `World`, a `ViewDistance` and a `ChunkViewer`, as set up by `VoxelWorldPlugin` with `remote` set.
+pub struct NetClientPlugin {
+    pub server: SocketAddr,
+    /// Shown above our avatar to other players
+    pub name: String,
+}
+
+impl Plugin for NetClientPlugin {
+    fn build(&self, app: &mut App) {
+        let client = NetClient::connect(self.server, self.name.clone())
+            .unwrap_or_else(|err| panic!("Failed to connect to {}: {}", self.server, err));
+
+        app.insert_resource(client)
+            .add_event::<BlockEdit>()
+            .add_event::<BlockEdited>()
+            .add_event::<SendChat>()
+            .add_event::<ChatReceived>()
+            .add_systems(
+                Update,
+                (
+                    NetClient::receive,
+                    NetClient::collect_broken,
+                    NetClient::send_position,
+                    NetClient::request_chunks,
+                    NetClient::unload_chunks,
+                    NetClient::send_edits,
+                    NetClient::send_chat,
+                    avatar::interpolate,
+                )
+                    .chain()
+                    .before(world_mesh_gen),
+            )
+            .add_systems(Last, NetClient::say_goodbye);
+    }
+}
+
+#[derive(Resource)]
+pub struct NetClient {
+    socket: UdpSocket,
+    name: String,
+    /// Set once the server has welcomed us
+    player: Option<u32>,
+    /// When each missing chunk was last asked for
+    requested: HashMap<IVec3, Duration>,
+    last_sent: Option<Duration>,
+    /// Blocks we've asked to break and what was there, which go in our inventory once they're broken
+    breaking: Vec<(IVec3, Block)>,
+}
+
+impl NetClient {
+    pub fn connect(server: SocketAddr, name: String) -> io::Result<Self> {
+        let any: SocketAddr = if server.is_ipv4() {
+            ([0, 0, 0, 0], 0).into()
+        } else {
+            ([0; 8], 0).into()
+        };
+
+        let socket = UdpSocket::bind(any)?;
+        socket.connect(server)?;
+        socket.set_nonblocking(true)?;
+
+        Ok(Self {
+            socket,
+            name,
+            player: None,
+            requested: HashMap::new(),
+            last_sent: None,
+            breaking: vec![],
+        })
+    }
+
+    /// The server's id for us, or None until we're connected
+    pub fn player(&self) -> Option<u32> {
+        self.player
+    }
+
+    fn send(&self, message: &ClientMessage) {
+        if let Err(err) = self.socket.send(&message.to_bytes()) {
+            warn!("Failed to send to the server: {}", err);
+        }
+    }
+
+    pub fn receive(
+        mut commands: Commands,
+        mut client: ResMut<NetClient>,
+        mut world: ResMut<World>,
+        mut players: Query<(Entity, &RemotePlayer, &mut Snapshots)>,
+        mut chat: EventWriter<ChatReceived>,
+        mut edited: EventWriter<BlockEdited>,
+        time: Res<Time>,
+    ) {
+        let mut loaded = vec![];
+        for (bytes, _) in receive_all(&client.socket) {
+            let message = match ServerMessage::from_bytes(&bytes) {
+                Ok(message) => message,
+                Err(err) => {
+                    warn!("Bad message from the server: {}", err);
+                    continue;
+                }
+            };
+
+            match message {
+                ServerMessage::Welcome { player } => {
+                    if client.player.is_none() {
+                        info!("Joined as player {}", player);
+                    }
+                    client.player = Some(player);
+                }
+                ServerMessage::Chunk { id, data } => {
+                    // Any we've moved away from since asking for them are unloaded again later this frame
+                    client.requested.remove(&IVec3::new(id.x, 0, id.z));
+
+                    match decompress_chunk(id, &data) {
+                        Ok(chunk) => {
+                            world.chunks.insert(id, chunk);
+                            world.invalidate_mesh(id);
+                            loaded.push(id);
+                        }
+                        Err(err) => warn!("Chunk {} from the server is corrupt: {}", id, err),
+                    }
+                }
+                ServerMessage::BlockChanged { pos, block } => {
+                    if let Some(old) = world.set_block(pos, block) {
+                        edited.send(BlockEdited {
+                            pos,
+                            old,
+                            new: block,
+                        });
+                    }
+                }
+                ServerMessage::Players(connected) => {
+                    for (entity, player, _) in &players {
+                        if !connected.contains(&(player.id, player.name.clone())) {
+                            commands.entity(entity).despawn_recursive();
+                        }
+                    }
+
+                    for (id, name) in connected {
+                        let known = players
+                            .iter()
+                            .any(|(_, player, _)| player.id == id && player.name == name);
+                        if Some(id) != client.player && !known {
+                            commands.spawn((
+                                RemotePlayer { id, name },
+                                Snapshots::default(),
+                                SpatialBundle::default(),
+                            ));
+                        }
+                    }
+                }
+                ServerMessage::PlayerMoved {
+                    player,
+                    translation,
+                    rotation,
+                } => {
+                    if let Some((_, _, mut snapshots)) = players
+                        .iter_mut()
+                        .find(|(_, remote, _)| remote.id == player)
+                    {
+                        snapshots.push(Snapshot {
+                            time: time.elapsed(),
+                            translation,
+                            rotation,
+                        });
+                    }
+                }
+                ServerMessage::Chat(line) => chat.send(ChatReceived(line)),
+            }
+        }
+
+        if !loaded.is_empty() {
+            light::relight_chunks(&mut world, &loaded);
+        }
+    }
+
+    /// Says hello until we're welcomed, then sends where the camera is
+    pub fn send_position(
+        mut client: ResMut<NetClient>,
+        query: Query<&Transform, With<ChunkViewer>>,
+        time: Res<Time>,
+    ) {
+        let now = time.elapsed();
+        if client
+            .last_sent
+            .is_some_and(|last_sent| now.saturating_sub(last_sent) < SEND_INTERVAL)
+        {
+            return;
+        }
+        client.last_sent = Some(now);
+
+        if client.player.is_none() {
+            client.send(&ClientMessage::Hello {
+                version: PROTOCOL_VERSION,
+                name: client.name.clone(),
+            });
+        } else if let Ok(transform) = query.get_single() {
+            client.send(&ClientMessage::Move {
+                translation: transform.translation,
+                rotation: transform.rotation,
+            });
+        }
+    }
+
+    /// Asks for the closest missing chunks, and asks again for any that never arrived
+    pub fn request_chunks(
+        mut client: ResMut<NetClient>,
+        world: Res<World>,
+        query: Query<&Transform, With<ChunkViewer>>,
+        view_distance: Res<ViewDistance>,
+        time: Res<Time>,
+    ) {
+        if client.player.is_none() {
+            return;
+        }
+        let Ok(transform) = query.get_single() else { return; };
+        let centre = split_pos(transform.translation.floor().as_ivec3()).0;
+        let now = time.elapsed();
+
+        let mut missing = chunks_around(centre, view_distance.chunks)
+            .filter(|chunk_id| !world.chunks.contains_key(chunk_id))
+            .filter(|chunk_id| {
+                client
+                    .requested
+                    .get(chunk_id)
+                    .is_none_or(|requested| now.saturating_sub(*requested) >= REQUEST_TIMEOUT)
+            })
+            .collect::<Vec<_>>();
+        if missing.is_empty() {
+            return;
+        }
+
+        missing.sort_by_key(|chunk_id| distance_squared(&[centre], *chunk_id));
+        missing.truncate(MAX_CHUNK_REQUESTS);
+
+        for chunk_id in &missing {
+            client.requested.insert(*chunk_id, now);
+        }
+        client.send(&ClientMessage::RequestChunks(missing));
+    }
+
+    pub fn unload_chunks(
+        mut commands: Commands,
+        mut world: ResMut<World>,
+        query: Query<&Transform, With<ChunkViewer>>,
+        view_distance: Res<ViewDistance>,
+    ) {
+        let Ok(transform) = query.get_single() else { return; };
+        let centre = split_pos(transform.translation.floor().as_ivec3()).0;
+        let radius = view_distance.chunks + 1;
+
+        let far = world
+            .chunks
+            .keys()
+            .copied()
+            .filter(|chunk_id| distance_squared(&[centre], *chunk_id) > radius * radius)
+            .collect::<Vec<_>>();
+
+        for chunk_id in far {
+            if let Some((_, Some(entity))) = world.unload_chunk(chunk_id) {
+                commands.entity(entity).despawn();
+            }
+        }
+    }
+
+    /// The server applies the edits and sends them back, so they aren't applied here
+    pub fn send_edits(
+        mut client: ResMut<NetClient>,
+        world: Res<World>,
+        mut edits: EventReader<BlockEdit>,
+    ) {
+        for &BlockEdit { pos, block } in edits.iter() {
+            client.send(&ClientMessage::SetBlock { pos, block });
+
+            let old = world.get_block(pos).unwrap_or(Block::Air);
+            if block == Block::Air && old != Block::Air {
+                if client.breaking.len() == MAX_BREAKING {
+                    client.breaking.remove(0);
+                }
+                client.breaking.push((pos, old));
+            }
+        }
+    }
+
+    /// Adds the blocks we broke to the viewer's inventory once the server's broken them,
+    /// as it does to its own copy of our inventory
+    pub fn collect_broken(
+        mut client: ResMut<NetClient>,
+        mut edited: EventReader<BlockEdited>,
+        mut inventories: Query<&mut Inventory, With<ChunkViewer>>,
+    ) {
+        for edit in edited.iter().filter(|edit| edit.new == Block::Air) {
+            let found = client.breaking.iter().position(|(pos, _)| *pos == edit.pos);
+            let Some(i) = found else { continue; };
+            let (_, block) = client.breaking.remove(i);
+
+            if let Ok(mut inventory) = inventories.get_single_mut() {
+                inventory.add(block, 1);
+            }
+        }
+    }
+
+    pub fn send_chat(client: Res<NetClient>, mut chat: EventReader<SendChat>) {
+        for SendChat(text) in chat.iter() {
+            client.send(&ClientMessage::Chat(text.clone()));
+        }
+    }
+
+    pub fn say_goodbye(client: Res<NetClient>, mut exit: EventReader<AppExit>) {
+        if exit.iter().next().is_some() && client.player.is_some() {
+            client.send(&ClientMessage::Goodbye);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, thread, time::Instant};
+
+    use super::*;
+    use crate::{chunk::TerrainGen, console::ConsoleCommandsPlugin, WorldConfig, WorldSimPlugin};
+
+    #[test]
+    fn messages_round_trip() {
+        let mut chunk = Chunk::new(IVec3::new(1, -2, 3));
+        chunk.generate(&TerrainGen::default());
+
+        let client_messages = [
+            ClientMessage::Hello {
+                version: PROTOCOL_VERSION,
+                name: "Über".to_string(),
+            },
+            ClientMessage::Move {
+                translation: Vec3::new(1.5, -2., 300.25),
+                rotation: Quat::from_rotation_y(1.),
+            },
+            ClientMessage::RequestChunks(vec![IVec3::ZERO, IVec3::new(-5, 0, 7)]),
+            ClientMessage::SetBlock {
+                pos: IVec3::new(-1, 2, -3),
+                block: Block::Torch,
+            },
+            ClientMessage::Chat("/help".to_string()),
+            ClientMessage::Goodbye,
+        ];
+        for message in client_messages {
+            let bytes = message.to_bytes();
+            assert_eq!(ClientMessage::from_bytes(&bytes).unwrap(), message);
+            assert!(ClientMessage::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        }
+
+        let server_messages = [
+            ServerMessage::Welcome { player: 7 },
+            ServerMessage::Chunk {
+                id: chunk.id(),
+                data: compress_chunk(&chunk),
+            },
+            ServerMessage::BlockChanged {
+                pos: IVec3::new(4, 5, 6),
+                block: Block::Lava,
+            },
+            ServerMessage::Players(vec![(0, "one".to_string()), (3, "two".to_string())]),
+            ServerMessage::PlayerMoved {
+                player: 3,
+                translation: Vec3::NEG_ONE,
+                rotation: Quat::IDENTITY,
+            },
+            ServerMessage::Chat("<one> hi".to_string()),
+        ];
+        for message in server_messages {
+            let bytes = message.to_bytes();
+            assert_eq!(ServerMessage::from_bytes(&bytes).unwrap(), message);
+            assert!(ServerMessage::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        }
+
+        let data = compress_chunk(&chunk);
+        assert!(data.len() < CHUNK_VOLUME / 4);
+        assert_eq!(
+            decompress_chunk(chunk.id(), &data).unwrap().to_bytes(),
+            chunk.to_bytes()
+        );
+        assert!(ClientMessage::from_bytes(&[9]).is_err());
+    }
+
+    fn view_distance() -> ViewDistance {
+        ViewDistance {
+            chunks: 1,
+            per_frame: 8,
+        }
+    }
+
+    fn server_app(save_dir: PathBuf) -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugins(WorldSimPlugin {
+                config: WorldConfig {
+                    save_dir,
+                    view_distance: view_distance(),
+                    ..default()
+                },
+            })
+            .add_plugins(ConsoleCommandsPlugin)
+            .add_plugins(NetServerPlugin {
+                addr: ([127, 0, 0, 1], 0).into(),
+            });
+        app
+    }
+
+    /// Every line of chat a client has received
+    #[derive(Resource, Default)]
+    struct Received(Vec<String>);
+
+    fn collect_chat(mut received: ResMut<Received>, mut chat: EventReader<ChatReceived>) {
+        received
+            .0
+            .extend(chat.iter().map(|ChatReceived(line)| line.clone()));
+    }
+
+    fn received(app: &App, line: &str) -> bool {
+        app.world
+            .resource::<Received>()
+            .0
+            .iter()
+            .any(|received| received == line)
+    }
+
+    fn client_app(server: SocketAddr, name: &str) -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .insert_resource(World::new())
+            .insert_resource(view_distance())
+            .add_plugins(NetClientPlugin {
+                server,
+                name: name.to_string(),
+            })
+            .init_resource::<Received>()
+            .add_systems(Update, collect_chat);
+        app.world.spawn((Transform::default(), ChunkViewer));
+        app
+    }
+
+    /// Updates every app until `done` or a few seconds pass
+    fn update_until(apps: &mut [App], mut done: impl FnMut(&mut [App]) -> bool) {
+        let start = Instant::now();
+
+        while !done(apps) {
+            assert!(start.elapsed() < Duration::from_secs(5), "Timed out");
+
+            for app in apps.iter_mut() {
+                app.update();
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn clients_share_a_world_over_localhost() {
+        let save_dir = std::env::temp_dir().join("bevy_craft_net_test");
+        let server = server_app(save_dir.clone());
+        let addr = server.world.resource::<NetServer>().local_addr().unwrap();
+        let mut one = client_app(addr, "one");
+        let mut viewers = one.world.query_filtered::<Entity, With<ChunkViewer>>();
+        let viewer = viewers.single(&one.world);
+        one.world.entity_mut(viewer).insert(Inventory::default());
+        let mut apps = vec![server, one, client_app(addr, "two")];
+
+        // The 5 chunks within 1 of the origin
+        update_until(&mut apps, |apps| {
+            apps[1..]
+                .iter()
+                .all(|client| client.world.resource::<World>().chunks.len() == 5)
+        });
+        assert_eq!(apps[0].world.resource::<NetServer>().player_count(), 2);
+        assert!(apps[1..].iter().all(|client| client
+            .world
+            .resource::<NetClient>()
+            .player()
+            .is_some()));
+
+        let pos = IVec3::new(1, 15, 1);
+        apps[1].world.send_event(BlockEdit {
+            pos,
+            block: Block::Torch,
+        });
+        update_until(&mut apps, |apps| {
+            apps.iter()
+                .all(|app| app.world.resource::<World>().get_block(pos) == Some(Block::Torch))
+        });
+
+        // Broken blocks go to whoever broke them, once everyone sees them broken
+        apps[1].world.send_event(BlockEdit {
+            pos,
+            block: Block::Air,
+        });
+        update_until(&mut apps, |apps| {
+            apps.iter()
+                .all(|app| app.world.resource::<World>().get_block(pos) == Some(Block::Air))
+        });
+        let inventory = apps[1].world.get::<Inventory>(viewer).unwrap();
+        assert_eq!(inventory.count(Block::Torch), 1);
+
+        let _ = std::fs::remove_dir_all(&save_dir);
+    }
+
+    #[test]
+    fn block_changes_are_sent_on_even_if_already_there() {
+        let fake_server = UdpSocket::bind(("127.0.0.1", 0)).unwrap();
+        let mut client = client_app(fake_server.local_addr().unwrap(), "one");
+        client
+            .world
+            .resource_mut::<World>()
+            .chunks
+            .insert(IVec3::ZERO, Chunk::new(IVec3::ZERO));
+        client.update();
+        let (_, client_addr) = fake_server.recv_from(&mut [0; MAX_DATAGRAM]).unwrap();
+
+        // Sent until it's been received twice, the second time with the torch already there,
+        // as when a chunk with the change in it arrives first
+        let changed = ServerMessage::BlockChanged {
+            pos: IVec3::ONE,
+            block: Block::Torch,
+        };
+        let mut reader = client.world.resource::<Events<BlockEdited>>().get_reader();
+        let mut edited = vec![];
+        update_until(std::slice::from_mut(&mut client), |apps| {
+            fake_server
+                .send_to(&changed.to_bytes(), client_addr)
+                .unwrap();
+            edited.extend(
+                reader
+                    .iter(apps[0].world.resource::<Events<BlockEdited>>())
+                    .copied(),
+            );
+            edited.len() >= 2
+        });
+
+        let torch = |old| BlockEdited {
+            pos: IVec3::ONE,
+            old,
+            new: Block::Torch,
+        };
+        assert_eq!(edited[..2], [torch(Block::Air), torch(Block::Torch)]);
+    }
+
+    #[test]
+    fn sprinting_moves_are_accepted() {
+        // Sprinting forwards, sideways and up at once, the fastest a `FlyCam` goes
+        let velocity = Vec3::ONE * MOVE_SPEED * SPRINT_MOD;
+        let second = Duration::from_secs(1);
+        assert!(NetServer::valid_move(Vec3::ZERO, velocity, second));
+
+        let save_dir = std::env::temp_dir().join("bevy_craft_sprint_test");
+        let server = server_app(save_dir.clone());
+        let addr = server.world.resource::<NetServer>().local_addr().unwrap();
+        let mut apps = vec![server, client_app(addr, "runner")];
+        update_until(&mut apps, |apps| {
+            apps[1].world.resource::<NetClient>().player().is_some()
+        });
+
+        let start = Instant::now();
+        let mut longest_wait = Duration::ZERO;
+        let mut longest_frame = Duration::ZERO;
+        while start.elapsed() < second {
+            let frame_start = Instant::now();
+            let mut viewers = apps[1]
+                .world
+                .query_filtered::<&mut Transform, With<ChunkViewer>>();
+            viewers.single_mut(&mut apps[1].world).translation =
+                velocity * start.elapsed().as_secs_f32();
+
+            for app in apps.iter_mut() {
+                app.update();
+            }
+            thread::sleep(Duration::from_millis(1));
+            longest_frame = longest_frame.max(frame_start.elapsed());
+
+            let now = apps[0].world.resource::<Time>().elapsed();
+            let server = apps[0].world.resource::<NetServer>();
+            if let Some(moved) = server.clients.values().next().unwrap().moved {
+                longest_wait = longest_wait.max(now - moved);
+            }
+        }
+
+        // Every move is taken, rather than some being turned down for being too fast,
+        // so the server hears one every send interval, give or take the frames it took to arrive
+        assert!(
+            longest_wait < SEND_INTERVAL * 2 + longest_frame * 2,
+            "Went {longest_wait:?} without a move, with frames up to {longest_frame:?}"
+        );
+
+        let _ = std::fs::remove_dir_all(&save_dir);
+    }
+
+    #[test]
+    fn edits_need_reach_and_the_block() {
+        let world = World::with_chunks([IVec3::ZERO], |pos| {
+            if pos.y == 0 {
+                Block::Stone
+            } else {
+                Block::Air
+            }
+        });
+        let inventory = Inventory::starting_kit();
+        let player = Vec3::new(8., 4., 8.);
+        let valid =
+            |player, pos, block| NetServer::valid_edit(&world, player, &inventory, pos, block);
+
+        assert!(valid(player, IVec3::new(8, 0, 8), Block::Air));
+        assert!(valid(player, IVec3::new(8, 1, 8), Block::Torch));
+
+        // Not carried, already there, or placed over another block
+        assert!(!valid(player, IVec3::new(8, 1, 8), Block::Lava));
+        assert!(!valid(player, IVec3::new(8, 1, 8), Block::Air));
+        assert!(!valid(player, IVec3::new(8, 0, 8), Block::Torch));
+
+        // Too far away to reach, or not loaded
+        assert!(!valid(player + MAX_REACH, IVec3::new(8, 0, 8), Block::Air));
+        assert!(!valid(player, IVec3::new(8, -1, 8), Block::Air));
+    }
+
+    #[test]
+    fn moves_are_limited_by_speed() {
+        let second = Duration::from_secs(1);
+        let valid = |to, elapsed| NetServer::valid_move(Vec3::ZERO, to, elapsed);
+
+        assert!(valid(Vec3::X * MAX_SPEED, second));
+        assert!(valid(Vec3::X * MOVE_SLACK, Duration::ZERO));
+        assert!(!valid(Vec3::X * MAX_SPEED, second / 2));
+        assert!(!valid(Vec3::Y * 10_000., second));
+    }
+
+    fn remote_players(app: &mut App) -> Vec<(RemotePlayer, Transform)> {
+        app.world
+            .query::<(&RemotePlayer, &Transform)>()
+            .iter(&app.world)
+            .map(|(player, transform)| (player.clone(), *transform))
+            .collect()
+    }
+
+    #[test]
+    fn players_see_each_other() {
+        let save_dir = std::env::temp_dir().join("bevy_craft_players_test");
+        let server = server_app(save_dir.clone());
+        let addr = server.world.resource::<NetServer>().local_addr().unwrap();
+        let mut apps = vec![server, client_app(addr, "one"), client_app(addr, "  two  ")];
+
+        update_until(&mut apps, |apps| {
+            apps[1..]
+                .iter_mut()
+                .all(|client| remote_players(client).len() == 1)
+        });
+        assert_eq!(remote_players(&mut apps[1])[0].0.name, "two");
+        assert_eq!(remote_players(&mut apps[2])[0].0.name, "one");
+
+        let moved = Transform::from_xyz(5., 20., -3.).looking_at(Vec3::ZERO, Vec3::Y);
+        *apps[1]
+            .world
+            .query_filtered::<&mut Transform, With<ChunkViewer>>()
+            .single_mut(&mut apps[1].world) = moved;
+        update_until(&mut apps, |apps| {
+            remote_players(&mut apps[2]).iter().all(|(_, transform)| {
+                transform.translation.abs_diff_eq(moved.translation, 1e-4)
+                    && transform.rotation.abs_diff_eq(moved.rotation, 1e-4)
+            })
+        });
+
+        // Leaving despawns their avatar for everyone else
+        apps[1].world.send_event(AppExit);
+        update_until(&mut apps, |apps| {
+            apps[0].world.resource::<NetServer>().player_count() == 1
+                && remote_players(&mut apps[2]).is_empty()
+        });
+
+        let _ = std::fs::remove_dir_all(&save_dir);
+    }
+
+    #[test]
+    fn chat_and_commands() {
+        let save_dir = std::env::temp_dir().join("bevy_craft_chat_test");
+        let _ = std::fs::remove_dir_all(&save_dir);
+        let server = server_app(save_dir.clone());
+        let addr = server.world.resource::<NetServer>().local_addr().unwrap();
+        let mut apps = vec![server, client_app(addr, "one"), client_app(addr, "two")];
+
+        update_until(&mut apps, |apps| {
+            apps[1..]
+                .iter()
+                .all(|client| client.world.resource::<World>().chunks.len() == 5)
+        });
+
+        apps[1]
+            .world
+            .send_event(SendChat("hello\nthere".to_string()));
+        update_until(&mut apps, |apps| received(&apps[2], "<one> hellothere"));
+
+        // Commands only reply to who sent them, and some can only be run by the server itself
+        let refused = "Error: regen can only be run on the server";
+        apps[2].world.send_event(SendChat("/regen".to_string()));
+        update_until(&mut apps, |apps| received(&apps[2], refused));
+        assert!(!received(&apps[1], refused));
+
+        // The rest change the world for everyone
+        let pos = IVec3::new(2, 15, 2);
+        apps[2]
+            .world
+            .send_event(SendChat("/setblock 2 15 2 stone".to_string()));
+        update_until(&mut apps, |apps| {
+            received(&apps[2], "Set [2, 15, 2] to stone")
+                && apps
+                    .iter()
+                    .all(|app| app.world.resource::<World>().get_block(pos) == Some(Block::Stone))
+        });
+        assert!(!received(&apps[1], "Set [2, 15, 2] to stone"));
+
+        let log = std::fs::read_to_string(save_dir.join("chat.log")).unwrap();
+        assert_eq!(log, "one joined\ntwo joined\n<one> hellothere\n");
+
+        // Players joining later see what was said
+        apps.push(client_app(addr, "three"));
+        update_until(&mut apps, |apps| received(&apps[3], "<one> hellothere"));
+
+        let _ = std::fs::remove_dir_all(&save_dir);
+    }
+}
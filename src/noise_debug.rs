@@ -155,11 +155,7 @@ pub fn noise_debug_system(
                 );
 
                 if regen {
-                    for (_, chunk) in &mut world.chunks {
-                        chunk.generate(&terrain_gen);
-                    }
-
-                    world.invalid_meshes = world.chunks.keys().copied().collect::<Vec<_>>();
+                    world.regenerate(&terrain_gen);
                 }
 
                 ui.label("Debug tex");
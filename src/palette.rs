@@ -0,0 +1,249 @@
+use std::mem::size_of;
+
+use crate::block::Block;
+
+/// Stores a fixed number of blocks as indices into a palette of the distinct blocks,
+/// packed into as few bits as the palette needs.
+#[derive(Clone)]
+pub enum PalettedStorage {
+    /// Every block is the same, so nothing else needs storing
+    Single { block: Block, len: usize },
+    Packed {
+        palette: Vec<Block>,
+        bits: usize,
+        data: Vec<u64>,
+        len: usize,
+    },
+}
+
+impl PalettedStorage {
+    pub fn new(len: usize, block: Block) -> Self {
+        Self::Single { block, len }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Self::Single { len, .. } | Self::Packed { len, .. } => *len,
+        }
+    }
+
+    fn words_needed(len: usize, bits: usize) -> usize {
+        let per_word = 64 / bits;
+        len.div_ceil(per_word)
+    }
+
+    fn read(data: &[u64], bits: usize, i: usize) -> usize {
+        let per_word = 64 / bits;
+        let shift = (i % per_word) * bits;
+        ((data[i / per_word] >> shift) & ((1 << bits) - 1)) as usize
+    }
+
+    fn write(data: &mut [u64], bits: usize, i: usize, value: usize) {
+        let per_word = 64 / bits;
+        let shift = (i % per_word) * bits;
+        let mask = ((1u64 << bits) - 1) << shift;
+        let word = &mut data[i / per_word];
+        *word = (*word & !mask) | ((value as u64) << shift);
+    }
+
+    pub fn get(&self, i: usize) -> Block {
+        assert!(i < self.len(), "Block index {} out of bounds", i);
+
+        match self {
+            Self::Single { block, .. } => *block,
+            Self::Packed {
+                palette,
+                bits,
+                data,
+                ..
+            } => palette[Self::read(data, *bits, i)],
+        }
+    }
+
+    pub fn set(&mut self, i: usize, new_block: Block) {
+        assert!(i < self.len(), "Block index {} out of bounds", i);
+
+        if let Self::Single { block, len } = *self {
+            if block == new_block {
+                return;
+            }
+
+            *self = Self::Packed {
+                palette: vec![block],
+                bits: 1,
+                data: vec![0; Self::words_needed(len, 1)],
+                len,
+            };
+        }
+
+        let Self::Packed { palette, bits, data, len } = self else { unreachable!() };
+
+        let index = match palette.iter().position(|block| *block == new_block) {
+            Some(index) => index,
+            None => {
+                palette.push(new_block);
+
+                // Repack with more bits once the palette outgrows them
+                if palette.len() > 1 << *bits {
+                    let new_bits = *bits * 2;
+                    let mut new_data = vec![0; Self::words_needed(*len, new_bits)];
+
+                    for j in 0..*len {
+                        Self::write(&mut new_data, new_bits, j, Self::read(data, *bits, j));
+                    }
+
+                    *bits = new_bits;
+                    *data = new_data;
+                }
+
+                palette.len() - 1
+            }
+        };
+
+        Self::write(data, *bits, i, index);
+    }
+
+    /// Drops unused palette entries, collapsing to a single value where possible
+    pub fn compact(&mut self) {
+        let Self::Packed { palette, bits, data, len } = self else { return; };
+
+        let mut used = vec![false; palette.len()];
+        for i in 0..*len {
+            used[Self::read(data, *bits, i)] = true;
+        }
+
+        let mut remap = vec![0; palette.len()];
+        let mut new_palette = vec![];
+        for (old_index, block) in palette.iter().enumerate() {
+            if used[old_index] {
+                remap[old_index] = new_palette.len();
+                new_palette.push(*block);
+            }
+        }
+
+        if new_palette.len() == 1 {
+            *self = Self::Single {
+                block: new_palette[0],
+                len: *len,
+            };
+            return;
+        }
+
+        let mut new_bits = 1;
+        while new_palette.len() > 1 << new_bits {
+            new_bits *= 2;
+        }
+
+        let mut new_data = vec![0; Self::words_needed(*len, new_bits)];
+        for i in 0..*len {
+            Self::write(&mut new_data, new_bits, i, remap[Self::read(data, *bits, i)]);
+        }
+
+        *palette = new_palette;
+        *bits = new_bits;
+        *data = new_data;
+    }
+
+    /// The number of bytes used, including the heap
+    pub fn memory_usage(&self) -> usize {
+        size_of::<Self>()
+            + match self {
+                Self::Single { .. } => 0,
+                Self::Packed { palette, data, .. } => {
+                    palette.capacity() * size_of::<Block>() + data.capacity() * size_of::<u64>()
+                }
+            }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A small xorshift so the tests are deterministic without pulling in rand
+    struct TestRng(u64);
+
+    impl TestRng {
+        fn next(&mut self) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0 as usize
+        }
+    }
+
+    #[test]
+    fn single_value_stays_single() {
+        let mut storage = PalettedStorage::new(4096, Block::Air);
+        storage.set(10, Block::Air);
+
+        assert!(matches!(storage, PalettedStorage::Single { .. }));
+        assert_eq!(storage.get(10), Block::Air);
+    }
+
+    #[test]
+    fn round_trips_arbitrary_edits() {
+        let mut rng = TestRng(0x2545_f491_4f6c_dd1d);
+        let mut storage = PalettedStorage::new(4096, Block::Stone);
+        let mut expected = vec![Block::Stone; 4096];
+
+        for _ in 0..20_000 {
+            let i = rng.next() % 4096;
+            let block = Block::ALL[rng.next() % Block::ALL.len()];
+
+            storage.set(i, block);
+            expected[i] = block;
+        }
+
+        for (i, block) in expected.iter().enumerate() {
+            assert_eq!(storage.get(i), *block);
+        }
+
+        storage.compact();
+
+        for (i, block) in expected.iter().enumerate() {
+            assert_eq!(storage.get(i), *block);
+        }
+    }
+
+    #[test]
+    fn compact_collapses_uniform_storage() {
+        let mut storage = PalettedStorage::new(4096, Block::Air);
+
+        for i in 0..4096 {
+            storage.set(i, Block::Dirt);
+        }
+        for i in 0..4096 {
+            storage.set(i, Block::Stone);
+        }
+
+        storage.compact();
+
+        assert!(matches!(
+            storage,
+            PalettedStorage::Single {
+                block: Block::Stone,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn compact_shrinks_bits() {
+        let mut storage = PalettedStorage::new(4096, Block::Air);
+
+        for (i, block) in Block::ALL.iter().enumerate() {
+            storage.set(i, *block);
+        }
+        for i in 2..Block::ALL.len() {
+            storage.set(i, Block::Air);
+        }
+
+        let before = storage.memory_usage();
+        storage.compact();
+
+        assert!(storage.memory_usage() < before);
+        assert_eq!(storage.get(0), Block::Air);
+        assert_eq!(storage.get(1), Block::Dirt);
+    }
+}
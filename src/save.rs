@@ -0,0 +1,174 @@
+use std::{fs, io, path::PathBuf};
+
+use bevy::{app::AppExit, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    chunk::{Chunk, TerrainGen},
+    console::{not_typing, AppConsoleExt, Args, ConsoleCommand},
+    time_of_day::TimeOfDay,
+    world::World,
+};
+
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SaveDir>()
+            .add_systems(Startup, Self::load_level)
+            .add_systems(
+                Update,
+                Self::save_key
+                    .run_if(resource_exists::<Input<KeyCode>>())
+                    .run_if(not_typing),
+            )
+            .add_console_command(
+                "save",
+                ConsoleCommand {
+                    usage: "".into(),
+                    description: "Saves the world".into(),
+                    run: Self::save_command,
+                    remote: false,
+                },
+            )
+            .add_systems(Last, Self::save_on_exit);
+    }
+}
+
+/// The directory the world is saved to
+#[derive(Resource)]
+pub struct SaveDir(pub PathBuf);
+
+impl Default for SaveDir {
+    fn default() -> Self {
+        Self(PathBuf::from("saves/world"))
+    }
+}
+
+/// World state that isn't stored in chunks
+#[derive(Serialize, Deserialize, Default)]
+pub struct LevelData {
+    pub time_of_day: f32,
+}
+
+impl SaveDir {
+    fn level_path(&self) -> PathBuf {
+        self.0.join("level.ron")
+    }
+
+    pub fn chat_log_path(&self) -> PathBuf {
+        self.0.join("chat.log")
+    }
+
+    fn chunk_path(&self, chunk_id: IVec3) -> PathBuf {
+        self.0
+            .join("chunks")
+            .join(format!("{}_{}_{}.chunk", chunk_id.x, chunk_id.y, chunk_id.z))
+    }
+
+    /// Returns None if the level hasn't been saved before
+    pub fn load_level(&self) -> Option<LevelData> {
+        let text = fs::read_to_string(self.level_path()).ok()?;
+
+        ron::from_str(&text)
+            .map_err(|err| error!("Failed to parse {:?}: {}", self.level_path(), err))
+            .ok()
+    }
+
+    pub fn save_level(&self, level: &LevelData) -> io::Result<()> {
+        let text = ron::ser::to_string_pretty(level, default())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+        fs::create_dir_all(&self.0)?;
+        fs::write(self.level_path(), text)
+    }
+
+    /// Returns None if the chunk hasn't been saved before
+    pub fn load_chunk(&self, chunk_id: IVec3) -> Option<Chunk> {
+        let bytes = fs::read(self.chunk_path(chunk_id)).ok()?;
+        let chunk = Chunk::from_bytes(chunk_id, &bytes);
+
+        if chunk.is_none() {
+            error!("Chunk {} save is corrupt", chunk_id);
+        }
+
+        chunk
+    }
+
+    /// Loads the chunk if it's been saved before, otherwise generates it
+    pub fn load_or_generate(&self, chunk_id: IVec3, terrain_gen: &TerrainGen) -> Chunk {
+        self.load_chunk(chunk_id).unwrap_or_else(|| {
+            let mut chunk = Chunk::new(chunk_id);
+            chunk.generate(terrain_gen);
+            chunk
+        })
+    }
+
+    pub fn save_chunk(&self, chunk: &Chunk) -> io::Result<()> {
+        let path = self.chunk_path(chunk.id());
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(path, chunk.to_bytes())
+    }
+
+    /// Saves every loaded chunk along with the level data
+    pub fn save(&self, world: &World, time_of_day: &TimeOfDay) -> io::Result<()> {
+        for chunk in world.chunks.values() {
+            self.save_chunk(chunk)?;
+        }
+
+        self.save_level(&LevelData {
+            time_of_day: time_of_day.time,
+        })
+    }
+}
+
+impl SavePlugin {
+    pub fn load_level(save_dir: Res<SaveDir>, mut time_of_day: ResMut<TimeOfDay>) {
+        if let Some(level) = save_dir.load_level() {
+            time_of_day.time = level.time_of_day;
+        }
+    }
+
+    pub fn save_and_log(save_dir: &SaveDir, world: &World, time_of_day: &TimeOfDay) {
+        match save_dir.save(world, time_of_day) {
+            Ok(()) => info!("Saved world to {:?}", save_dir.0),
+            Err(err) => error!("Failed to save world to {:?}: {}", save_dir.0, err),
+        }
+    }
+
+    pub fn save_key(
+        keys: Res<Input<KeyCode>>,
+        save_dir: Res<SaveDir>,
+        world: Res<World>,
+        time_of_day: Res<TimeOfDay>,
+    ) {
+        if keys.just_pressed(KeyCode::F5) {
+            Self::save_and_log(&save_dir, &world, &time_of_day);
+        }
+    }
+
+    fn save_command(world: &mut bevy::ecs::world::World, args: &Args) -> Result<String, String> {
+        args.at_most(0)?;
+        let save_dir = world.resource::<SaveDir>();
+
+        save_dir
+            .save(world.resource(), world.resource())
+            .map(|()| format!("Saved world to {:?}", save_dir.0))
+            .map_err(|err| format!("Failed to save world to {:?}: {}", save_dir.0, err))
+    }
+
+    pub fn save_on_exit(
+        mut exit: EventReader<AppExit>,
+        save_dir: Res<SaveDir>,
+        world: Res<World>,
+        time_of_day: Res<TimeOfDay>,
+    ) {
+        if exit.iter().next().is_some() {
+            Self::save_and_log(&save_dir, &world, &time_of_day);
+        }
+    }
+}
@@ -0,0 +1,542 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use bevy::{asset::FileAssetIo, prelude::*};
+use bevy_egui::{egui, EguiContexts};
+use serde::Deserialize;
+
+use crate::{
+    block::Block,
+    history::EditHistory,
+    nbt::{self, Tag},
+    world::{rotate_in_box, rotated_size, World},
+    world_edit::WorldEdit,
+};
+
+/// Adds a window for exporting a box of the world to a Sponge schematic, and importing one back
+pub struct SchematicPlugin;
+
+impl Plugin for SchematicPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(BlockMapping::load())
+            .init_resource::<SchematicState>()
+            .add_systems(Update, Self::schematic_window);
+    }
+}
+
+/// What blocks from other tools are imported as
+#[derive(Resource, Deserialize)]
+pub struct BlockMapping {
+    /// Keyed by block name, without any block states
+    pub names: HashMap<String, Block>,
+    /// Used for any block that isn't in `names`
+    pub fallback: Block,
+}
+
+impl Default for BlockMapping {
+    fn default() -> Self {
+        Self {
+            names: HashMap::new(),
+            fallback: Block::Stone,
+        }
+    }
+}
+
+impl BlockMapping {
+    fn path() -> PathBuf {
+        FileAssetIo::get_base_path()
+            .join("assets")
+            .join("schem_mapping.ron")
+    }
+
+    /// Loads the table from `assets/schem_mapping.ron`, falling back to an empty one
+    pub fn load() -> Self {
+        let path = Self::path();
+        let Ok(text) = fs::read_to_string(&path) else { return Self::default(); };
+
+        ron::from_str(&text)
+            .map_err(|err| error!("Failed to parse {:?}: {}", path, err))
+            .unwrap_or_default()
+    }
+
+    /// Blocks written by `name` always map back, so schematics round trip
+    pub fn block(&self, name: &str) -> Block {
+        // Block states such as `[facing=north]` aren't supported
+        let name = name.split('[').next().unwrap_or(name);
+
+        Block::ALL
+            .into_iter()
+            .find(|block| block_name(*block) == name)
+            .or_else(|| self.names.get(name).copied())
+            .unwrap_or(self.fallback)
+    }
+}
+
+/// The name a block is exported with, matching Minecraft's where there's an equivalent
+pub fn block_name(block: Block) -> &'static str {
+    match block {
+        Block::Air => "minecraft:air",
+        Block::Dirt => "minecraft:dirt",
+        Block::Stone => "minecraft:stone",
+        Block::Torch => "minecraft:torch",
+        Block::Lava => "minecraft:lava",
+    }
+}
+
+/// A box of blocks in the Sponge schematic format
+#[derive(Clone, PartialEq, Debug)]
+pub struct Schematic {
+    pub size: IVec3,
+    /// Ordered by y, then z, then x
+    pub blocks: Vec<Block>,
+}
+
+/// The Minecraft data version the exported block names come from, 1.16.5
+const DATA_VERSION: i32 = 2586;
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn write_varint(bytes: &mut Vec<i8>, mut value: u32) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+
+        if value == 0 {
+            bytes.push(byte as i8);
+            return;
+        }
+        bytes.push((byte | 0x80) as i8);
+    }
+}
+
+fn read_varints(bytes: &[i8]) -> io::Result<Vec<u32>> {
+    let mut values = vec![];
+    let mut value = 0;
+    let mut shift = 0;
+
+    for &byte in bytes {
+        let byte = byte as u8;
+        if shift >= 32 {
+            return Err(invalid_data("Varint too long"));
+        }
+
+        value |= ((byte & 0x7f) as u32) << shift;
+        shift += 7;
+
+        if byte & 0x80 == 0 {
+            values.push(value);
+            value = 0;
+            shift = 0;
+        }
+    }
+
+    if shift != 0 {
+        return Err(invalid_data("Block data ends in the middle of a varint"));
+    }
+
+    Ok(values)
+}
+
+impl Schematic {
+    fn index(&self, pos: IVec3) -> usize {
+        ((pos.y * self.size.z + pos.z) * self.size.x + pos.x) as usize
+    }
+
+    /// Copies the blocks between the corners `from` and `to`, treating unloaded chunks as air
+    pub fn from_world(world: &World, from: IVec3, to: IVec3) -> Self {
+        let (min, max) = (from.min(to), from.max(to));
+        let size = max - min + IVec3::ONE;
+
+        let mut blocks = Vec::with_capacity((size.x * size.y * size.z) as usize);
+        for y in 0..size.y {
+            for z in 0..size.z {
+                for x in 0..size.x {
+                    let pos = min + IVec3::new(x, y, z);
+                    blocks.push(world.get_block(pos).unwrap_or(Block::Air));
+                }
+            }
+        }
+
+        Self { size, blocks }
+    }
+
+    pub fn get(&self, pos: IVec3) -> Block {
+        self.blocks[self.index(pos)]
+    }
+
+    /// Places the blocks with their lowest corner at `origin`, skipping unloaded chunks.
+    ///
+    /// Returns the position and old block of every block that changed.
+    pub fn paste(
+        &self,
+        world: &mut World,
+        origin: IVec3,
+        include_air: bool,
+    ) -> Vec<(IVec3, Block)> {
+        let mut placed = Vec::with_capacity(self.blocks.len());
+        for y in 0..self.size.y {
+            for z in 0..self.size.z {
+                for x in 0..self.size.x {
+                    let pos = IVec3::new(x, y, z);
+                    let block = self.get(pos);
+
+                    if include_air || block != Block::Air {
+                        placed.push((origin + pos, block));
+                    }
+                }
+            }
+        }
+
+        world.set_blocks(placed)
+    }
+
+    /// Mirrors the blocks along x and z as requested,
+    /// then turns them `quarter_turns` times anticlockwise about the y axis
+    pub fn transformed(&self, quarter_turns: u32, mirror_x: bool, mirror_z: bool) -> Self {
+        let size = rotated_size(self.size, quarter_turns);
+        let mut blocks = vec![Block::Air; self.blocks.len()];
+        let mut transformed = Self {
+            size,
+            blocks: vec![],
+        };
+
+        for y in 0..self.size.y {
+            for z in 0..self.size.z {
+                for x in 0..self.size.x {
+                    let pos = IVec3::new(x, y, z);
+                    let mirrored = IVec3::new(
+                        if mirror_x { self.size.x - 1 - x } else { x },
+                        y,
+                        if mirror_z { self.size.z - 1 - z } else { z },
+                    );
+
+                    let new_pos = rotate_in_box(mirrored, self.size, quarter_turns);
+                    blocks[transformed.index(new_pos)] = self.get(pos);
+                }
+            }
+        }
+
+        transformed.blocks = blocks;
+        transformed
+    }
+
+    /// Builds a version 2 schematic
+    pub fn to_nbt(&self) -> io::Result<Tag> {
+        let short = |value: i32| {
+            i16::try_from(value)
+                .map(Tag::Short)
+                .map_err(|_| invalid_data("Schematic too large"))
+        };
+
+        let mut palette = vec![];
+        let mut data = vec![];
+        for block in &self.blocks {
+            let index = match palette.iter().position(|b| b == block) {
+                Some(index) => index,
+                None => {
+                    palette.push(*block);
+                    palette.len() - 1
+                }
+            };
+
+            write_varint(&mut data, index as u32);
+        }
+
+        let palette = palette
+            .into_iter()
+            .enumerate()
+            .map(|(index, block)| (block_name(block).to_string(), Tag::Int(index as i32)))
+            .collect::<BTreeMap<_, _>>();
+
+        Ok(Tag::Compound(BTreeMap::from([
+            ("Version".to_string(), Tag::Int(2)),
+            ("DataVersion".to_string(), Tag::Int(DATA_VERSION)),
+            ("Width".to_string(), short(self.size.x)?),
+            ("Height".to_string(), short(self.size.y)?),
+            ("Length".to_string(), short(self.size.z)?),
+            ("PaletteMax".to_string(), Tag::Int(palette.len() as i32)),
+            ("Palette".to_string(), Tag::Compound(palette)),
+            ("BlockData".to_string(), Tag::ByteArray(data)),
+        ])))
+    }
+
+    /// Reads a version 2 or 3 schematic, looking up block names in `mapping`
+    pub fn from_nbt(root: &Tag, mapping: &BlockMapping) -> io::Result<Self> {
+        // Version 3 wraps everything in another compound
+        let root = root.get("Schematic").unwrap_or(root);
+
+        let dimension = |name: &str| {
+            root.get(name)
+                .and_then(Tag::as_i64)
+                .map(|value| value as u16 as i32)
+                .ok_or_else(|| invalid_data("Schematic has no size"))
+        };
+        let size = IVec3::new(
+            dimension("Width")?,
+            dimension("Height")?,
+            dimension("Length")?,
+        );
+
+        let (palette, data) = match root.get("Version").and_then(Tag::as_i64) {
+            Some(2) | Some(1) => (root.get("Palette"), root.get("BlockData")),
+            Some(3) => {
+                let blocks = root.get("Blocks");
+                (
+                    blocks.and_then(|blocks| blocks.get("Palette")),
+                    blocks.and_then(|blocks| blocks.get("Data")),
+                )
+            }
+            _ => return Err(invalid_data("Unsupported schematic version")),
+        };
+
+        let (Some(Tag::Compound(palette)), Some(Tag::ByteArray(data))) = (palette, data) else {
+            return Err(invalid_data("Schematic has no blocks"));
+        };
+
+        let mut lookup = HashMap::new();
+        for (name, index) in palette {
+            let index = index
+                .as_i64()
+                .ok_or_else(|| invalid_data("Palette index isn't a number"))?;
+            lookup.insert(index as u32, mapping.block(name));
+        }
+
+        let blocks = read_varints(data)?
+            .into_iter()
+            .map(|index| {
+                lookup
+                    .get(&index)
+                    .copied()
+                    .ok_or_else(|| invalid_data("Block isn't in the palette"))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        // Each side fits in a u16, so this can't overflow like an i32 could
+        let volume = size.x as i64 * size.y as i64 * size.z as i64;
+        if blocks.len() as i64 != volume {
+            return Err(invalid_data("Block data doesn't match the schematic size"));
+        }
+
+        Ok(Self { size, blocks })
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        nbt::write_gzip(fs::File::create(path)?, "Schematic", &self.to_nbt()?)
+    }
+
+    pub fn load(path: &Path, mapping: &BlockMapping) -> io::Result<Self> {
+        let (_, root) = nbt::read_gzip(io::BufReader::new(fs::File::open(path)?))?;
+        Self::from_nbt(&root, mapping)
+    }
+}
+
+#[derive(Resource)]
+pub struct SchematicState {
+    /// The first corner of the box to export
+    pub from: IVec3,
+    /// The opposite corner, imports are placed with their lowest corner at `from`
+    pub to: IVec3,
+    pub path: String,
+    /// The outcome of the last import or export
+    status: Option<String>,
+}
+
+impl Default for SchematicState {
+    fn default() -> Self {
+        Self {
+            from: IVec3::new(0, 0, 0),
+            to: IVec3::new(15, 15, 15),
+            path: "schematics/build.schem".to_string(),
+            status: None,
+        }
+    }
+}
+
+impl SchematicPlugin {
+    pub fn schematic_window(
+        mut ctx: EguiContexts,
+        mut state: ResMut<SchematicState>,
+        mapping: Res<BlockMapping>,
+        edit: Res<WorldEdit>,
+        mut world: ResMut<World>,
+        mut history: ResMut<EditHistory>,
+    ) {
+        egui::Window::new("Schematic").show(ctx.ctx_mut(), |ui| {
+            egui::Grid::new("schematic_grid")
+                .num_columns(4)
+                .show(ui, |ui| {
+                    ui.label("From");
+                    ui.add(egui::DragValue::new(&mut state.from.x));
+                    ui.add(egui::DragValue::new(&mut state.from.y));
+                    ui.add(egui::DragValue::new(&mut state.from.z));
+                    ui.end_row();
+
+                    ui.label("To");
+                    ui.add(egui::DragValue::new(&mut state.to.x));
+                    ui.add(egui::DragValue::new(&mut state.to.y));
+                    ui.add(egui::DragValue::new(&mut state.to.z));
+                    ui.end_row();
+                });
+
+            if let Some(selection) = edit.selection() {
+                if ui.button("Use selection").clicked() {
+                    state.from = selection.min;
+                    state.to = selection.max;
+                }
+            }
+
+            ui.horizontal(|ui| {
+                ui.label("Path");
+                ui.text_edit_singleline(&mut state.path);
+            });
+
+            let path = PathBuf::from(&state.path);
+
+            ui.horizontal(|ui| {
+                if ui.button("Export").clicked() {
+                    let schematic = Schematic::from_world(&world, state.from, state.to);
+
+                    state.status = Some(match schematic.save(&path) {
+                        Ok(()) => format!("Exported to {:?}", path),
+                        Err(err) => format!("Export failed: {}", err),
+                    });
+                }
+
+                if ui.button("Import").clicked() {
+                    state.status = Some(match Schematic::load(&path, &mapping) {
+                        Ok(schematic) => {
+                            let changed =
+                                schematic.paste(&mut world, state.from.min(state.to), true);
+                            history.record(&world, changed);
+                            format!("Imported {:?}", path)
+                        }
+                        Err(err) => format!("Import failed: {}", err),
+                    });
+                }
+            });
+
+            if let Some(status) = &state.status {
+                ui.label(status);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::TerrainGen;
+
+    fn generated_world() -> World {
+        let terrain_gen = TerrainGen::default();
+        World::with_chunks([IVec3::new(-1, 0, 0), IVec3::ZERO], |pos| {
+            terrain_gen.block_at(pos)
+        })
+    }
+
+    #[test]
+    fn varints_round_trip() {
+        let values = [0, 1, 127, 128, 300, 16_383, 16_384, u32::MAX];
+
+        let mut bytes = vec![];
+        for value in values {
+            write_varint(&mut bytes, value);
+        }
+
+        assert_eq!(read_varints(&bytes).unwrap(), values);
+        assert!(read_varints(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn world_round_trips_through_schem() {
+        let mut world = generated_world();
+        world.set_blocks([
+            (IVec3::new(-3, 12, 4), Block::Torch),
+            (IVec3::new(2, 13, 5), Block::Lava),
+        ]);
+
+        let (from, to) = (IVec3::new(-5, 0, 1), IVec3::new(4, 15, 9));
+        let schematic = Schematic::from_world(&world, from, to);
+
+        let mut bytes = vec![];
+        nbt::write_gzip(&mut bytes, "Schematic", &schematic.to_nbt().unwrap()).unwrap();
+        let (_, root) = nbt::read_gzip(bytes.as_slice()).unwrap();
+        let loaded = Schematic::from_nbt(&root, &BlockMapping::default()).unwrap();
+
+        assert_eq!(loaded, schematic);
+
+        // Paste it somewhere else and compare block by block
+        let offset = IVec3::new(0, 0, 2);
+        let mut pasted = generated_world();
+        loaded.paste(&mut pasted, from + offset, true);
+
+        for x in from.x..=to.x {
+            for y in from.y..=to.y {
+                for z in from.z..=to.z {
+                    let pos = IVec3::new(x, y, z);
+                    assert_eq!(pasted.get_block(pos + offset), world.get_block(pos));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_blocks_use_the_mapping() {
+        let mapping = BlockMapping {
+            names: HashMap::from([("minecraft:grass_block".to_string(), Block::Dirt)]),
+            fallback: Block::Air,
+        };
+
+        let root = Tag::Compound(BTreeMap::from([
+            ("Version".to_string(), Tag::Int(2)),
+            ("Width".to_string(), Tag::Short(3)),
+            ("Height".to_string(), Tag::Short(1)),
+            ("Length".to_string(), Tag::Short(1)),
+            (
+                "Palette".to_string(),
+                Tag::Compound(BTreeMap::from([
+                    (
+                        "minecraft:grass_block[snowy=false]".to_string(),
+                        Tag::Int(0),
+                    ),
+                    ("minecraft:oak_log[axis=y]".to_string(), Tag::Int(1)),
+                    ("minecraft:torch".to_string(), Tag::Int(2)),
+                ])),
+            ),
+            ("BlockData".to_string(), Tag::ByteArray(vec![0, 1, 2])),
+        ]));
+
+        let schematic = Schematic::from_nbt(&root, &mapping).unwrap();
+        assert_eq!(schematic.blocks, [Block::Dirt, Block::Air, Block::Torch]);
+    }
+
+    #[test]
+    fn mismatched_sizes_are_rejected() {
+        let schematic = Schematic {
+            size: IVec3::new(2, 2, 2),
+            blocks: vec![Block::Stone; 8],
+        };
+        let Tag::Compound(mut root) = schematic.to_nbt().unwrap() else {
+            unreachable!()
+        };
+        let mapping = BlockMapping::default();
+
+        root.insert("Width".to_string(), Tag::Short(3));
+        assert!(Schematic::from_nbt(&Tag::Compound(root.clone()), &mapping).is_err());
+
+        // Sides are read as unsigned, big enough to overflow an i32 volume
+        for side in ["Width", "Height", "Length"] {
+            root.insert(side.to_string(), Tag::Short(-1));
+        }
+        assert!(Schematic::from_nbt(&Tag::Compound(root), &mapping).is_err());
+    }
+}
@@ -0,0 +1,655 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, SystemTime},
+};
+
+use bevy::{
+    ecs::{event::ManualEventReader, world::World as EcsWorld},
+    prelude::*,
+    utils::HashSet,
+};
+use rhai::{Array, Dynamic, Engine, EvalAltResult, FnPtr, FuncArgs, Map, AST, INT};
+
+use crate::{
+    block::Block,
+    console::{Args, ConsoleCommand, ConsoleCommands},
+    history::EditHistory,
+    ticks::{tick_blocks, BlockTicked},
+    world::{world_mesh_gen, BlockEdited, World},
+};
+
+/// How often the scripts directory is checked for changes
+const RELOAD_INTERVAL: Duration = Duration::from_secs(1);
+/// Stops a script that's stuck in a loop from freezing the game
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// Runs the Rhai scripts in a directory, reloading them when they change.
+///
+/// Scripts can give blocks behaviours with `on_block` that run when they're placed, broken, updated or randomly ticked,
+/// schedule block updates, and add console commands, which `register_remote_command` lets players on a server run too.
+/// Blocks are built into the game, so scripts can only add behaviours to them, not new blocks.
+///
+/// Blocks scripts set are sent as `BlockEdited`s, and each console command's edits are undone together.
+/// Edits made by block behaviours aren't undoable, so they don't push players' own edits out of the history.
+/// See `assets/scripts` for examples.
+pub struct ScriptingPlugin {
+    pub dir: PathBuf,
+}
+
+impl Default for ScriptingPlugin {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("assets/scripts"),
+        }
+    }
+}
+
+impl Plugin for ScriptingPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Scripts::new(self.dir.clone()))
+            .add_event::<BlockEdited>()
+            .add_event::<BlockTicked>()
+            .add_systems(
+                Update,
+                (Scripts::reload_changed, Scripts::dispatch_edits)
+                    .chain()
+                    .before(world_mesh_gen),
+            )
+            .add_systems(FixedUpdate, Scripts::dispatch_ticks.after(tick_blocks));
+    }
+}
+
+/// Functions a script registered for a block, called with the block's position
+#[derive(Default)]
+struct Behaviour {
+    /// The script that registered it, which its functions are defined in
+    script: usize,
+    on_place: Option<FnPtr>,
+    on_break: Option<FnPtr>,
+    on_update: Option<FnPtr>,
+    on_random_tick: Option<FnPtr>,
+}
+
+struct ScriptCommand {
+    script: usize,
+    name: String,
+    usage: String,
+    description: String,
+    /// Whether players on a server can run it from chat
+    remote: bool,
+    /// Called with an array of the arguments, returning what to print
+    run: FnPtr,
+}
+
+/// The state the functions scripts call can reach
+#[derive(Default)]
+struct Host {
+    /// Lent from the ECS while scripts run, and None otherwise
+    world: Option<World>,
+    behaviours: HashMap<Block, Behaviour>,
+    commands: Vec<ScriptCommand>,
+    /// The script being run for the first time, which anything registered belongs to
+    loading: usize,
+    /// Blocks changed by the function being called, sent as `BlockEdited`s once it returns
+    edits: Vec<BlockEdited>,
+}
+
+#[derive(Resource)]
+pub struct Scripts {
+    dir: PathBuf,
+    engine: Engine,
+    host: Arc<Mutex<Host>>,
+    /// Every loaded script and its path, in the order they were run
+    loaded: Vec<(PathBuf, AST)>,
+    /// The path, modification time and size of each script when it was loaded, to spot changes
+    stamps: Vec<(PathBuf, Option<SystemTime>, u64)>,
+    last_checked: Option<Duration>,
+}
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+fn block_named(name: &str) -> ScriptResult<Block> {
+    Block::from_name(name).ok_or_else(|| format!("Unknown block {:?}", name).into())
+}
+
+fn to_pos(x: INT, y: INT, z: INT) -> IVec3 {
+    IVec3::new(x as i32, y as i32, z as i32)
+}
+
+impl Scripts {
+    pub fn new(dir: PathBuf) -> Self {
+        let host = Arc::new(Mutex::new(Host::default()));
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        engine.on_print(|text| info!("[script] {}", text));
+
+        let lent = host.clone();
+        engine.register_fn(
+            "on_block",
+            move |name: &str, functions: Map| -> ScriptResult<()> {
+                let block = block_named(name)?;
+                let mut host = lent.lock().unwrap();
+                let mut behaviour = Behaviour {
+                    script: host.loading,
+                    ..default()
+                };
+
+                for (key, value) in functions {
+                    let function = value
+                        .try_cast::<FnPtr>()
+                        .ok_or_else(|| format!("{} must be a function", key))?;
+                    match key.as_str() {
+                        "on_place" => behaviour.on_place = Some(function),
+                        "on_break" => behaviour.on_break = Some(function),
+                        "on_update" => behaviour.on_update = Some(function),
+                        "on_random_tick" => behaviour.on_random_tick = Some(function),
+                        _ => return Err(format!("Unknown block function {:?}", key).into()),
+                    }
+                }
+
+                host.behaviours.insert(block, behaviour);
+                Ok(())
+            },
+        );
+
+        // register_remote_command also lets players on a server run it from chat
+        for (function, remote) in [
+            ("register_command", false),
+            ("register_remote_command", true),
+        ] {
+            let lent = host.clone();
+            engine.register_fn(
+                function,
+                move |name: &str, usage: &str, description: &str, run: FnPtr| {
+                    let mut host = lent.lock().unwrap();
+                    let script = host.loading;
+                    host.commands.retain(|command| command.name != name);
+                    host.commands.push(ScriptCommand {
+                        script,
+                        name: name.to_string(),
+                        usage: usage.to_string(),
+                        description: description.to_string(),
+                        remote,
+                        run,
+                    });
+                },
+            );
+        }
+
+        let lent = host.clone();
+        engine.register_fn(
+            "get_block",
+            move |x: INT, y: INT, z: INT| -> ScriptResult<Dynamic> {
+                let host = lent.lock().unwrap();
+                let world = host.world.as_ref().ok_or("The world can't be used yet")?;
+
+                Ok(world
+                    .get_block(to_pos(x, y, z))
+                    .map_or(Dynamic::UNIT, |block| block.name().into()))
+            },
+        );
+
+        let lent = host.clone();
+        engine.register_fn(
+            "set_block",
+            move |x: INT, y: INT, z: INT, name: &str| -> ScriptResult<bool> {
+                let block = block_named(name)?;
+                let pos = to_pos(x, y, z);
+                let mut host = lent.lock().unwrap();
+                let world = host.world.as_mut().ok_or("The world can't be used yet")?;
+
+                let Some(old) = world.set_block(pos, block) else { return Ok(false); };
+                if old != block {
+                    host.edits.push(BlockEdited {
+                        pos,
+                        old,
+                        new: block,
+                    });
+                }
+                Ok(true)
+            },
+        );
+
+        let lent = host.clone();
+        engine.register_fn(
+            "schedule_update",
+            move |x: INT, y: INT, z: INT, ticks: INT| -> ScriptResult<()> {
+                let mut host = lent.lock().unwrap();
+                let world = host.world.as_mut().ok_or("The world can't be used yet")?;
+
+                world.schedule_tick(to_pos(x, y, z), ticks.max(1) as u64);
+                Ok(())
+            },
+        );
+
+        Self {
+            dir,
+            engine,
+            host,
+            loaded: vec![],
+            stamps: vec![],
+            last_checked: None,
+        }
+    }
+
+    fn host(&self) -> MutexGuard<'_, Host> {
+        self.host.lock().unwrap()
+    }
+
+    /// The blocks with an `on_random_tick`, which the world needs to send random ticks for
+    pub fn random_blocks(&self) -> HashSet<Block> {
+        self.host()
+            .behaviours
+            .iter()
+            .filter(|(_, behaviour)| behaviour.on_random_tick.is_some())
+            .map(|(block, _)| *block)
+            .collect()
+    }
+
+    /// The scripts in the directory, sorted so they run in a consistent order
+    fn scan(dir: &Path) -> Vec<(PathBuf, Option<SystemTime>, u64)> {
+        let Ok(entries) = fs::read_dir(dir) else { return vec![]; };
+
+        let mut stamps = entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "rhai"))
+            .filter_map(|entry| {
+                let metadata = entry.metadata().ok()?;
+                Some((entry.path(), metadata.modified().ok(), metadata.len()))
+            })
+            .collect::<Vec<_>>();
+        stamps.sort_by(|a, b| a.0.cmp(&b.0));
+        stamps
+    }
+
+    /// Loads every script again if any have been added, removed or changed since they were last loaded
+    pub fn reload(&mut self, commands: &mut ConsoleCommands) {
+        let stamps = Self::scan(&self.dir);
+        if stamps == self.stamps {
+            return;
+        }
+
+        {
+            let mut host = self.host();
+            host.behaviours.clear();
+            for command in host.commands.drain(..) {
+                commands.unregister(&command.name);
+            }
+        }
+        self.loaded.clear();
+
+        for (path, _, _) in &stamps {
+            let ast = match fs::read_to_string(path)
+                .map_err(|err| err.to_string())
+                .and_then(|text| self.engine.compile(text).map_err(|err| err.to_string()))
+            {
+                Ok(ast) => ast,
+                Err(err) => {
+                    error!("Failed to load {:?}: {}", path, err);
+                    continue;
+                }
+            };
+
+            // Kept even if it fails part way through, as it may have registered something first
+            self.host().loading = self.loaded.len();
+            if let Err(err) = self.engine.run_ast(&ast) {
+                error!("Error running {:?}: {}", path, err);
+            }
+            self.loaded.push((path.clone(), ast));
+        }
+
+        for command in &self.host().commands {
+            commands.register(
+                command.name.clone(),
+                ConsoleCommand {
+                    usage: command.usage.clone().into(),
+                    description: command.description.clone().into(),
+                    run: run_script_command,
+                    remote: command.remote,
+                },
+            );
+        }
+
+        info!("Loaded {} scripts from {:?}", self.loaded.len(), self.dir);
+        self.stamps = stamps;
+    }
+
+    /// Calls a script function, lending it the world.
+    ///
+    /// Blocks it set are sent as `BlockEdited`s so other behaviours react to them,
+    /// and with `record` are also recorded as one edit in the `EditHistory` if there is one.
+    fn call(
+        &self,
+        world: &mut EcsWorld,
+        script: usize,
+        function: &FnPtr,
+        args: impl FuncArgs,
+        record: bool,
+    ) -> Result<Dynamic, String> {
+        let (path, ast) = &self.loaded[script];
+
+        self.host().world = Some(std::mem::take(&mut *world.resource_mut::<World>()));
+        let result = function.call::<Dynamic>(&self.engine, ast, args);
+        *world.resource_mut::<World>() = self.host().world.take().unwrap();
+
+        let edits = std::mem::take(&mut self.host().edits);
+        if record && !edits.is_empty() {
+            world.resource_scope(|world, voxel_world: Mut<World>| {
+                if let Some(mut history) = world.get_resource_mut::<EditHistory>() {
+                    history.record(
+                        &voxel_world,
+                        edits.iter().map(|edit| (edit.pos, edit.old)).collect(),
+                    );
+                }
+            });
+        }
+        if let Some(mut events) = world.get_resource_mut::<Events<BlockEdited>>() {
+            events.extend(edits);
+        }
+
+        result.map_err(|err| format!("Error in {:?}: {}", path, err))
+    }
+
+    /// Calls one of a block's functions if it has it
+    fn call_behaviour(
+        &self,
+        world: &mut EcsWorld,
+        block: Block,
+        pos: IVec3,
+        function: impl Fn(&Behaviour) -> Option<&FnPtr>,
+    ) {
+        let Some((script, function)) = self.host().behaviours.get(&block).and_then(|behaviour| {
+            function(behaviour).map(|function| (behaviour.script, function.clone()))
+        }) else {
+            return;
+        };
+
+        let args = (pos.x as INT, pos.y as INT, pos.z as INT);
+        if let Err(err) = self.call(world, script, &function, args, false) {
+            error!("{}", err);
+        }
+    }
+
+    pub fn reload_changed(world: &mut EcsWorld) {
+        let now = world.resource::<Time>().elapsed();
+
+        world.resource_scope(|world, mut scripts: Mut<Scripts>| {
+            if scripts
+                .last_checked
+                .is_some_and(|last_checked| now.saturating_sub(last_checked) < RELOAD_INTERVAL)
+            {
+                return;
+            }
+            scripts.last_checked = Some(now);
+
+            let mut commands = world.get_resource_or_insert_with(ConsoleCommands::default);
+            scripts.reload(&mut commands);
+
+            let random_blocks = scripts.random_blocks();
+            if let Some(mut world) = world.get_resource_mut::<World>() {
+                world.ticks.random_blocks = random_blocks;
+            }
+        });
+    }
+
+    /// Calls `on_break` for the old block and `on_place` for the new one
+    pub fn dispatch_edits(world: &mut EcsWorld, mut reader: Local<ManualEventReader<BlockEdited>>) {
+        let edits = reader
+            .iter(world.resource::<Events<BlockEdited>>())
+            .copied()
+            .collect::<Vec<_>>();
+        if edits.is_empty() {
+            return;
+        }
+
+        world.resource_scope(|world, scripts: Mut<Scripts>| {
+            for BlockEdited { pos, old, new } in edits {
+                scripts.call_behaviour(world, old, pos, |behaviour| behaviour.on_break.as_ref());
+                scripts.call_behaviour(world, new, pos, |behaviour| behaviour.on_place.as_ref());
+            }
+        });
+    }
+
+    /// Calls `on_update` for the blocks whose scheduled updates are due, and `on_random_tick` for randomly ticked ones
+    pub fn dispatch_ticks(world: &mut EcsWorld, mut reader: Local<ManualEventReader<BlockTicked>>) {
+        let ticked = reader
+            .iter(world.resource::<Events<BlockTicked>>())
+            .copied()
+            .collect::<Vec<_>>();
+        if ticked.is_empty() {
+            return;
+        }
+
+        world.resource_scope(|world, scripts: Mut<Scripts>| {
+            for BlockTicked { pos, block, random } in ticked {
+                if random {
+                    scripts.call_behaviour(world, block, pos, |behaviour| {
+                        behaviour.on_random_tick.as_ref()
+                    });
+                } else {
+                    scripts.call_behaviour(world, block, pos, |behaviour| {
+                        behaviour.on_update.as_ref()
+                    });
+                }
+            }
+        });
+    }
+}
+
+/// Runs whichever script command was typed
+fn run_script_command(world: &mut EcsWorld, args: &Args) -> Result<String, String> {
+    world.resource_scope(|world, scripts: Mut<Scripts>| {
+        let (script, run) = scripts
+            .host()
+            .commands
+            .iter()
+            .find(|command| command.name == args.command)
+            .map(|command| (command.script, command.run.clone()))
+            .ok_or_else(|| format!("{} was removed from the scripts", args.command))?;
+
+        let words = args
+            .words()
+            .iter()
+            .map(|word| Dynamic::from(word.to_string()))
+            .collect::<Array>();
+        let output = scripts.call(world, script, &run, (words,), true)?;
+
+        Ok(if output.is_unit() {
+            String::new()
+        } else {
+            output.to_string()
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LAVA: &str = r#"
+        on_block("lava", #{
+            on_place: |x, y, z| schedule_update(x, y, z, 2),
+            on_update: |x, y, z| {
+                if get_block(x, y - 1, z) == "air" {
+                    set_block(x, y - 1, z, "lava");
+                }
+            },
+        });
+        on_block("torch", #{
+            on_break: |x, y, z| set_block(x, y + 1, z, "stone"),
+        });
+        on_block("dirt", #{
+            on_random_tick: |x, y, z| set_block(x, y + 1, z, "stone"),
+        });
+    "#;
+
+    fn test_app(dir: &Path) -> App {
+        let _ = fs::remove_dir_all(dir);
+        fs::create_dir_all(dir).unwrap();
+
+        let mut app = App::new();
+        app.insert_resource(World::with_chunks([IVec3::ZERO], |_| Block::Air))
+            .init_resource::<ConsoleCommands>()
+            .init_resource::<EditHistory>()
+            .add_event::<BlockEdited>()
+            .add_event::<BlockTicked>()
+            .add_systems(FixedUpdate, (tick_blocks, Scripts::dispatch_ticks).chain())
+            .insert_resource(Scripts::new(dir.to_path_buf()));
+        app
+    }
+
+    fn reload(app: &mut App) {
+        app.world
+            .resource_scope(|world, mut scripts: Mut<Scripts>| {
+                scripts.reload(&mut world.resource_mut::<ConsoleCommands>());
+            });
+    }
+
+    fn block(app: &App, pos: IVec3) -> Option<Block> {
+        app.world.resource::<World>().get_block(pos)
+    }
+
+    #[test]
+    fn behaviours_react_to_edits_and_updates() {
+        let dir = std::env::temp_dir().join("bevy_craft_scripts_test");
+        let mut app = test_app(&dir);
+        fs::write(dir.join("lava.rhai"), LAVA).unwrap();
+        reload(&mut app);
+
+        let pos = IVec3::new(1, 5, 1);
+        app.world.send_event(BlockEdited {
+            pos,
+            old: Block::Air,
+            new: Block::Lava,
+        });
+        app.world
+            .resource_mut::<World>()
+            .set_block(pos, Block::Lava);
+        app.add_systems(Update, Scripts::dispatch_edits);
+        app.update();
+
+        // Updates are scheduled 2 ticks ahead
+        app.world.run_schedule(FixedUpdate);
+        assert_eq!(block(&app, pos - IVec3::Y), Some(Block::Air));
+        app.world.run_schedule(FixedUpdate);
+        assert_eq!(block(&app, pos - IVec3::Y), Some(Block::Lava));
+
+        // Lava set by the script is placed like any other, so it keeps flowing
+        app.update();
+        app.world.run_schedule(FixedUpdate);
+        app.world.run_schedule(FixedUpdate);
+        assert_eq!(block(&app, pos - IVec3::Y * 2), Some(Block::Lava));
+        // Behaviours' edits aren't undoable, so they never push the player's out of the history
+        assert!(!app.world.resource::<EditHistory>().can_undo());
+
+        let torch = IVec3::new(3, 3, 3);
+        app.world.send_event(BlockEdited {
+            pos: torch,
+            old: Block::Torch,
+            new: Block::Air,
+        });
+        app.update();
+        assert_eq!(block(&app, torch + IVec3::Y), Some(Block::Stone));
+
+        let scripts = app.world.resource::<Scripts>();
+        assert_eq!(scripts.random_blocks(), HashSet::from_iter([Block::Dirt]));
+        let dirt = IVec3::new(5, 5, 5);
+        app.world.send_event(BlockTicked {
+            pos: dirt,
+            block: Block::Dirt,
+            random: true,
+        });
+        app.world.run_schedule(FixedUpdate);
+        assert_eq!(block(&app, dirt + IVec3::Y), Some(Block::Stone));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn commands_reload_when_scripts_change() {
+        let dir = std::env::temp_dir().join("bevy_craft_script_commands_test");
+        let mut app = test_app(&dir);
+        let run = |app: &mut App, line: &str| {
+            app.world
+                .resource_scope(|world, commands: Mut<ConsoleCommands>| commands.run(world, line))
+        };
+        let run_remote = |app: &mut App, line: &str| {
+            app.world
+                .resource_scope(|world, commands: Mut<ConsoleCommands>| {
+                    commands.run_remote(world, line, Vec3::ZERO)
+                })
+        };
+
+        fs::write(
+            dir.join("greet.rhai"),
+            r#"register_command("greet", "<name>", "Says hello", |args| "Hello " + args[0]);"#,
+        )
+        .unwrap();
+        reload(&mut app);
+        assert_eq!(run(&mut app, "greet you"), Ok("Hello you".to_string()));
+        assert!(run(&mut app, "help greet").unwrap().contains("Says hello"));
+        assert!(run_remote(&mut app, "greet you").is_err());
+
+        // A broken script doesn't stop the others loading
+        fs::write(dir.join("broken.rhai"), "on_block(").unwrap();
+        fs::write(
+            dir.join("greet.rhai"),
+            r#"register_remote_command("wave", "", "Waves", |args| { set_block(0, 0, 0, "dirt"); "o/" });"#,
+        )
+        .unwrap();
+        reload(&mut app);
+        assert!(run(&mut app, "greet you").is_err());
+        assert_eq!(run_remote(&mut app, "wave"), Ok("o/".to_string()));
+        assert_eq!(block(&app, IVec3::ZERO), Some(Block::Dirt));
+
+        // Script edits are sent like any other, and can be undone
+        let mut reader = app.world.resource::<Events<BlockEdited>>().get_reader();
+        let edits = reader
+            .iter(app.world.resource::<Events<BlockEdited>>())
+            .copied()
+            .collect::<Vec<_>>();
+        assert_eq!(
+            edits,
+            [BlockEdited {
+                pos: IVec3::ZERO,
+                old: Block::Air,
+                new: Block::Dirt,
+            }]
+        );
+        app.world
+            .resource_scope(|world, mut history: Mut<EditHistory>| {
+                assert!(history.undo(&mut world.resource_mut::<World>()));
+            });
+        assert_eq!(block(&app, IVec3::ZERO), Some(Block::Air));
+
+        fs::write(dir.join("glass.rhai"), r#"on_block("glass", #{});"#).unwrap();
+        reload(&mut app);
+        assert_eq!(app.world.resource::<Scripts>().loaded.len(), 2);
+        assert!(app.world.resource::<Scripts>().host().behaviours.is_empty());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn examples_run() {
+        let mut app = test_app(&std::env::temp_dir().join("bevy_craft_script_examples_test"));
+        app.insert_resource(Scripts::new(PathBuf::from("assets/scripts")));
+        reload(&mut app);
+
+        let output = app
+            .world
+            .resource_scope(|world, commands: Mut<ConsoleCommands>| {
+                commands.run(world, "pillar 1 2 3 4 dirt")
+            });
+        assert_eq!(output, Ok("Placed 4 dirt".to_string()));
+        assert!(app
+            .world
+            .resource::<Scripts>()
+            .host()
+            .behaviours
+            .contains_key(&Block::Lava));
+    }
+}
@@ -0,0 +1,339 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+
+use crate::{
+    block::Block,
+    camera::{FlyCam, FlyCamPlugin},
+    console::not_typing,
+    history::EditHistory,
+    world::World,
+    world_edit::block_picker,
+};
+
+/// Sculpts the terrain where the camera is looking, one stroke each time `B` is pressed
+pub struct SculptPlugin;
+
+impl Plugin for SculptPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Brush>().add_systems(
+            Update,
+            (
+                Self::sculpt.run_if(not_typing),
+                Self::draw_brush,
+                Self::brush_window,
+            ),
+        );
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BrushShape {
+    Sphere,
+    Cube,
+    /// Upright, as tall as it is wide
+    Cylinder,
+    /// Fills dips and flattens bumps within a sphere, ignoring the mode
+    Smooth,
+}
+
+impl BrushShape {
+    pub const ALL: [BrushShape; 4] = [
+        BrushShape::Sphere,
+        BrushShape::Cube,
+        BrushShape::Cylinder,
+        BrushShape::Smooth,
+    ];
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BrushMode {
+    /// Fills the air in the brush
+    Add,
+    /// Clears everything in the brush
+    Remove,
+    /// Swaps the blocks in the brush, leaving air alone
+    Paint,
+}
+
+impl BrushMode {
+    pub const ALL: [BrushMode; 3] = [BrushMode::Add, BrushMode::Remove, BrushMode::Paint];
+}
+
+#[derive(Resource)]
+pub struct Brush {
+    pub shape: BrushShape,
+    pub mode: BrushMode,
+    pub radius: f32,
+    /// The block added or painted
+    pub block: Block,
+}
+
+impl Default for Brush {
+    fn default() -> Self {
+        Self {
+            shape: BrushShape::Sphere,
+            mode: BrushMode::Add,
+            radius: 4.,
+            block: Block::Stone,
+        }
+    }
+}
+
+impl Brush {
+    /// Whether the block at `pos` is inside the brush
+    pub fn contains(&self, centre: Vec3, pos: IVec3) -> bool {
+        let offset = pos.as_vec3() + 0.5 - centre;
+
+        match self.shape {
+            BrushShape::Sphere | BrushShape::Smooth => offset.length() <= self.radius,
+            BrushShape::Cube => offset.abs().max_element() <= self.radius,
+            BrushShape::Cylinder => {
+                Vec2::new(offset.x, offset.z).length() <= self.radius
+                    && offset.y.abs() <= self.radius
+            }
+        }
+    }
+
+    /// Every block position the brush could touch
+    fn positions(&self, centre: Vec3) -> impl Iterator<Item = IVec3> + '_ {
+        let min = (centre - self.radius).floor().as_ivec3();
+        let max = (centre + self.radius).ceil().as_ivec3();
+
+        (min.x..=max.x)
+            .flat_map(move |x| {
+                (min.y..=max.y).flat_map(move |y| (min.z..=max.z).map(move |z| IVec3::new(x, y, z)))
+            })
+            .filter(move |pos| self.contains(centre, *pos))
+    }
+
+    /// Applies one stroke of the brush centred on `centre`.
+    ///
+    /// Returns the position and old block of every block that changed.
+    pub fn apply(&self, world: &mut World, centre: Vec3) -> Vec<(IVec3, Block)> {
+        let placed = match self.shape {
+            BrushShape::Smooth => self
+                .positions(centre)
+                .filter_map(|pos| Some((pos, smoothed(world, pos)?)))
+                .collect::<Vec<_>>(),
+            _ => self
+                .positions(centre)
+                .filter_map(|pos| {
+                    let old = world.get_block(pos)?;
+
+                    match self.mode {
+                        BrushMode::Add if old == Block::Air => Some((pos, self.block)),
+                        BrushMode::Remove => Some((pos, Block::Air)),
+                        BrushMode::Paint if old != Block::Air => Some((pos, self.block)),
+                        _ => None,
+                    }
+                })
+                .collect(),
+        };
+
+        world.set_blocks(placed)
+    }
+}
+
+/// What the block at `pos` becomes when smoothed, going by the majority of the 3x3x3 cube around it.
+///
+/// New solid blocks copy the most common solid block around them.
+fn smoothed(world: &World, pos: IVec3) -> Option<Block> {
+    let old = world.get_block(pos)?;
+
+    let mut solid = 0;
+    let mut counts = [0; Block::ALL.len()];
+    for x in -1..=1 {
+        for y in -1..=1 {
+            for z in -1..=1 {
+                let Some(block) = world.get_block(pos + IVec3::new(x, y, z)) else { continue; };
+
+                if block.is_opaque() {
+                    solid += 1;
+                    counts[block as usize] += 1;
+                }
+            }
+        }
+    }
+
+    match (old.is_opaque(), solid >= 14) {
+        (true, false) => Some(Block::Air),
+        (false, true) => Block::ALL
+            .into_iter()
+            .max_by_key(|block| counts[*block as usize]),
+        _ => None,
+    }
+}
+
+impl SculptPlugin {
+    fn hit_pos(world: &World, transform: &Transform) -> Option<Vec3> {
+        let hit = world.cast_ray(&FlyCamPlugin::create_ray(transform))?;
+        Some(hit.hit_pos)
+    }
+
+    pub fn sculpt(
+        keys: Res<Input<KeyCode>>,
+        query: Query<&Transform, With<FlyCam>>,
+        brush: Res<Brush>,
+        mut world: ResMut<World>,
+        mut history: ResMut<EditHistory>,
+    ) {
+        if !keys.just_pressed(KeyCode::B) {
+            return;
+        }
+
+        let Ok(transform) = query.get_single() else { return; };
+        let Some(centre) = Self::hit_pos(&world, transform) else { return; };
+
+        let changed = brush.apply(&mut world, centre);
+        history.record(&world, changed);
+    }
+
+    pub fn draw_brush(
+        query: Query<&Transform, With<FlyCam>>,
+        brush: Res<Brush>,
+        world: Res<World>,
+        mut gizmos: Gizmos,
+    ) {
+        let Ok(transform) = query.get_single() else { return; };
+        let Some(centre) = Self::hit_pos(&world, transform) else { return; };
+
+        match brush.shape {
+            BrushShape::Sphere | BrushShape::Smooth => {
+                gizmos.sphere(centre, Quat::IDENTITY, brush.radius, Color::WHITE);
+            }
+            BrushShape::Cube => {
+                gizmos.cuboid(
+                    Transform::from_translation(centre).with_scale(Vec3::splat(brush.radius * 2.)),
+                    Color::WHITE,
+                );
+            }
+            BrushShape::Cylinder => {
+                for y in [-brush.radius, brush.radius] {
+                    gizmos.circle(centre + Vec3::Y * y, Vec3::Y, brush.radius, Color::WHITE);
+                }
+            }
+        }
+    }
+
+    pub fn brush_window(mut ctx: EguiContexts, mut brush: ResMut<Brush>) {
+        egui::Window::new("Sculpt").show(ctx.ctx_mut(), |ui| {
+            ui.label("Press B to sculpt where you're looking");
+
+            egui::Grid::new("sculpt_grid")
+                .num_columns(2)
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.label("Shape");
+                    ui.horizontal(|ui| {
+                        for shape in BrushShape::ALL {
+                            ui.selectable_value(&mut brush.shape, shape, format!("{:?}", shape));
+                        }
+                    });
+                    ui.end_row();
+
+                    ui.label("Mode");
+                    ui.add_enabled_ui(brush.shape != BrushShape::Smooth, |ui| {
+                        ui.horizontal(|ui| {
+                            for mode in BrushMode::ALL {
+                                ui.selectable_value(&mut brush.mode, mode, format!("{:?}", mode));
+                            }
+                        });
+                    });
+                    ui.end_row();
+
+                    ui.label("Radius");
+                    ui.add(
+                        egui::DragValue::new(&mut brush.radius)
+                            .speed(0.1)
+                            .clamp_range(0.5..=16.),
+                    );
+                    ui.end_row();
+                });
+
+            block_picker(ui, "Block", &mut brush.block);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn empty_world() -> World {
+        let chunk_ids = [-1, 0]
+            .into_iter()
+            .flat_map(|x| [-1, 0].map(|z| IVec3::new(x, 0, z)));
+        World::with_chunks(chunk_ids, |_| Block::Air)
+    }
+
+    #[test]
+    fn shapes_add_and_remove() {
+        let centre = Vec3::new(0., 8., 0.);
+
+        for (shape, count) in [
+            // The blocks whose centres are within 2 of a block corner
+            (BrushShape::Sphere, 32),
+            (BrushShape::Cube, 64),
+            (BrushShape::Cylinder, 48),
+        ] {
+            let mut world = empty_world();
+            let brush = Brush {
+                shape,
+                radius: 2.,
+                ..default()
+            };
+
+            let changed = brush.apply(&mut world, centre);
+            assert_eq!(changed.len(), count, "{:?}", shape);
+            assert!(changed.iter().all(|(_, old)| *old == Block::Air));
+
+            let brush = Brush {
+                mode: BrushMode::Remove,
+                ..brush
+            };
+            brush.apply(&mut world, centre);
+            assert!(changed
+                .iter()
+                .all(|(pos, _)| world.get_block(*pos) == Some(Block::Air)));
+        }
+    }
+
+    #[test]
+    fn paint_leaves_air() {
+        let mut world = empty_world();
+        world.set_blocks([(IVec3::new(0, 8, 0), Block::Stone)]);
+
+        let brush = Brush {
+            mode: BrushMode::Paint,
+            block: Block::Dirt,
+            ..default()
+        };
+        let changed = brush.apply(&mut world, Vec3::new(0., 8., 0.));
+
+        assert_eq!(changed, vec![(IVec3::new(0, 8, 0), Block::Stone)]);
+        assert_eq!(world.get_block(IVec3::new(0, 8, 0)), Some(Block::Dirt));
+        assert_eq!(world.get_block(IVec3::new(1, 8, 0)), Some(Block::Air));
+    }
+
+    #[test]
+    fn smoothing_fills_holes_and_removes_spikes() {
+        let mut world = empty_world();
+        let ground = (-6..6)
+            .flat_map(|x| (-6..6).flat_map(move |z| (0..4).map(move |y| IVec3::new(x, y, z))))
+            .filter(|pos| *pos != IVec3::new(0, 3, 0))
+            .map(|pos| (pos, Block::Dirt))
+            .chain([(IVec3::new(3, 4, 3), Block::Stone)]);
+        world.set_blocks(ground);
+
+        let brush = Brush {
+            shape: BrushShape::Smooth,
+            radius: 6.,
+            ..default()
+        };
+        brush.apply(&mut world, Vec3::new(0., 4., 0.));
+
+        assert_eq!(world.get_block(IVec3::new(0, 3, 0)), Some(Block::Dirt));
+        assert_eq!(world.get_block(IVec3::new(3, 4, 3)), Some(Block::Air));
+        assert_eq!(world.get_block(IVec3::new(1, 2, 1)), Some(Block::Dirt));
+    }
+}
@@ -0,0 +1,185 @@
+use bevy::prelude::*;
+
+use crate::{
+    chunk::TerrainGen,
+    console::{AppConsoleExt, Args, ConsoleCommand},
+    light,
+    save::SaveDir,
+    world::{split_pos, world_mesh_gen, ChunkViewer, World},
+};
+
+/// Loads chunks around every `ChunkViewer`, and unloads them once no viewer is near
+pub struct ChunkStreamingPlugin;
+
+impl Plugin for ChunkStreamingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ViewDistance>()
+            .add_systems(
+                Update,
+                (Self::load_chunks, Self::unload_chunks).before(world_mesh_gen),
+            )
+            .add_console_command(
+                "viewdistance",
+                ConsoleCommand {
+                    usage: "[chunks]".into(),
+                    description: "Shows or sets how far away chunks are loaded".into(),
+                    run: Self::view_distance_command,
+                    remote: false,
+                },
+            );
+    }
+}
+
+#[derive(Resource, Clone)]
+pub struct ViewDistance {
+    /// The radius in chunks around the camera to keep loaded
+    pub chunks: i32,
+    /// How many chunks can be loaded each frame
+    pub per_frame: usize,
+}
+
+impl Default for ViewDistance {
+    fn default() -> Self {
+        Self {
+            chunks: 8,
+            per_frame: 4,
+        }
+    }
+}
+
+/// The terrain layer chunks within `radius` of `centre`, ignoring height
+pub fn chunks_around(centre: IVec3, radius: i32) -> impl Iterator<Item = IVec3> {
+    (-radius..=radius).flat_map(move |x| {
+        (-radius..=radius)
+            .filter(move |z| x * x + z * z <= radius * radius)
+            .map(move |z| IVec3::new(centre.x + x, 0, centre.z + z))
+    })
+}
+
+/// The horizontal distance squared from a chunk to the closest of `centres`
+pub fn distance_squared(centres: &[IVec3], chunk_id: IVec3) -> i32 {
+    centres
+        .iter()
+        .map(|centre| {
+            let offset = chunk_id - *centre;
+            offset.x * offset.x + offset.z * offset.z
+        })
+        .min()
+        .unwrap_or(i32::MAX)
+}
+
+impl ChunkStreamingPlugin {
+    fn camera_chunk(transform: &Transform) -> IVec3 {
+        split_pos(transform.translation.floor().as_ivec3()).0
+    }
+
+    /// The chunks each viewer is in
+    fn viewer_chunks(query: &Query<&Transform, With<ChunkViewer>>) -> Vec<IVec3> {
+        query.iter().map(Self::camera_chunk).collect()
+    }
+
+    fn view_distance_command(
+        world: &mut bevy::ecs::world::World,
+        args: &Args,
+    ) -> Result<String, String> {
+        args.at_most(1)?;
+        let mut view_distance = world.resource_mut::<ViewDistance>();
+
+        if let Some(chunks) = args.optional::<i32>(0, "chunks")? {
+            if chunks < 1 {
+                return Err("The view distance must be at least 1".to_string());
+            }
+            view_distance.chunks = chunks;
+        }
+
+        Ok(format!("View distance is {} chunks", view_distance.chunks))
+    }
+
+    /// Loads the missing chunks closest to any viewer, either from the save or by generating them
+    pub fn load_chunks(
+        query: Query<&Transform, With<ChunkViewer>>,
+        mut world: ResMut<World>,
+        terrain_gen: Res<TerrainGen>,
+        save_dir: Res<SaveDir>,
+        view_distance: Res<ViewDistance>,
+    ) {
+        let centres = Self::viewer_chunks(&query);
+        let radius = view_distance.chunks;
+
+        let mut missing = vec![];
+        for centre in &centres {
+            for chunk_id in chunks_around(*centre, radius) {
+                if !world.chunks.contains_key(&chunk_id) && !missing.contains(&chunk_id) {
+                    missing.push(chunk_id);
+                }
+            }
+        }
+
+        missing.sort_by_key(|chunk_id| distance_squared(&centres, *chunk_id));
+        missing.truncate(view_distance.per_frame);
+
+        let mut loaded = vec![];
+        for chunk_id in missing {
+            let chunk = save_dir.load_or_generate(chunk_id, &terrain_gen);
+            world.chunks.insert(chunk_id, chunk);
+            loaded.push(chunk_id);
+
+            // Chunks above or below the terrain only exist once something's been built there
+            for dir in [IVec3::Y, IVec3::NEG_Y] {
+                let mut chunk_id = chunk_id + dir;
+
+                while let Some(chunk) = save_dir.load_chunk(chunk_id) {
+                    world.chunks.insert(chunk_id, chunk);
+                    loaded.push(chunk_id);
+                    chunk_id += dir;
+                }
+            }
+        }
+
+        for &chunk_id in &loaded {
+            world.invalidate_mesh(chunk_id);
+        }
+
+        if !loaded.is_empty() {
+            light::relight_chunks(&mut world, &loaded);
+        }
+    }
+
+    /// Unloads chunks just outside the view distance of every viewer, saving any that were edited
+    pub fn unload_chunks(
+        mut commands: Commands,
+        query: Query<&Transform, With<ChunkViewer>>,
+        mut world: ResMut<World>,
+        save_dir: Res<SaveDir>,
+        view_distance: Res<ViewDistance>,
+    ) {
+        let centres = Self::viewer_chunks(&query);
+        if centres.is_empty() {
+            return;
+        }
+        // Leave a margin so chunks on the edge don't flicker in and out
+        let radius = view_distance.chunks + 1;
+
+        let far = world
+            .chunks
+            .keys()
+            .copied()
+            .filter(|chunk_id| distance_squared(&centres, *chunk_id) > radius * radius)
+            .collect::<Vec<_>>();
+
+        for chunk_id in far {
+            let modified = world.modified.contains(&chunk_id);
+            let Some((chunk, entity)) = world.unload_chunk(chunk_id) else { continue; };
+
+            if modified {
+                if let Err(err) = save_dir.save_chunk(&chunk) {
+                    error!("Failed to save chunk {}: {}", chunk_id, err);
+                }
+            }
+
+            if let Some(entity) = entity {
+                commands.entity(entity).despawn();
+            }
+        }
+    }
+}
@@ -0,0 +1,211 @@
+use bevy::{prelude::*, utils::HashSet};
+
+use crate::{
+    block::Block,
+    chunk::{CHUNK_SIZE_I32, CHUNK_VOLUME},
+    world::World,
+};
+
+/// How many random blocks in each loaded chunk are ticked every tick
+pub const RANDOM_TICKS_PER_CHUNK: u32 = 3;
+
+/// When blocks get updated on their own, without anyone editing them.
+///
+/// Scheduled ticks update a block a set number of ticks later, like lava flowing,
+/// and random ticks pick blocks at random in every loaded chunk, for slow changes like grass spreading.
+pub struct BlockTicks {
+    /// The number of ticks so far
+    pub tick: u64,
+    /// Positions to update and the tick to update them on, sorted by tick and then by when they were scheduled
+    scheduled: Vec<(u64, IVec3)>,
+    /// The blocks random ticks are sent for, others are skipped
+    pub random_blocks: HashSet<Block>,
+    /// Random ticks land in the same places for the same seed
+    rng: u64,
+}
+
+impl Default for BlockTicks {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+/// A block being ticked, sent by `tick_blocks`
+#[derive(Event, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BlockTicked {
+    pub pos: IVec3,
+    pub block: Block,
+    /// A random tick rather than a scheduled one
+    pub random: bool,
+}
+
+impl BlockTicks {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            tick: 0,
+            scheduled: vec![],
+            random_blocks: HashSet::default(),
+            rng: seed,
+        }
+    }
+
+    /// Updates the block at `pos` in `delay` ticks, at least 1
+    pub fn schedule(&mut self, pos: IVec3, delay: u64) {
+        let tick = self.tick + delay.max(1);
+        let index = self.scheduled.partition_point(|(at, _)| *at <= tick);
+        self.scheduled.insert(index, (tick, pos));
+    }
+
+    /// How many updates are waiting
+    pub fn scheduled(&self) -> usize {
+        self.scheduled.len()
+    }
+
+    /// SplitMix64, which is plenty for picking blocks and works with any seed
+    fn next_random(&mut self) -> u64 {
+        self.rng = self.rng.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.rng;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+impl World {
+    /// Updates the block at `pos` in `delay` ticks
+    pub fn schedule_tick(&mut self, pos: IVec3, delay: u64) {
+        self.ticks.schedule(pos, delay);
+    }
+
+    /// Advances a tick, returning the blocks whose scheduled updates are due in the order they were scheduled,
+    /// followed by the random ticks in each loaded chunk.
+    ///
+    /// Updates in unloaded chunks are dropped.
+    pub fn advance_ticks(&mut self) -> Vec<BlockTicked> {
+        self.ticks.tick += 1;
+        let tick = self.ticks.tick;
+
+        let due = self.ticks.scheduled.partition_point(|(at, _)| *at <= tick);
+        let mut ticked = self
+            .ticks
+            .scheduled
+            .drain(..due)
+            .collect::<Vec<_>>()
+            .into_iter()
+            .filter_map(|(_, pos)| {
+                Some(BlockTicked {
+                    pos,
+                    block: self.get_block(pos)?,
+                    random: false,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        if self.ticks.random_blocks.is_empty() {
+            return ticked;
+        }
+
+        // Sorted, as the map's order depends on how the chunks were loaded
+        let mut chunk_ids = self.chunks.keys().copied().collect::<Vec<_>>();
+        chunk_ids.sort_by_key(|id| (id.x, id.y, id.z));
+
+        for chunk_id in chunk_ids {
+            for _ in 0..RANDOM_TICKS_PER_CHUNK {
+                let index = (self.ticks.next_random() % CHUNK_VOLUME as u64) as i32;
+                let local = IVec3::new(
+                    index % CHUNK_SIZE_I32,
+                    index / CHUNK_SIZE_I32 % CHUNK_SIZE_I32,
+                    index / (CHUNK_SIZE_I32 * CHUNK_SIZE_I32),
+                );
+                let pos = chunk_id * CHUNK_SIZE_I32 + local;
+
+                let block = self.chunks[&chunk_id].get_or_air(local.x, local.y, local.z);
+                if self.ticks.random_blocks.contains(&block) {
+                    ticked.push(BlockTicked {
+                        pos,
+                        block,
+                        random: true,
+                    });
+                }
+            }
+        }
+
+        ticked
+    }
+}
+
+/// Advances the world's block ticks, once per fixed update
+pub fn tick_blocks(mut world: ResMut<World>, mut events: EventWriter<BlockTicked>) {
+    let ticked = world.advance_ticks();
+    events.send_batch(ticked);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::CHUNK_SIZE;
+
+    fn dirt_world(seed: u64) -> World {
+        let world = World::with_chunks([IVec3::ZERO, IVec3::X], |pos| {
+            if pos.y == 0 {
+                Block::Dirt
+            } else {
+                Block::Air
+            }
+        });
+
+        World {
+            ticks: BlockTicks::new(seed),
+            ..world
+        }
+    }
+
+    #[test]
+    fn scheduled_ticks_run_in_order() {
+        let mut world = dirt_world(0);
+        let a = IVec3::new(1, 0, 0);
+        let b = IVec3::new(2, 0, 0);
+        let unloaded = IVec3::new(-1, 0, 0);
+
+        world.schedule_tick(a, 2);
+        world.schedule_tick(unloaded, 1);
+        world.schedule_tick(b, 1);
+        world.schedule_tick(b, 2);
+        world.schedule_tick(a, 0);
+
+        let pos = |ticked: Vec<BlockTicked>| ticked.iter().map(|tick| tick.pos).collect::<Vec<_>>();
+        assert_eq!(pos(world.advance_ticks()), [b, a]);
+        assert_eq!(pos(world.advance_ticks()), [a, b]);
+        assert_eq!(pos(world.advance_ticks()), []);
+        assert_eq!(world.ticks.scheduled(), 0);
+    }
+
+    #[test]
+    fn random_ticks_are_deterministic() {
+        let random_ticks = |seed| {
+            let mut world = dirt_world(seed);
+            world.ticks.random_blocks.insert(Block::Dirt);
+
+            (0..1000)
+                .flat_map(|_| world.advance_ticks())
+                .collect::<Vec<_>>()
+        };
+
+        let ticked = random_ticks(7);
+        assert_eq!(ticked, random_ticks(7));
+        assert_ne!(ticked, random_ticks(8));
+
+        // Dirt is one layer of each of the 2 chunks, so that share of their random ticks land on it
+        let expected = (2 * 1000 * RANDOM_TICKS_PER_CHUNK) as f32 / CHUNK_SIZE as f32;
+        let landed = ticked.len() as f32;
+        assert!(
+            (expected * 0.8..expected * 1.2).contains(&landed),
+            "{landed} not near {expected}"
+        );
+        assert!(ticked
+            .iter()
+            .all(|tick| tick.random && tick.block == Block::Dirt && tick.pos.y == 0));
+        assert!(ticked.iter().any(|tick| tick.pos.x < CHUNK_SIZE_I32));
+        assert!(ticked.iter().any(|tick| tick.pos.x >= CHUNK_SIZE_I32));
+    }
+}
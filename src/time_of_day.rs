@@ -0,0 +1,165 @@
+use std::f32::consts::TAU;
+
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+
+pub struct TimeOfDayPlugin;
+
+impl Plugin for TimeOfDayPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(TimeOfDay::default())
+            .add_systems(Update, Self::advance);
+    }
+}
+
+/// Moves the sun and tints the sky to match the time of day
+pub struct SkyPlugin;
+
+impl Plugin for SkyPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, TimeOfDayPlugin::spawn_sun)
+            .add_systems(Update, TimeOfDayPlugin::apply.after(TimeOfDayPlugin::advance));
+    }
+}
+
+/// A window for changing the time of day, which needs `EguiPlugin`
+pub struct TimeOfDayWindowPlugin;
+
+impl Plugin for TimeOfDayWindowPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, TimeOfDayPlugin::egui_time_of_day);
+    }
+}
+
+/// Marks the directional light that acts as the sun
+#[derive(Component)]
+pub struct Sun;
+
+#[derive(Resource)]
+pub struct TimeOfDay {
+    /// The fraction of the day that has passed, where 0 is midnight and 0.5 is noon
+    pub time: f32,
+    /// Length of a full day in seconds, at a speed of 1
+    pub day_length: f32,
+    pub speed: f32,
+    pub paused: bool,
+}
+
+impl Default for TimeOfDay {
+    fn default() -> Self {
+        Self {
+            time: 0.35,
+            day_length: 600.,
+            speed: 1.,
+            paused: false,
+        }
+    }
+}
+
+impl TimeOfDay {
+    /// The direction pointing towards the sun
+    pub fn sun_dir(&self) -> Vec3 {
+        let angle = (self.time - 0.25) * TAU;
+        Vec3::new(angle.cos(), angle.sin(), 0.2).normalize()
+    }
+
+    /// How much daylight there is, from 0 at night to 1 during the day
+    pub fn daylight(&self) -> f32 {
+        (self.sun_dir().y * 4.).clamp(0., 1.)
+    }
+}
+
+fn lerp_color(a: Color, b: Color, t: f32) -> Color {
+    let [ar, ag, ab, aa] = a.as_rgba_f32();
+    let [br, bg, bb, ba] = b.as_rgba_f32();
+
+    Color::rgba(
+        ar + (br - ar) * t,
+        ag + (bg - ag) * t,
+        ab + (bb - ab) * t,
+        aa + (ba - aa) * t,
+    )
+}
+
+impl TimeOfDayPlugin {
+    pub fn spawn_sun(mut commands: Commands) {
+        commands.spawn((
+            DirectionalLightBundle {
+                directional_light: DirectionalLight {
+                    color: Color::WHITE,
+                    illuminance: 20_000.,
+                    shadows_enabled: true,
+                    ..default()
+                },
+                transform: Transform::from_xyz(1., 2., 0.2)
+                    .looking_at(Vec3::new(0., 0., 0.), Vec3::Y),
+                ..default()
+            },
+            Sun,
+        ));
+    }
+
+    pub fn advance(mut time_of_day: ResMut<TimeOfDay>, time: Res<Time>) {
+        if time_of_day.paused {
+            return;
+        }
+
+        let delta = time.delta_seconds() * time_of_day.speed / time_of_day.day_length;
+        time_of_day.time = (time_of_day.time + delta).rem_euclid(1.);
+    }
+
+    pub fn apply(
+        time_of_day: Res<TimeOfDay>,
+        mut sun: Query<(&mut Transform, &mut DirectionalLight), With<Sun>>,
+        mut ambient: ResMut<AmbientLight>,
+        mut clear_color: ResMut<ClearColor>,
+    ) {
+        let sun_dir = time_of_day.sun_dir();
+        let daylight = time_of_day.daylight();
+
+        for (mut transform, mut light) in &mut sun {
+            *transform = Transform::from_translation(sun_dir).looking_at(Vec3::ZERO, Vec3::Y);
+            light.illuminance = 20_000. * daylight;
+            // Redder near the horizon
+            light.color = lerp_color(
+                Color::rgb(1., 0.6, 0.3),
+                Color::WHITE,
+                (sun_dir.y * 2.).clamp(0., 1.),
+            );
+        }
+
+        ambient.brightness = 0.02 + 0.28 * daylight;
+        ambient.color = lerp_color(Color::rgb(0.4, 0.45, 0.8), Color::WHITE, daylight);
+
+        clear_color.0 = lerp_color(
+            Color::rgb(0.01, 0.01, 0.04),
+            Color::rgb(0.5, 0.7, 1.),
+            daylight,
+        );
+    }
+
+    pub fn egui_time_of_day(mut ctx: EguiContexts, mut time_of_day: ResMut<TimeOfDay>) {
+        egui::Window::new("Time of Day").show(ctx.ctx_mut(), |ui| {
+            egui::Grid::new("time_of_day_grid")
+                .num_columns(2)
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.label("Time");
+                    ui.add(egui::Slider::new(&mut time_of_day.time, 0.0..=1.));
+                    ui.end_row();
+
+                    ui.label("Speed");
+                    ui.add(
+                        egui::DragValue::new(&mut time_of_day.speed)
+                            .speed(0.1)
+                            .clamp_range(0..=100),
+                    );
+                    ui.end_row();
+
+                    ui.label("Paused");
+                    ui.checkbox(&mut time_of_day.paused, "");
+                    ui.end_row();
+                });
+        });
+    }
+}
@@ -0,0 +1,386 @@
+use std::{f32::consts::FRAC_PI_2, fs, io, path::Path};
+
+use bevy::prelude::*;
+
+use crate::{
+    block::Block,
+    camera::{FlyCam, FlyCamPlugin},
+    chunk::{Chunk, TerrainGen},
+    history::EditHistory,
+    light,
+    save::SaveDir,
+    world::{rotate_in_box, rotated_size, split_pos, World},
+};
+
+/// Stamps MagicaVoxel models dropped onto the window into the world,
+/// centred on the block under the crosshair and turned to face away from the camera
+pub struct VoxImportPlugin;
+
+impl Plugin for VoxImportPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, Self::import_dropped);
+    }
+}
+
+/// A single model from a `.vox` file, converted to this world's axes
+pub struct VoxModel {
+    pub size: IVec3,
+    /// The position and palette index of each voxel
+    pub voxels: Vec<(IVec3, u8)>,
+}
+
+pub struct VoxFile {
+    pub models: Vec<VoxModel>,
+    /// Palette index `i` uses colour `i - 1`, or None if the file uses the default palette
+    pub palette: Option<Vec<[u8; 4]>>,
+}
+
+/// Reads the little endian values a `.vox` file is made of
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if len > self.bytes.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "Vox file ends early",
+            ));
+        }
+
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn i32(&mut self) -> io::Result<i32> {
+        Ok(self.u32()? as i32)
+    }
+
+    /// Reads a chunk header, returning its id, content and children
+    fn chunk(&mut self) -> io::Result<(&'a [u8], Reader<'a>, Reader<'a>)> {
+        let id = self.take(4)?;
+        let content_len = self.u32()? as usize;
+        let children_len = self.u32()? as usize;
+
+        let content = Reader {
+            bytes: self.take(content_len)?,
+        };
+        let children = Reader {
+            bytes: self.take(children_len)?,
+        };
+
+        Ok((id, content, children))
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+impl VoxFile {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Self::parse(&fs::read(path)?)
+    }
+
+    /// Parses the models and palette, ignoring the scene graph and materials
+    pub fn parse(bytes: &[u8]) -> io::Result<Self> {
+        let mut reader = Reader { bytes };
+
+        if reader.take(4)? != b"VOX " {
+            return Err(invalid_data("Not a vox file"));
+        }
+        let _version = reader.u32()?;
+
+        let (id, _, mut children) = reader.chunk()?;
+        if id != b"MAIN" {
+            return Err(invalid_data("Vox file has no MAIN chunk"));
+        }
+
+        let mut models = vec![];
+        let mut palette = None;
+        // Each XYZI chunk follows the SIZE chunk it belongs to
+        let mut size = None;
+
+        while !children.bytes.is_empty() {
+            let (id, mut content, _) = children.chunk()?;
+
+            match id {
+                b"SIZE" => {
+                    // MagicaVoxel is z up
+                    let (x, y, z) = (content.i32()?, content.i32()?, content.i32()?);
+                    size = Some(IVec3::new(x, z, y));
+                }
+                b"XYZI" => {
+                    let size = size
+                        .take()
+                        .ok_or_else(|| invalid_data("XYZI without SIZE"))?;
+                    let count = content.u32()? as usize;
+                    let data = content.take(count * 4)?;
+
+                    let voxels = data
+                        .chunks(4)
+                        .map(|voxel| {
+                            // Flipping the vox y keeps the model from being mirrored
+                            let pos = IVec3::new(
+                                voxel[0] as i32,
+                                voxel[2] as i32,
+                                size.z - 1 - voxel[1] as i32,
+                            );
+
+                            if pos.cmplt(IVec3::ZERO).any() || pos.cmpge(size).any() {
+                                Err(invalid_data("Voxel outside its model"))
+                            } else {
+                                Ok((pos, voxel[3]))
+                            }
+                        })
+                        .collect::<io::Result<_>>()?;
+
+                    models.push(VoxModel { size, voxels });
+                }
+                b"RGBA" => {
+                    let data = content.take(256 * 4)?;
+                    palette = Some(
+                        data.chunks(4)
+                            .map(|colour| [colour[0], colour[1], colour[2], colour[3]])
+                            .collect(),
+                    );
+                }
+                _ => {}
+            }
+        }
+
+        Ok(Self { models, palette })
+    }
+
+    /// The block each palette index is imported as.
+    ///
+    /// Files without a palette are imported as stone,
+    /// as the default palette isn't stored.
+    pub fn blocks(&self) -> [Block; 256] {
+        let mut blocks = [Block::Air; 256];
+
+        for (index, block) in blocks.iter_mut().enumerate().skip(1) {
+            *block = match &self.palette {
+                Some(palette) => block_for_colour(palette[index - 1]),
+                None => Block::Stone,
+            };
+        }
+
+        blocks
+    }
+}
+
+/// Picks the block with the closest colour, or air if the colour is transparent
+pub fn block_for_colour([r, g, b, a]: [u8; 4]) -> Block {
+    if a == 0 {
+        return Block::Air;
+    }
+
+    Block::ALL
+        .into_iter()
+        .filter_map(|block| {
+            let [br, bg, bb] = block.colour()?;
+            let distance = [(r, br), (g, bg), (b, bb)]
+                .into_iter()
+                .map(|(x, y)| (x as i32 - y as i32).pow(2))
+                .sum::<i32>();
+
+            Some((block, distance))
+        })
+        .min_by_key(|(_, distance)| *distance)
+        .map_or(Block::Air, |(block, _)| block)
+}
+
+impl VoxModel {
+    /// Stamps the model into the world with its lowest corner at `origin`,
+    /// after turning it `quarter_turns` times anticlockwise about the y axis.
+    ///
+    /// Chunks that aren't loaded are made with `create_chunk`.
+    /// Returns the position and old block of every block that changed.
+    pub fn stamp(
+        &self,
+        world: &mut World,
+        blocks: &[Block; 256],
+        origin: IVec3,
+        quarter_turns: u32,
+        mut create_chunk: impl FnMut(IVec3) -> Chunk,
+    ) -> Vec<(IVec3, Block)> {
+        let placed = self
+            .voxels
+            .iter()
+            .map(|(pos, index)| {
+                (
+                    origin + rotate_in_box(*pos, self.size, quarter_turns),
+                    blocks[*index as usize],
+                )
+            })
+            .filter(|(_, block)| *block != Block::Air)
+            .collect::<Vec<_>>();
+
+        let mut created = vec![];
+        for (pos, _) in &placed {
+            let chunk_id = split_pos(*pos).0;
+
+            if !world.chunks.contains_key(&chunk_id) {
+                world.chunks.insert(chunk_id, create_chunk(chunk_id));
+                world.invalidate_mesh(chunk_id);
+                // New chunks off the terrain layer are only loaded again if they're saved
+                world.modified.insert(chunk_id);
+                created.push(chunk_id);
+            }
+        }
+
+        if !created.is_empty() {
+            light::relight_chunks(world, &created);
+        }
+
+        world.set_blocks(placed)
+    }
+}
+
+impl VoxImportPlugin {
+    pub fn import_dropped(
+        mut events: EventReader<FileDragAndDrop>,
+        query: Query<&Transform, With<FlyCam>>,
+        mut world: ResMut<World>,
+        terrain_gen: Res<TerrainGen>,
+        save_dir: Res<SaveDir>,
+        mut history: ResMut<EditHistory>,
+    ) {
+        for event in events.iter() {
+            let FileDragAndDrop::DroppedFile { path_buf, .. } = event else { continue; };
+            if path_buf.extension().and_then(|ext| ext.to_str()) != Some("vox") {
+                continue;
+            }
+
+            let file = match VoxFile::open(path_buf) {
+                Ok(file) => file,
+                Err(err) => {
+                    error!("Failed to read {:?}: {}", path_buf, err);
+                    continue;
+                }
+            };
+            let Some(model) = file.models.first() else {
+                warn!("{:?} has no models", path_buf);
+                continue;
+            };
+            if file.models.len() > 1 {
+                warn!(
+                    "{:?} has several models, only the first is imported",
+                    path_buf
+                );
+            }
+
+            let Ok(transform) = query.get_single() else { return; };
+
+            let forward = transform.forward();
+            let quarter_turns =
+                ((forward.x.atan2(forward.z) / FRAC_PI_2).round() as i32).rem_euclid(4) as u32;
+
+            let target = FlyCamPlugin::target(&world, transform).map_or(
+                transform.translation.floor().as_ivec3(),
+                |(_, place_pos)| place_pos,
+            );
+
+            let size = rotated_size(model.size, quarter_turns);
+            let origin = target - IVec3::new(size.x / 2, 0, size.z / 2);
+
+            let changed = model.stamp(
+                &mut world,
+                &file.blocks(),
+                origin,
+                quarter_turns,
+                |chunk_id| save_dir.load_or_generate(chunk_id, &terrain_gen),
+            );
+            history.record(&world, changed);
+            info!("Imported {:?} at {}", path_buf, origin);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::CHUNK_SIZE_I32;
+
+    fn chunk(id: &[u8; 4], content: &[u8], children: &[u8]) -> Vec<u8> {
+        let mut bytes = id.to_vec();
+        bytes.extend((content.len() as u32).to_le_bytes());
+        bytes.extend((children.len() as u32).to_le_bytes());
+        bytes.extend(content);
+        bytes.extend(children);
+        bytes
+    }
+
+    /// A 2x3x4 (x, y, z up) model with two voxels,
+    /// with palette index 1 lava coloured and index 2 transparent
+    fn test_file() -> Vec<u8> {
+        let size = [2u32, 3, 4]
+            .into_iter()
+            .flat_map(u32::to_le_bytes)
+            .collect::<Vec<_>>();
+        let mut xyzi = 2u32.to_le_bytes().to_vec();
+        xyzi.extend([1, 0, 3, 1, 0, 2, 0, 2]);
+        let mut rgba = vec![0; 256 * 4];
+        rgba[..4].copy_from_slice(&[230, 100, 20, 255]);
+
+        let mut children = chunk(b"SIZE", &size, &[]);
+        children.extend(chunk(b"XYZI", &xyzi, &[]));
+        children.extend(chunk(b"nTRN", &[0; 8], &[]));
+        children.extend(chunk(b"RGBA", &rgba, &[]));
+
+        let mut bytes = b"VOX ".to_vec();
+        bytes.extend(150u32.to_le_bytes());
+        bytes.extend(chunk(b"MAIN", &[], &children));
+        bytes
+    }
+
+    #[test]
+    fn parses_models_into_world_axes() {
+        let file = VoxFile::parse(&test_file()).unwrap();
+
+        assert_eq!(file.models.len(), 1);
+        let model = &file.models[0];
+        assert_eq!(model.size, IVec3::new(2, 4, 3));
+        assert_eq!(
+            model.voxels,
+            vec![(IVec3::new(1, 3, 2), 1), (IVec3::new(0, 0, 0), 2)]
+        );
+
+        let blocks = file.blocks();
+        assert_eq!(blocks[0], Block::Air);
+        assert_eq!(blocks[1], Block::Lava);
+        assert_eq!(blocks[2], Block::Air);
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let bytes = test_file();
+
+        assert!(VoxFile::parse(&bytes[..bytes.len() - 10]).is_err());
+        assert!(VoxFile::parse(b"PNG ").is_err());
+    }
+
+    #[test]
+    fn stamping_creates_missing_chunks() {
+        let file = VoxFile::parse(&test_file()).unwrap();
+        let mut world = World::new();
+
+        let origin = IVec3::new(-1, CHUNK_SIZE_I32 - 1, 0);
+        file.models[0].stamp(&mut world, &file.blocks(), origin, 0, Chunk::new);
+
+        let pos = origin + IVec3::new(1, 3, 2);
+        assert_eq!(world.get_block(pos), Some(Block::Lava));
+        // Transparent voxels don't need their chunk
+        assert_eq!(world.get_block(origin), None);
+        assert!(world.modified.contains(&split_pos(pos).0));
+        assert!(world.invalid_meshes.contains(&split_pos(pos).0));
+    }
+}
@@ -1,33 +1,114 @@
-use bevy::{prelude::*, utils::HashMap};
+use std::time::Instant;
+
+use bevy::{
+    diagnostic::Diagnostics,
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
 use parry3d::{na, query::RayCast};
 
 use crate::{
     block::Block,
-    chunk::Chunk,
+    block_material::BlockMaterial,
+    chunk::{Chunk, TerrainGen, CHUNK_SIZE_I32},
+    culling::Connectivity,
+    custom_diagnostics::CustomDiagnosticsPlugin,
+    history::EditHistory,
+    light,
     mesh::{mesh_to_tri_mesh, Direction},
+    ticks::BlockTicks,
 };
 
+/// Splits a global block position into its chunk id and the position within that chunk
+pub fn split_pos(pos: IVec3) -> (IVec3, IVec3) {
+    (
+        IVec3::new(
+            pos.x.div_euclid(CHUNK_SIZE_I32),
+            pos.y.div_euclid(CHUNK_SIZE_I32),
+            pos.z.div_euclid(CHUNK_SIZE_I32),
+        ),
+        IVec3::new(
+            pos.x.rem_euclid(CHUNK_SIZE_I32),
+            pos.y.rem_euclid(CHUNK_SIZE_I32),
+            pos.z.rem_euclid(CHUNK_SIZE_I32),
+        ),
+    )
+}
+
+/// Turns a position within a box of `size` anticlockwise about the y axis,
+/// keeping it within the turned box
+pub fn rotate_in_box(pos: IVec3, size: IVec3, quarter_turns: u32) -> IVec3 {
+    match quarter_turns % 4 {
+        0 => pos,
+        1 => IVec3::new(pos.z, pos.y, size.x - 1 - pos.x),
+        2 => IVec3::new(size.x - 1 - pos.x, pos.y, size.z - 1 - pos.z),
+        _ => IVec3::new(size.z - 1 - pos.z, pos.y, pos.x),
+    }
+}
+
+/// The size of a box after being turned `quarter_turns` times about the y axis
+pub fn rotated_size(size: IVec3, quarter_turns: u32) -> IVec3 {
+    match quarter_turns % 4 {
+        0 | 2 => size,
+        _ => IVec3::new(size.z, size.y, size.x),
+    }
+}
+
 #[derive(Clone)]
 pub struct WorldHit {
     pub chunk_id: IVec3,
     pub hit_pos: Vec3,
-    pub dst: f32,
 }
 
 pub struct WorldTarget {
     pub local_pos: IVec3,
+    /// The air block in front of the target, where a new block would go
+    pub place_pos: IVec3,
+}
+
+/// A request to change a single block, such as from clicking on it.
+///
+/// Applied by `apply_block_edits`, or sent to the server when connected to one.
+#[derive(Event, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BlockEdit {
+    pub pos: IVec3,
     pub block: Block,
 }
 
+/// Sent when a player's edit or a script changes a block, for gameplay to react to
+#[derive(Event, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BlockEdited {
+    pub pos: IVec3,
+    pub old: Block,
+    pub new: Block,
+}
+
+/// Marks the camera that chunks are loaded, LODed and culled around
+#[derive(Component)]
+pub struct ChunkViewer;
+
 #[derive(Resource)]
 pub struct World {
     pub chunks: HashMap<IVec3, Chunk>,
     pub colliders: HashMap<IVec3, parry3d::shape::TriMesh>,
     pub meshes: HashMap<IVec3, Entity>,
+    /// The LOD each chunk is meshed at, missing chunks use full detail
+    pub lods: HashMap<IVec3, u32>,
+    /// Updated alongside the meshes
+    pub connectivity: HashMap<IVec3, Connectivity>,
 
     pub invalid_meshes: Vec<IVec3>,
+    /// Chunks edited since they were loaded
+    pub modified: HashSet<IVec3>,
+    pub ticks: BlockTicks,
+
+    pub material: Handle<BlockMaterial>,
+}
 
-    pub material: Handle<StandardMaterial>,
+impl Default for World {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl World {
@@ -36,14 +117,141 @@ impl World {
             chunks: HashMap::default(),
             colliders: HashMap::default(),
             meshes: HashMap::default(),
+            lods: HashMap::default(),
+            connectivity: HashMap::default(),
 
             invalid_meshes: vec![],
+            modified: HashSet::default(),
+            ticks: BlockTicks::default(),
 
             material: Handle::default(),
         }
     }
 
-    /// Returns the chunk pos and the hit distance
+    /// Gets a block from its global position, returning None if its chunk isn't loaded
+    pub fn get_block(&self, pos: IVec3) -> Option<Block> {
+        let (chunk_id, local) = split_pos(pos);
+        self.chunks
+            .get(&chunk_id)
+            .map(|chunk| chunk.get_or_air(local.x, local.y, local.z))
+    }
+
+    /// Sets a block from its global position, updating light and invalidating meshes.
+    ///
+    /// Returns the old block, or None if its chunk isn't loaded.
+    pub fn set_block(&mut self, pos: IVec3, block: Block) -> Option<Block> {
+        let (chunk_id, local) = split_pos(pos);
+        let chunk = self.chunks.get_mut(&chunk_id)?;
+
+        let old = std::mem::replace(
+            &mut *chunk.get_mut(local.x as usize, local.y as usize, local.z as usize),
+            block,
+        );
+
+        light::update_block(self, pos);
+        self.invalidate_mesh(chunk_id);
+        self.modified.insert(chunk_id);
+
+        Some(old)
+    }
+
+    /// Sets many blocks at once, relighting and invalidating each affected chunk only once.
+    ///
+    /// Blocks in unloaded chunks are skipped.
+    /// Returns the position and old block of every block that changed, in order.
+    pub fn set_blocks(
+        &mut self,
+        blocks: impl IntoIterator<Item = (IVec3, Block)>,
+    ) -> Vec<(IVec3, Block)> {
+        let mut changed = vec![];
+        let mut chunk_ids = HashSet::default();
+
+        for (pos, block) in blocks {
+            let (chunk_id, local) = split_pos(pos);
+            let Some(chunk) = self.chunks.get_mut(&chunk_id) else { continue; };
+
+            let old = std::mem::replace(
+                &mut *chunk.get_mut(local.x as usize, local.y as usize, local.z as usize),
+                block,
+            );
+
+            if old != block {
+                changed.push((pos, old));
+                chunk_ids.insert(chunk_id);
+            }
+        }
+
+        // Light can spread out of the edited chunks into their neighbours
+        let mut relight = chunk_ids.clone();
+        for chunk_id in &chunk_ids {
+            for dir in Direction::iter() {
+                let neighbour = *chunk_id + dir.normal();
+                if self.chunks.contains_key(&neighbour) {
+                    relight.insert(neighbour);
+                }
+            }
+        }
+
+        if !relight.is_empty() {
+            light::relight_chunks(self, &relight.into_iter().collect::<Vec<_>>());
+        }
+
+        for chunk_id in chunk_ids {
+            self.invalidate_mesh(chunk_id);
+            self.modified.insert(chunk_id);
+        }
+
+        changed
+    }
+
+    /// Queues a chunk's mesh to be rebuilt, if it isn't already
+    pub fn invalidate_mesh(&mut self, chunk_id: IVec3) {
+        if !self.invalid_meshes.contains(&chunk_id) {
+            self.invalid_meshes.push(chunk_id);
+        }
+    }
+
+    /// Generates every loaded chunk again, throwing away any edits
+    pub fn regenerate(&mut self, terrain_gen: &TerrainGen) {
+        for chunk in self.chunks.values_mut() {
+            chunk.generate(terrain_gen);
+        }
+
+        let chunk_ids = self.chunks.keys().copied().collect::<Vec<_>>();
+        light::relight_chunks(self, &chunk_ids);
+        self.invalid_meshes = chunk_ids;
+    }
+
+    /// Builds a chunk's mesh at its LOD, sampling light across chunk borders
+    pub fn build_chunk_mesh(&self, chunk_id: IVec3) -> Mesh {
+        let lod = self.lods.get(&chunk_id).copied().unwrap_or(0);
+        self.build_chunk_mesh_at(chunk_id, lod)
+    }
+
+    /// Builds a chunk's mesh at a specific LOD, ignoring the one it's displayed at
+    pub fn build_chunk_mesh_at(&self, chunk_id: IVec3, lod: u32) -> Mesh {
+        let origin = chunk_id * CHUNK_SIZE_I32;
+
+        self.chunks[&chunk_id].build_mesh(lod, |local| {
+            light::brightness(light::level_at(self, origin + local))
+        })
+    }
+
+    /// Removes a chunk along with its collider and LOD,
+    /// returning the chunk and its mesh entity
+    pub fn unload_chunk(&mut self, chunk_id: IVec3) -> Option<(Chunk, Option<Entity>)> {
+        let chunk = self.chunks.remove(&chunk_id)?;
+
+        self.colliders.remove(&chunk_id);
+        self.lods.remove(&chunk_id);
+        self.connectivity.remove(&chunk_id);
+        self.modified.remove(&chunk_id);
+        self.invalid_meshes.retain(|id| *id != chunk_id);
+
+        Some((chunk, self.meshes.remove(&chunk_id)))
+    }
+
+    /// Returns the closest chunk hit and where it was hit
     pub fn cast_ray(&self, ray: &parry3d::query::Ray) -> Option<WorldHit> {
         self.colliders
             .iter()
@@ -53,9 +261,9 @@ impl World {
                     tri_mesh.cast_ray(
                         &na::Isometry3::from_parts(
                             na::Translation3::new(
-                                16. * chunk_id.x as f32,
-                                16. * chunk_id.y as f32,
-                                16. * chunk_id.z as f32,
+                                (CHUNK_SIZE_I32 * chunk_id.x) as f32,
+                                (CHUNK_SIZE_I32 * chunk_id.y) as f32,
+                                (CHUNK_SIZE_I32 * chunk_id.z) as f32,
                             ),
                             na::UnitQuaternion::from_euler_angles(0., 0., 0.),
                         ),
@@ -69,7 +277,6 @@ impl World {
             .min_by(|(_, dst1), (_, dst2)| dst1.partial_cmp(dst2).expect("Invalid ray dst"))
             .map(|(chunk_id, dst)| WorldHit {
                 chunk_id,
-                dst,
                 hit_pos: {
                     let p = ray.point_at(dst);
                     Vec3::new(p.x, p.y, p.z)
@@ -78,9 +285,9 @@ impl World {
     }
 
     pub fn target_from_hit(&self, hit: WorldHit) -> Option<WorldTarget> {
-        let x = hit.hit_pos.x - 16. * hit.chunk_id.x as f32;
-        let y = hit.hit_pos.y - 16. * hit.chunk_id.y as f32;
-        let z = hit.hit_pos.z - 16. * hit.chunk_id.z as f32;
+        let x = hit.hit_pos.x - (CHUNK_SIZE_I32 * hit.chunk_id.x) as f32;
+        let y = hit.hit_pos.y - (CHUNK_SIZE_I32 * hit.chunk_id.y) as f32;
+        let z = hit.hit_pos.z - (CHUNK_SIZE_I32 * hit.chunk_id.z) as f32;
 
         let dir = if (x + 0.0005) % 1. <= 0.001 {
             Direction::Px
@@ -105,25 +312,78 @@ impl World {
 
         match (block1, block2) {
             (Block::Air, Block::Air) => None,
-            (Block::Air, block2) => Some(WorldTarget {
+            (Block::Air, _) => Some(WorldTarget {
                 local_pos: block2pos,
-                block: block2,
+                place_pos: IVec3::new(x, y, z),
             }),
-            (block1, Block::Air) => Some(WorldTarget {
+            (_, Block::Air) => Some(WorldTarget {
                 local_pos: IVec3::new(x, y, z),
-                block: block1,
+                place_pos: block2pos,
             }),
             _ => None,
         }
     }
 }
 
+#[cfg(test)]
+impl World {
+    /// A world with the given chunks loaded and lit,
+    /// where `fill` picks the block at every global position in them
+    pub fn with_chunks(
+        chunk_ids: impl IntoIterator<Item = IVec3>,
+        fill: impl Fn(IVec3) -> Block,
+    ) -> Self {
+        let mut world = Self::new();
+
+        for chunk_id in chunk_ids {
+            let mut chunk = Chunk::new(chunk_id);
+            let origin = chunk_id * CHUNK_SIZE_I32;
+
+            for x in 0..CHUNK_SIZE_I32 {
+                for y in 0..CHUNK_SIZE_I32 {
+                    for z in 0..CHUNK_SIZE_I32 {
+                        *chunk.get_mut(x as usize, y as usize, z as usize) =
+                            fill(origin + IVec3::new(x, y, z));
+                    }
+                }
+            }
+
+            world.chunks.insert(chunk_id, chunk);
+        }
+
+        let chunk_ids = world.chunks.keys().copied().collect::<Vec<_>>();
+        light::relight_chunks(&mut world, &chunk_ids);
+        world
+    }
+}
+
+/// Applies `BlockEdit`s straight to the world, recording them in the edit history
+pub fn apply_block_edits(
+    mut edits: EventReader<BlockEdit>,
+    mut world: ResMut<World>,
+    mut history: ResMut<EditHistory>,
+    mut edited: EventWriter<BlockEdited>,
+) {
+    for &BlockEdit { pos, block } in edits.iter() {
+        // Single blocks update light incrementally, which is much faster than set_blocks
+        if let Some(old) = world.set_block(pos, block).filter(|old| *old != block) {
+            history.record(&world, vec![(pos, old)]);
+            edited.send(BlockEdited {
+                pos,
+                old,
+                new: block,
+            });
+        }
+    }
+}
+
 pub fn world_mesh_gen(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut world: ResMut<World>,
+    mut diagnostics: Diagnostics,
 ) {
-    println!(
+    debug!(
         "Regenerating {} meshes this frame",
         world.invalid_meshes.len()
     );
@@ -135,15 +395,31 @@ pub fn world_mesh_gen(
             commands.entity(chunk).despawn();
         }
 
-        let mesh = world.chunks[&chunk_id].build_mesh();
-        world.colliders.insert(chunk_id, mesh_to_tri_mesh(&mesh));
+        if !world.chunks.contains_key(&chunk_id) {
+            continue;
+        }
+
+        let start = Instant::now();
+        let mesh = world.build_chunk_mesh(chunk_id);
+        diagnostics.add_measurement(CustomDiagnosticsPlugin::mesh_time_diagnostic_id(), || {
+            start.elapsed().as_secs_f64() * 1000.
+        });
+        let connectivity = Connectivity::of(&world.chunks[&chunk_id]);
+        world.connectivity.insert(chunk_id, connectivity);
+
+        // Only full detail chunks can be targeted
+        if world.lods.get(&chunk_id).copied().unwrap_or(0) == 0 {
+            world.colliders.insert(chunk_id, mesh_to_tri_mesh(&mesh));
+        } else {
+            world.colliders.remove(&chunk_id);
+        }
 
         let mesh_handle = meshes.add(mesh);
         let entity = commands
-            .spawn(PbrBundle {
+            .spawn(MaterialMeshBundle {
                 mesh: mesh_handle.clone(),
                 material: world.material.clone(),
-                transform: Transform::from_translation(16. * chunk_id.as_vec3()),
+                transform: Transform::from_translation((chunk_id * CHUNK_SIZE_I32).as_vec3()),
                 ..default()
             })
             .id();
@@ -151,3 +427,28 @@ pub fn world_mesh_gen(
         world.meshes.insert(chunk_id, entity);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotation_stays_in_box() {
+        let size = IVec3::new(2, 1, 5);
+
+        for quarter_turns in 0..4 {
+            let rotated = rotated_size(size, quarter_turns);
+
+            for x in 0..size.x {
+                for z in 0..size.z {
+                    let pos = rotate_in_box(IVec3::new(x, 0, z), size, quarter_turns);
+                    assert!(pos.cmpge(IVec3::ZERO).all() && pos.cmplt(rotated).all());
+                }
+            }
+        }
+
+        // A quarter turn takes +x to -z
+        assert_eq!(rotate_in_box(IVec3::new(1, 0, 0), size, 1), IVec3::new(0, 0, 0));
+        assert_eq!(rotate_in_box(IVec3::new(0, 0, 0), size, 1), IVec3::new(0, 0, 1));
+    }
+}
@@ -0,0 +1,360 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+
+use crate::{
+    block::Block,
+    camera::{FlyCam, FlyCamPlugin},
+    console::not_typing,
+    history::EditHistory,
+    mesh::Direction,
+    schem::Schematic,
+    world::World,
+};
+
+/// Bulk editing of a box of blocks, selected by looking at each corner and pressing `[` or `]`
+pub struct WorldEditPlugin;
+
+impl Plugin for WorldEditPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<WorldEdit>().add_systems(
+            Update,
+            (
+                Self::pick_corners.run_if(not_typing),
+                Self::draw_selection,
+                Self::world_edit_window,
+            ),
+        );
+    }
+}
+
+/// An inclusive box of blocks
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Selection {
+    pub min: IVec3,
+    pub max: IVec3,
+}
+
+impl Selection {
+    /// The corners can be given in any order
+    pub fn new(a: IVec3, b: IVec3) -> Self {
+        Self {
+            min: a.min(b),
+            max: a.max(b),
+        }
+    }
+
+    pub fn size(&self) -> IVec3 {
+        self.max - self.min + IVec3::ONE
+    }
+
+    /// The number of blocks selected, which can be too many for an `i32`
+    pub fn volume(&self) -> i64 {
+        (0..3)
+            .map(|axis| self.max[axis] as i64 - self.min[axis] as i64 + 1)
+            .product()
+    }
+
+    pub fn positions(&self) -> impl Iterator<Item = IVec3> {
+        let (min, max) = (self.min, self.max);
+
+        (min.x..=max.x).flat_map(move |x| {
+            (min.y..=max.y).flat_map(move |y| (min.z..=max.z).map(move |z| IVec3::new(x, y, z)))
+        })
+    }
+}
+
+/// Sets every block in the selection.
+///
+/// Like every edit here, this returns the position and old block of every block that changed.
+pub fn fill(world: &mut World, selection: Selection, block: Block) -> Vec<(IVec3, Block)> {
+    world.set_blocks(selection.positions().map(|pos| (pos, block)))
+}
+
+/// Swaps every `from` block in the selection for `to`
+pub fn replace(
+    world: &mut World,
+    selection: Selection,
+    from: Block,
+    to: Block,
+) -> Vec<(IVec3, Block)> {
+    let placed = selection
+        .positions()
+        .filter(|pos| world.get_block(*pos) == Some(from))
+        .map(|pos| (pos, to))
+        .collect::<Vec<_>>();
+
+    world.set_blocks(placed)
+}
+
+/// Clears every solid block in the selection that's completely surrounded by solid blocks,
+/// leaving a one block thick shell around anything solid
+pub fn hollow(world: &mut World, selection: Selection) -> Vec<(IVec3, Block)> {
+    let is_solid = |pos: IVec3| world.get_block(pos).is_some_and(|block| block.is_opaque());
+
+    let cleared = selection
+        .positions()
+        .filter(|pos| is_solid(*pos) && Direction::iter().all(|dir| is_solid(*pos + dir.normal())))
+        .map(|pos| (pos, Block::Air))
+        .collect::<Vec<_>>();
+
+    world.set_blocks(cleared)
+}
+
+#[derive(Resource)]
+pub struct WorldEdit {
+    pub corners: [Option<IVec3>; 2],
+    pub clipboard: Option<Schematic>,
+    /// The block to fill with, and to replace with
+    pub block: Block,
+    /// The block to be replaced
+    pub replace: Block,
+    /// How many times to turn the clipboard anticlockwise when pasting
+    pub quarter_turns: u32,
+    pub mirror_x: bool,
+    pub mirror_z: bool,
+    /// Whether air in the clipboard overwrites blocks when pasting
+    pub paste_air: bool,
+}
+
+impl Default for WorldEdit {
+    fn default() -> Self {
+        Self {
+            corners: [None; 2],
+            clipboard: None,
+            block: Block::Stone,
+            replace: Block::Dirt,
+            quarter_turns: 0,
+            mirror_x: false,
+            mirror_z: false,
+            paste_air: true,
+        }
+    }
+}
+
+impl WorldEdit {
+    /// Only exists once both corners are picked
+    pub fn selection(&self) -> Option<Selection> {
+        match self.corners {
+            [Some(a), Some(b)] => Some(Selection::new(a, b)),
+            _ => None,
+        }
+    }
+}
+
+pub fn block_picker(ui: &mut egui::Ui, label: &str, block: &mut Block) {
+    egui::ComboBox::from_label(label)
+        .selected_text(format!("{:?}", block))
+        .show_ui(ui, |ui| {
+            for option in Block::ALL {
+                ui.selectable_value(block, option, format!("{:?}", option));
+            }
+        });
+}
+
+impl WorldEditPlugin {
+    pub fn pick_corners(
+        keys: Res<Input<KeyCode>>,
+        query: Query<&Transform, With<FlyCam>>,
+        world: Res<World>,
+        mut edit: ResMut<WorldEdit>,
+    ) {
+        let corner = if keys.just_pressed(KeyCode::BracketLeft) {
+            0
+        } else if keys.just_pressed(KeyCode::BracketRight) {
+            1
+        } else {
+            return;
+        };
+
+        let Ok(transform) = query.get_single() else { return; };
+        if let Some((pos, _)) = FlyCamPlugin::target(&world, transform) {
+            edit.corners[corner] = Some(pos);
+        }
+    }
+
+    pub fn draw_selection(edit: Res<WorldEdit>, mut gizmos: Gizmos) {
+        for corner in edit.corners.iter().flatten() {
+            gizmos.cuboid(
+                Transform::from_translation(corner.as_vec3() + 0.5).with_scale(Vec3::splat(1.02)),
+                Color::YELLOW,
+            );
+        }
+
+        if let Some(selection) = edit.selection() {
+            let size = selection.size().as_vec3();
+            gizmos.cuboid(
+                Transform::from_translation(selection.min.as_vec3() + size / 2.)
+                    .with_scale(size + 0.02),
+                Color::WHITE,
+            );
+        }
+    }
+
+    pub fn world_edit_window(
+        mut ctx: EguiContexts,
+        mut edit: ResMut<WorldEdit>,
+        mut world: ResMut<World>,
+        mut history: ResMut<EditHistory>,
+        query: Query<&Transform, With<FlyCam>>,
+    ) {
+        egui::Window::new("World Edit").show(ctx.ctx_mut(), |ui| {
+            let selection = edit.selection();
+
+            match selection {
+                Some(selection) => ui.label(format!(
+                    "Selected {} to {}, {} blocks",
+                    selection.min,
+                    selection.max,
+                    selection.size().to_array().iter().product::<i32>()
+                )),
+                None => ui.label("Look at a block and press [ and ] to pick the corners"),
+            };
+
+            block_picker(ui, "Block", &mut edit.block);
+            block_picker(ui, "Replace", &mut edit.replace);
+
+            ui.add_enabled_ui(selection.is_some(), |ui| {
+                let Some(selection) = selection else { return; };
+
+                ui.horizontal(|ui| {
+                    if ui.button("Fill").clicked() {
+                        let changed = fill(&mut world, selection, edit.block);
+                        history.record(&world, changed);
+                    }
+                    if ui.button("Replace").clicked() {
+                        let changed = replace(&mut world, selection, edit.replace, edit.block);
+                        history.record(&world, changed);
+                    }
+                    if ui.button("Hollow").clicked() {
+                        let changed = hollow(&mut world, selection);
+                        history.record(&world, changed);
+                    }
+                    if ui.button("Copy").clicked() {
+                        edit.clipboard =
+                            Some(Schematic::from_world(&world, selection.min, selection.max));
+                    }
+                });
+            });
+
+            ui.separator();
+
+            ui.horizontal(|ui| {
+                ui.label("Turns");
+                ui.add(egui::DragValue::new(&mut edit.quarter_turns).clamp_range(0..=3));
+                ui.checkbox(&mut edit.mirror_x, "Mirror x");
+                ui.checkbox(&mut edit.mirror_z, "Mirror z");
+                ui.checkbox(&mut edit.paste_air, "Paste air");
+            });
+
+            let Ok(transform) = query.get_single() else { return; };
+            let target = FlyCamPlugin::target(&world, transform);
+
+            ui.add_enabled_ui(edit.clipboard.is_some() && target.is_some(), |ui| {
+                if !ui.button("Paste at crosshair").clicked() {
+                    return;
+                }
+
+                if let (Some(clipboard), Some((_, place_pos))) = (&edit.clipboard, target) {
+                    let changed = clipboard
+                        .transformed(edit.quarter_turns, edit.mirror_x, edit.mirror_z)
+                        .paste(&mut world, place_pos, edit.paste_air);
+                    history.record(&world, changed);
+                }
+            });
+
+            ui.separator();
+
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(history.can_undo(), egui::Button::new("Undo"))
+                    .clicked()
+                {
+                    history.undo(&mut world);
+                }
+                if ui
+                    .add_enabled(history.can_redo(), egui::Button::new("Redo"))
+                    .clicked()
+                {
+                    history.redo(&mut world);
+                }
+            });
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::CHUNK_SIZE_I32;
+
+    fn empty_world() -> World {
+        World::with_chunks([IVec3::new(-1, 0, 0), IVec3::ZERO], |_| Block::Air)
+    }
+
+    #[test]
+    fn fill_and_replace_across_chunks() {
+        let mut world = empty_world();
+        let selection = Selection::new(IVec3::new(2, 3, 4), IVec3::new(-3, 1, 1));
+
+        let changed = fill(&mut world, selection, Block::Stone);
+        assert_eq!(changed.len(), 6 * 3 * 4);
+        assert!(changed.iter().all(|(_, old)| *old == Block::Air));
+
+        // Both chunks are rebuilt, each only once
+        assert_eq!(
+            world.invalid_meshes.iter().filter(|id| id.x == -1).count(),
+            1
+        );
+        assert_eq!(
+            world.invalid_meshes.iter().filter(|id| id.x == 0).count(),
+            1
+        );
+
+        world.set_block(IVec3::new(0, 2, 2), Block::Dirt);
+        let changed = replace(&mut world, selection, Block::Dirt, Block::Lava);
+        assert_eq!(changed, vec![(IVec3::new(0, 2, 2), Block::Dirt)]);
+        assert_eq!(world.get_block(IVec3::new(0, 2, 2)), Some(Block::Lava));
+    }
+
+    #[test]
+    fn hollow_leaves_a_shell() {
+        let mut world = empty_world();
+        let selection = Selection::new(IVec3::new(-2, 0, 0), IVec3::new(2, 4, 4));
+        fill(&mut world, selection, Block::Stone);
+
+        hollow(&mut world, selection);
+
+        for pos in selection.positions() {
+            let on_edge = pos.cmpeq(selection.min).any() || pos.cmpeq(selection.max).any();
+            let expected = if on_edge { Block::Stone } else { Block::Air };
+            assert_eq!(world.get_block(pos), Some(expected), "{}", pos);
+        }
+    }
+
+    #[test]
+    fn paste_turns_and_mirrors() {
+        let mut world = empty_world();
+        world.set_blocks([
+            (IVec3::new(0, 0, 0), Block::Stone),
+            (IVec3::new(1, 0, 0), Block::Dirt),
+            (IVec3::new(2, 0, 0), Block::Torch),
+        ]);
+        let clipboard = Schematic::from_world(&world, IVec3::ZERO, IVec3::new(2, 0, 0));
+
+        // Turning a row along x puts it along z, with the old +x end at the lowest z
+        let origin = IVec3::new(-CHUNK_SIZE_I32, 5, 3);
+        clipboard
+            .transformed(1, false, false)
+            .paste(&mut world, origin, true);
+        assert_eq!(world.get_block(origin), Some(Block::Torch));
+        assert_eq!(world.get_block(origin + IVec3::Z * 2), Some(Block::Stone));
+
+        let origin = IVec3::new(5, 5, 5);
+        clipboard
+            .transformed(0, true, false)
+            .paste(&mut world, origin, true);
+        assert_eq!(world.get_block(origin), Some(Block::Torch));
+        assert_eq!(world.get_block(origin + IVec3::X), Some(Block::Dirt));
+        assert_eq!(world.get_block(origin + IVec3::X * 2), Some(Block::Stone));
+    }
+}